@@ -0,0 +1,113 @@
+/*
+cargo bench -p async-ping --bench ping
+*/
+
+use core::{
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+use std::{
+    collections::VecDeque,
+    io::Error as IoError,
+    net::{Ipv4Addr, SocketAddr},
+    sync::Mutex,
+};
+
+use async_ping::PingClient;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use icmp_client::{AsyncClient, AsyncClientWithConfigError, Config as ClientConfig, ReadBuf};
+use icmp_packet::icmpv4;
+use tokio::{runtime::Runtime, task::JoinSet};
+
+//
+#[derive(Debug, Default)]
+struct Queue {
+    replies: VecDeque<(Vec<u8>, SocketAddr)>,
+    waker: Option<Waker>,
+}
+
+/// Echoes every echo request at once, so that only the client side is measured.
+#[derive(Debug, Default)]
+struct EchoClient {
+    queue: Mutex<Queue>,
+}
+
+impl AsyncClient for EchoClient {
+    fn with_config(_config: &ClientConfig) -> Result<Self, AsyncClientWithConfigError> {
+        Ok(Self::default())
+    }
+
+    fn poll_send_to(
+        &self,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+        addr: SocketAddr,
+    ) -> Poll<Result<usize, IoError>> {
+        let bytes = icmpv4::render_echo_reply_packet_bytes(buf)
+            .ok_or_else(|| IoError::other("not an echo request"))?;
+
+        let mut queue = self.queue.lock().expect("Never when lock");
+        queue.replies.push_back((bytes, addr));
+        if let Some(waker) = queue.waker.take() {
+            waker.wake();
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<SocketAddr, IoError>> {
+        let mut queue = self.queue.lock().expect("Never when lock");
+        match queue.replies.pop_front() {
+            Some((bytes, addr)) => {
+                buf.put_slice(&bytes);
+                Poll::Ready(Ok(addr))
+            }
+            None => {
+                queue.waker = Some(cx.waker().to_owned());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+async fn sweep(n: u32) {
+    let client = PingClient::with_clients(Some((EchoClient::default(), false)), None);
+
+    let mut set = JoinSet::new();
+    for i in 0..n {
+        let client = client.clone();
+        set.spawn(async move {
+            client
+                .ping_v4(
+                    Ipv4Addr::from(0x0a00_0000 + i),
+                    None,
+                    Some(1),
+                    [0; 32],
+                    Duration::from_secs(5),
+                )
+                .await
+        });
+    }
+    while let Some(ret) = set.join_next().await {
+        ret.expect("Never when join").expect("ping");
+    }
+}
+
+fn bench_sweep(c: &mut Criterion) {
+    let rt = Runtime::new().expect("Never when Runtime::new");
+
+    let mut group = c.benchmark_group("sweep");
+    for n in [1_000, 10_000] {
+        group.throughput(Throughput::Elements(n as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(n), &n, |b, n| {
+            b.iter(|| rt.block_on(sweep(*n)));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_sweep);
+criterion_main!(benches);
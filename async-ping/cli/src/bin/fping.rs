@@ -0,0 +1,103 @@
+/*
+RUST_BACKTRACE=1 RUST_LOG=trace cargo run -p async-ping-cli --bin async_ping_fping -- 127.0.0.1 ::1
+Or
+cargo install async-ping-cli
+async_ping_fping -c 3 -r 1 -i 10 -p 500 --pps 100 10.0.0.1 10.0.0.2 10.0.0.3
+async_ping_fping -g 10.0.0.0/24
+*/
+
+use core::time::Duration;
+use std::{env, net::IpAddr};
+
+use async_ping::{DiscoveryTargets, Ipv4Cidr, PingClient, ScanConfig, TargetStatus};
+use futures_util::StreamExt as _;
+use icmp_client::Config as ClientConfig;
+use tracing_subscriber::{fmt, layer::SubscriberExt as _, util::SubscriberInitExt as _};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = ScanConfig::new();
+    let mut targets = vec![];
+    let mut cidrs = vec![];
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-c" => {
+                config = config.count(args.next().ok_or("args c missing")?.parse()?);
+            }
+            "-r" => {
+                config = config.retries(args.next().ok_or("args r missing")?.parse()?);
+            }
+            "-i" => {
+                let ms = args.next().ok_or("args i missing")?.parse()?;
+                config = config.interval(Duration::from_millis(ms));
+            }
+            "-p" => {
+                let ms = args.next().ok_or("args p missing")?.parse()?;
+                config = config.period(Duration::from_millis(ms));
+            }
+            "-t" => {
+                let ms = args.next().ok_or("args t missing")?.parse()?;
+                config = config.timeout(Duration::from_millis(ms));
+            }
+            "-g" => {
+                cidrs.push(args.next().ok_or("args g missing")?.parse::<Ipv4Cidr>()?);
+            }
+            "--pps" => {
+                let pps = args.next().ok_or("args pps missing")?.parse()?;
+                if pps == 0 {
+                    return Err("args pps zero".into());
+                }
+                config = config.packets_per_second(pps);
+            }
+            _ => targets.push(
+                arg.parse::<IpAddr>()
+                    .map_err(|err| format!("args {arg} invalid, err:{err}"))?,
+            ),
+        }
+    }
+    if targets.is_empty() && cidrs.is_empty() {
+        return Err("args targets missing".into());
+    }
+
+    //
+    tracing_subscriber::registry().with(fmt::layer()).init();
+
+    //
+    let mut builder = PingClient::<icmp_client::impl_tokio::Client>::builder();
+    if !cidrs.is_empty() || targets.iter().any(IpAddr::is_ipv4) {
+        builder = builder.v4(ClientConfig::new());
+    }
+    if targets.iter().any(IpAddr::is_ipv6) {
+        builder = builder.v6(ClientConfig::with_ipv6());
+    }
+    let client = builder.build()?;
+
+    // The ranges lazily, whatever their size.
+    let addrs = cidrs
+        .into_iter()
+        .fold(DiscoveryTargets::new(), DiscoveryTargets::ipv4_cidr)
+        .addrs();
+    let mut scan = client.scan(targets.into_iter().chain(addrs), config)?;
+    while let Some(result) = scan.next().await {
+        let rtts = result
+            .rtts
+            .iter()
+            .map(|x| match x {
+                Some(rtt) => format!("{:.2}", rtt.as_secs_f64() * 1000.0),
+                None => "-".to_owned(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        match result.status {
+            TargetStatus::Alive => println!("{} is alive : {rtts}", result.ip),
+            TargetStatus::Unreachable => {
+                println!("{} is unreachable : {:?}", result.ip, result.error)
+            }
+            TargetStatus::Timeout => println!("{} is unreachable : timeout", result.ip),
+        }
+    }
+
+    Ok(())
+}
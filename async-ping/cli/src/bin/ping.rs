@@ -3,16 +3,24 @@ RUST_BACKTRACE=1 RUST_LOG=trace cargo run -p async-ping-cli --bin async_ping_pin
 Or
 cargo install async-ping-cli
 async_ping_ping 127.0.0.1
+
+Record Route or Timestamp, needs a raw socket
+async_ping_ping 127.0.0.1 -R
+async_ping_ping 127.0.0.1 -T tsonly
 */
 
 use core::time::Duration;
 use std::{env, net::IpAddr};
 
 use async_ping::{
-    icmp_packet::{Icmp, Icmpv4, Icmpv6},
-    PingClient,
+    icmp_packet::ip_options::{
+        parse_ipv4_options, render_record_route_option_bytes, render_timestamp_option_bytes,
+        TimestampFlag,
+    },
+    PingClient, PingSessionConfig, PingSessionEvent, ProbeOutcome, ReplyEventKind,
 };
-use icmp_client::Config as ClientConfig;
+use futures_util::StreamExt as _;
+use icmp_client::{Config as ClientConfig, SocketType};
 use tracing_subscriber::{fmt, layer::SubscriberExt as _, util::SubscriberInitExt as _};
 
 #[tokio::main]
@@ -22,61 +30,126 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .ok_or("args ip missing")?
         .parse::<IpAddr>()
         .map_err(|err| format!("args ip invalid, err:{err}"))?;
+    let ip_options = match env::args().nth(2).as_deref() {
+        Some("-R") => Some(render_record_route_option_bytes()),
+        Some("-T") => {
+            let flag = match env::args().nth(3).as_deref() {
+                Some("tsonly") => TimestampFlag::TsOnly,
+                Some("tsandaddr") => TimestampFlag::TsAndAddr,
+                _ => return Err("args -T invalid, tsonly or tsandaddr".into()),
+            };
+            Some(render_timestamp_option_bytes(flag, &[]))
+        }
+        Some(arg) => return Err(format!("args {arg} invalid").into()),
+        None => None,
+    };
 
     //
     tracing_subscriber::registry().with(fmt::layer()).init();
 
     //
+    let payload = vec![0; 32];
+    let timeout_dur = Duration::from_secs(2);
+
+    let builder = PingClient::<icmp_client::impl_tokio::Client>::builder()
+        .timeout(timeout_dur)
+        .payload(payload.clone());
     let client = match ip {
         IpAddr::V4(_) => {
-            PingClient::<icmp_client::impl_tokio::Client>::new(Some(ClientConfig::new()), None)?
+            let mut client_config = ClientConfig::new();
+            if let Some(ip_options) = ip_options.clone() {
+                client_config = client_config
+                    .socket_type(SocketType::Raw)
+                    .ip_options(ip_options);
+            }
+            builder.v4(client_config)
+        }
+        IpAddr::V6(_) => {
+            let mut client_config = ClientConfig::with_ipv6();
+            if let Some(ip_options) = ip_options.clone() {
+                client_config = client_config.ip_options(ip_options);
+            }
+            builder.v6(client_config)
         }
-        IpAddr::V6(_) => PingClient::<icmp_client::impl_tokio::Client>::new(
-            None,
-            Some(ClientConfig::with_ipv6()),
-        )?,
-    };
-
-    {
-        let client = client.clone();
-        tokio::spawn(async move {
-            client.handle_v4_recv_from().await;
-        });
-    }
-
-    {
-        let client = client.clone();
-        tokio::spawn(async move {
-            client.handle_v6_recv_from().await;
-        });
     }
+    .build()?;
 
-    //
-    let payload = vec![0; 32];
-    let timeout_dur = Duration::from_secs(2);
-
-    for i in 0..1000 {
-        let ret = match client
-            .ping(ip, None, Some(i), payload.clone(), timeout_dur)
-            .await
-        {
-            Ok((icmp, dur)) => match icmp {
-                Icmp::V4(Icmpv4::EchoReply(echo_reply)) => Ok((dur, echo_reply.sequence_number)),
-                Icmp::V4(Icmpv4::Other(tp, _, _)) => Err(format!("{tp:?}")),
-                Icmp::V6(Icmpv6::EchoReply(echo_reply)) => Ok((dur, echo_reply.sequence_number)),
-                Icmp::V6(Icmpv6::Other(tp, _, _)) => Err(format!("{tp:?}")),
+    let mut session = client.session(
+        ip,
+        PingSessionConfig::new()
+            .count(1000)
+            .timeout(timeout_dur)
+            .payload_size(payload.len()),
+    )?;
+    while let Some(event) = session.next().await {
+        match event {
+            PingSessionEvent::Probe {
+                sequence_number,
+                outcome,
+            } => match outcome {
+                Ok(ProbeOutcome::Reply {
+                    from,
+                    rtt,
+                    ttl,
+                    ip_options,
+                    ..
+                }) => {
+                    // The options are in the IP header of the replies.
+                    if let Some(ip_options) = ip_options {
+                        println!("{:?}", parse_ipv4_options(&ip_options));
+                    }
+                    match ttl {
+                        Some(ttl) => println!(
+                            "from={from} icmp_seq={sequence_number} ttl={ttl} time={}ms",
+                            rtt.as_millis()
+                        ),
+                        None => println!(
+                            "from={from} icmp_seq={sequence_number} time={}ms",
+                            rtt.as_millis()
+                        ),
+                    }
+                }
+                Ok(outcome) => println!("icmp_seq={sequence_number} {outcome:?}"),
+                Err(err) => println!("err={err}"),
             },
-            Err(err) => Err(err.to_string()),
-        };
-
-        match ret {
-            Ok((dur, sequence_number)) => {
-                println!("icmp_seq={sequence_number} time={}ms", dur.as_millis())
+            PingSessionEvent::Reply(event) => {
+                let kind = match event.kind {
+                    ReplyEventKind::Duplicate => "DUP!",
+                    ReplyEventKind::Late => "late",
+                    ReplyEventKind::Reordered => "reordered",
+                };
+                println!(
+                    "icmp_seq={} time={}ms ({kind})",
+                    event.sequence_number,
+                    event.rtt.as_millis()
+                );
+            }
+            PingSessionEvent::Summary(summary) => {
+                let statistics = &summary.statistics;
+                println!(
+                    "{} transmitted, {} received, {} errors, {:.1}% loss, time {}ms",
+                    statistics.transmitted,
+                    statistics.received,
+                    statistics.errors,
+                    statistics.loss() * 100.0,
+                    summary.elapsed.as_millis()
+                );
+                if let (Some(min), Some(avg), Some(max), Some(mdev)) = (
+                    statistics.rtt_min(),
+                    statistics.rtt_avg(),
+                    statistics.rtt_max(),
+                    statistics.rtt_mdev(),
+                ) {
+                    println!(
+                        "rtt min/avg/max/mdev = {:.3}/{:.3}/{:.3}/{:.3} ms",
+                        min.as_secs_f64() * 1000.0,
+                        avg.as_secs_f64() * 1000.0,
+                        max.as_secs_f64() * 1000.0,
+                        mdev.as_secs_f64() * 1000.0,
+                    );
+                }
             }
-            Err(err) => println!("err={err}"),
         }
-
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
     }
 
     Ok(())
@@ -0,0 +1,117 @@
+/*
+sudo sysctl -w net.ipv4.icmp_echo_ignore_all=1
+RUST_BACKTRACE=1 RUST_LOG=trace cargo run -p async-ping-cli --bin async_ping_responder -- --delay-ms 100 --loss 0.1 --duplicate 0.1
+Or
+cargo install async-ping-cli
+async_ping_responder --ipv6 --rate-limit 10/1000 --mangle flip:0.01
+async_ping_responder --tun tun0 --bind 10.0.0.2
+*/
+
+use core::time::Duration;
+use std::{env, net::IpAddr};
+
+use async_ping::responder::{Config, Mangle, RateLimit, Responder};
+use icmp_client::{Config as ClientConfig, SocketType};
+use tracing_subscriber::{fmt, layer::SubscriberExt as _, util::SubscriberInitExt as _};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut is_ipv6 = false;
+    let mut bind: Option<IpAddr> = None;
+    let mut config = Config::new();
+    let mut tun_name: Option<String> = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ipv6" => {
+                is_ipv6 = true;
+            }
+            "--bind" => {
+                bind = Some(args.next().ok_or("args bind missing")?.parse()?);
+            }
+            "--tun" => {
+                tun_name = Some(args.next().ok_or("args tun missing")?);
+            }
+            "--delay-ms" => {
+                let ms = args.next().ok_or("args delay-ms missing")?.parse()?;
+                config = config.delay(Duration::from_millis(ms));
+            }
+            "--jitter-ms" => {
+                let ms = args.next().ok_or("args jitter-ms missing")?.parse()?;
+                config = config.jitter(Duration::from_millis(ms));
+            }
+            "--loss" => {
+                let loss = args.next().ok_or("args loss missing")?.parse()?;
+                config = config.loss(loss);
+            }
+            "--duplicate" => {
+                let probability = args.next().ok_or("args duplicate missing")?.parse()?;
+                config = config.duplicate(probability);
+            }
+            // BURST/INTERVAL_MS
+            "--rate-limit" => {
+                let value = args.next().ok_or("args rate-limit missing")?;
+                let (burst, ms) = value.split_once('/').ok_or("args rate-limit invalid")?;
+                config = config.rate_limit(RateLimit::new(
+                    burst.parse()?,
+                    Duration::from_millis(ms.parse()?),
+                ));
+            }
+            // flip:PROBABILITY, truncate:N:PROBABILITY or fill:BYTE:PROBABILITY
+            "--mangle" => {
+                let value = args.next().ok_or("args mangle missing")?;
+                let mut parts = value.split(':');
+                let mangle = match parts.next() {
+                    Some("flip") => Mangle::FlipBit,
+                    Some("truncate") => {
+                        Mangle::Truncate(parts.next().ok_or("args mangle invalid")?.parse()?)
+                    }
+                    Some("fill") => {
+                        Mangle::Fill(parts.next().ok_or("args mangle invalid")?.parse()?)
+                    }
+                    _ => return Err("args mangle invalid".into()),
+                };
+                let probability = parts.next().map(|x| x.parse()).transpose()?.unwrap_or(1.0);
+                config = config.mangle(mangle, probability);
+            }
+            _ => return Err(format!("args {arg} unknown").into()),
+        }
+    }
+
+    // Whatever the order of the args.
+    let mut client_config = if is_ipv6 {
+        ClientConfig::with_ipv6()
+    } else {
+        ClientConfig::new()
+    };
+    if let Some(ip) = bind {
+        if is_ipv6 && ip.is_ipv4() {
+            return Err(format!("args bind {ip} not IPv6").into());
+        }
+        client_config = client_config.bind((ip, 0).into());
+    }
+    let client_config = client_config.socket_type(SocketType::Raw);
+
+    //
+    tracing_subscriber::registry().with(fmt::layer()).init();
+
+    //
+    if let Some(tun_name) = tun_name {
+        #[cfg(target_os = "linux")]
+        {
+            let client = icmp_client::impl_tun::Client::open(&tun_name, &client_config)?;
+            Responder::new(client, config)?.run().await?;
+            return Ok(());
+        }
+        #[cfg(not(target_os = "linux"))]
+        return Err(format!("args tun {tun_name} unsupported").into());
+    }
+
+    let client = icmp_client::impl_tokio::Client::new(&client_config)?;
+    let responder = Responder::new(client, config)?;
+
+    responder.run().await?;
+
+    Ok(())
+}
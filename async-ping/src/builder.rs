@@ -0,0 +1,437 @@
+//! [`PingClient`] construction, the options are validated before any socket is opened.
+
+use core::time::Duration;
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6},
+    sync::Arc,
+};
+
+use icmp_client::{AsyncClient, AsyncClientWithConfigError, Config as ClientConfig};
+use tokio::sync::Semaphore;
+
+use crate::PingClient;
+
+//
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+pub const DEFAULT_PAYLOAD_SIZE: usize = 32;
+
+/// Max payload of an echo request, the IPv4 one, IPv6 allows 20 bytes more.
+///
+/// The 2 bytes of the length prefix of `PayloadLengthDelimitedEchoRequest` are taken off.
+pub const PAYLOAD_MAX_SIZE_V4: usize = 65535 - 20 - 8 - 2;
+pub const PAYLOAD_MAX_SIZE_V6: usize = 65535 - 8 - 2;
+
+//
+/// Identifier of the echo requests sent without one.
+///
+/// Linux DGRAM sockets replace it with their port anyway.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IdentifierStrategy {
+    /// A random one per echo request.
+    #[default]
+    Random,
+    Fixed(u16),
+    /// The low 16 bits of the process id, like `ping`.
+    ProcessId,
+}
+
+impl IdentifierStrategy {
+    pub(crate) fn identifier(&self) -> Option<u16> {
+        match self {
+            Self::Random => None,
+            Self::Fixed(x) => Some(*x),
+            Self::ProcessId => Some(std::process::id() as u16),
+        }
+    }
+}
+
+//
+pub struct PingClientBuilder<C> {
+    pub v4_client_config: Option<ClientConfig>,
+    pub v6_client_config: Option<ClientConfig>,
+    /// Instead of `v4_client_config`, e.g. `Box<dyn AsyncClient + Send + Sync>`, and whether it is raw.
+    pub v4_client: Option<(C, bool)>,
+    /// Instead of `v6_client_config`.
+    pub v6_client: Option<(C, bool)>,
+    pub timeout: Duration,
+    pub payload: Vec<u8>,
+    pub identifier: IdentifierStrategy,
+    /// The receive tasks are spawned by the first ping, else run `handle_v4_recv_from`
+    /// and `handle_v6_recv_from` by hand.
+    pub spawn_recv_from: bool,
+    /// Pings in flight at most, the others wait before sending, see `PingClient::probe_stats`.
+    pub max_in_flight: Option<usize>,
+}
+
+impl<C> core::fmt::Debug for PingClientBuilder<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PingClientBuilder")
+            .field("v4_client_config", &self.v4_client_config)
+            .field("v6_client_config", &self.v6_client_config)
+            .field("v4_client", &self.v4_client.as_ref().map(|(_, x)| x))
+            .field("v6_client", &self.v6_client.as_ref().map(|(_, x)| x))
+            .field("timeout", &self.timeout)
+            .field("payload", &self.payload.len())
+            .field("identifier", &self.identifier)
+            .field("spawn_recv_from", &self.spawn_recv_from)
+            .field("max_in_flight", &self.max_in_flight)
+            .finish()
+    }
+}
+
+impl<C> Default for PingClientBuilder<C> {
+    fn default() -> Self {
+        Self {
+            v4_client_config: None,
+            v6_client_config: None,
+            v4_client: None,
+            v6_client: None,
+            timeout: DEFAULT_TIMEOUT,
+            payload: vec![0; DEFAULT_PAYLOAD_SIZE],
+            identifier: IdentifierStrategy::default(),
+            spawn_recv_from: true,
+            max_in_flight: None,
+        }
+    }
+}
+
+impl<C> PingClientBuilder<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Binds `0.0.0.0:0` unless `bind` is set.
+    pub fn v4(mut self, client_config: ClientConfig) -> Self {
+        self.v4_client_config = Some(client_config);
+        self
+    }
+
+    /// Binds `[::]:0` unless `bind` is set.
+    pub fn v6(mut self, client_config: ClientConfig) -> Self {
+        self.v6_client_config = Some(client_config);
+        self
+    }
+
+    pub fn v4_client(mut self, client: C, is_raw: bool) -> Self {
+        self.v4_client = Some((client, is_raw));
+        self
+    }
+
+    pub fn v6_client(mut self, client: C, is_raw: bool) -> Self {
+        self.v6_client = Some((client, is_raw));
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn payload(mut self, payload: impl Into<Vec<u8>>) -> Self {
+        self.payload = payload.into();
+        self
+    }
+
+    pub fn identifier(mut self, identifier: IdentifierStrategy) -> Self {
+        self.identifier = identifier;
+        self
+    }
+
+    pub fn spawn_recv_from(mut self, spawn_recv_from: bool) -> Self {
+        self.spawn_recv_from = spawn_recv_from;
+        self
+    }
+
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = Some(max_in_flight);
+        self
+    }
+
+    /// Checks the options without opening anything.
+    pub fn validate(&self) -> Result<(), PingClientBuildError> {
+        let has_v4 = self.v4_client_config.is_some() || self.v4_client.is_some();
+        let has_v6 = self.v6_client_config.is_some() || self.v6_client.is_some();
+        if !has_v4 && !has_v6 {
+            return Err(PingClientBuildError::NoFamily);
+        }
+
+        for (is_ipv6, client_config, has_client) in [
+            (
+                false,
+                self.v4_client_config.as_ref(),
+                self.v4_client.is_some(),
+            ),
+            (
+                true,
+                self.v6_client_config.as_ref(),
+                self.v6_client.is_some(),
+            ),
+        ] {
+            let Some(client_config) = client_config else {
+                continue;
+            };
+            if has_client {
+                return Err(PingClientBuildError::ConfigAndClient { is_ipv6 });
+            }
+            if client_config.is_ipv6() != is_ipv6 {
+                return Err(PingClientBuildError::FamilyMismatch { is_ipv6 });
+            }
+            if client_config.connect.is_some() {
+                return Err(PingClientBuildError::Connected { is_ipv6 });
+            }
+            if is_ipv6 && client_config.ip_options.is_some() {
+                return Err(PingClientBuildError::IpOptionsOnV6);
+            }
+        }
+
+        if self.timeout.is_zero() {
+            return Err(PingClientBuildError::TimeoutZero);
+        }
+        let payload_max_size = if has_v4 {
+            PAYLOAD_MAX_SIZE_V4
+        } else {
+            PAYLOAD_MAX_SIZE_V6
+        };
+        if self.payload.len() > payload_max_size {
+            return Err(PingClientBuildError::PayloadTooLarge {
+                size: self.payload.len(),
+                max: payload_max_size,
+            });
+        }
+        if self.max_in_flight == Some(0) {
+            return Err(PingClientBuildError::MaxInFlightZero);
+        }
+
+        Ok(())
+    }
+}
+
+impl<C> PingClientBuilder<C>
+where
+    C: AsyncClient + Send + Sync + 'static,
+{
+    pub fn build(self) -> Result<PingClient<C>, PingClientBuildError> {
+        self.validate()?;
+
+        let v4_client = match (self.v4_client, self.v4_client_config) {
+            (Some(client), _) => Some(client),
+            (None, Some(mut client_config)) => {
+                if client_config.bind.is_none() {
+                    client_config.bind = Some(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0).into());
+                }
+                let client = C::with_config(&client_config).map_err(|err| {
+                    PingClientBuildError::WithConfig {
+                        is_ipv6: false,
+                        err,
+                    }
+                })?;
+                Some((client, client_config.is_raw()))
+            }
+            (None, None) => None,
+        };
+
+        let v6_client = match (self.v6_client, self.v6_client_config) {
+            (Some(client), _) => Some(client),
+            (None, Some(mut client_config)) => {
+                if client_config.bind.is_none() {
+                    client_config.bind =
+                        Some(SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, 0, 0, 0).into());
+                }
+                let client = C::with_config(&client_config)
+                    .map_err(|err| PingClientBuildError::WithConfig { is_ipv6: true, err })?;
+                Some((client, client_config.is_raw()))
+            }
+            (None, None) => None,
+        };
+
+        let mut client = PingClient::with_clients(v4_client, v6_client);
+        client.timeout = self.timeout;
+        client.payload = self.payload.into();
+        client.identifier = self.identifier;
+        client.semaphore = self.max_in_flight.map(|x| Arc::new(Semaphore::new(x)));
+        client.spawn_recv_from = self.spawn_recv_from;
+
+        Ok(client)
+    }
+}
+
+//
+#[derive(Debug)]
+pub enum PingClientBuildError {
+    NoFamily,
+    /// The config of the family is of the other one.
+    FamilyMismatch {
+        is_ipv6: bool,
+    },
+    ConfigAndClient {
+        is_ipv6: bool,
+    },
+    /// `connect` is set, see `ConnectedPingClient`.
+    Connected {
+        is_ipv6: bool,
+    },
+    IpOptionsOnV6,
+    TimeoutZero,
+    PayloadTooLarge {
+        size: usize,
+        max: usize,
+    },
+    MaxInFlightZero,
+    WithConfig {
+        is_ipv6: bool,
+        err: AsyncClientWithConfigError,
+    },
+}
+
+impl core::fmt::Display for PingClientBuildError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let family = |is_ipv6: &bool| if *is_ipv6 { "v6" } else { "v4" };
+        match self {
+            Self::NoFamily => write!(f, "neither v4 nor v6 configured"),
+            Self::FamilyMismatch { is_ipv6 } => write!(
+                f,
+                "{} client config is for the other family",
+                family(is_ipv6)
+            ),
+            Self::ConfigAndClient { is_ipv6 } => {
+                write!(f, "{} client config and client both set", family(is_ipv6))
+            }
+            Self::Connected { is_ipv6 } => write!(
+                f,
+                "{} client config connect set, use ConnectedPingClient",
+                family(is_ipv6)
+            ),
+            Self::IpOptionsOnV6 => write!(f, "v6 client config ip_options set, IPv4 only"),
+            Self::TimeoutZero => write!(f, "timeout zero"),
+            Self::PayloadTooLarge { size, max } => {
+                write!(f, "payload too large, size:{size} max:{max}")
+            }
+            Self::MaxInFlightZero => write!(f, "max_in_flight zero"),
+            Self::WithConfig { is_ipv6, err } => {
+                write!(
+                    f,
+                    "{} client with_config failed, err:{err}",
+                    family(is_ipv6)
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for PingClientBuildError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::WithConfig { err, .. } => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use icmp_client::impl_tokio::Client;
+    use icmp_packet::ICMP_HEADER_SIZE;
+
+    use crate::ProbeOutcome;
+
+    #[test]
+    fn test_validate() {
+        let builder = || PingClientBuilder::<Client>::new();
+
+        assert!(matches!(
+            builder().validate(),
+            Err(PingClientBuildError::NoFamily)
+        ));
+        assert!(matches!(
+            builder().v6(ClientConfig::new()).validate(),
+            Err(PingClientBuildError::FamilyMismatch { is_ipv6: true })
+        ));
+        assert!(matches!(
+            builder().v4(ClientConfig::with_ipv6()).validate(),
+            Err(PingClientBuildError::FamilyMismatch { is_ipv6: false })
+        ));
+        assert!(matches!(
+            builder()
+                .v4(ClientConfig::new().connect("127.0.0.1:0".parse().expect("Never")))
+                .validate(),
+            Err(PingClientBuildError::Connected { is_ipv6: false })
+        ));
+        assert!(matches!(
+            builder()
+                .v6(ClientConfig::with_ipv6().ip_options(vec![1; 4]))
+                .validate(),
+            Err(PingClientBuildError::IpOptionsOnV6)
+        ));
+        assert!(matches!(
+            builder()
+                .v4(ClientConfig::new())
+                .timeout(Duration::ZERO)
+                .validate(),
+            Err(PingClientBuildError::TimeoutZero)
+        ));
+        assert!(builder()
+            .v4(ClientConfig::new())
+            .payload(vec![0; PAYLOAD_MAX_SIZE_V4])
+            .validate()
+            .is_ok());
+        assert!(matches!(
+            builder()
+                .v4(ClientConfig::new())
+                .payload(vec![0; PAYLOAD_MAX_SIZE_V4 + 1])
+                .validate(),
+            Err(PingClientBuildError::PayloadTooLarge { .. })
+        ));
+        assert!(builder()
+            .v6(ClientConfig::with_ipv6())
+            .payload(vec![0; PAYLOAD_MAX_SIZE_V4 + 1])
+            .validate()
+            .is_ok());
+        assert!(builder()
+            .v6(ClientConfig::with_ipv6())
+            .payload(vec![0; PAYLOAD_MAX_SIZE_V6])
+            .validate()
+            .is_ok());
+        assert!(matches!(
+            builder()
+                .v6(ClientConfig::with_ipv6())
+                .payload(vec![0; PAYLOAD_MAX_SIZE_V6 + 1])
+                .validate(),
+            Err(PingClientBuildError::PayloadTooLarge { .. })
+        ));
+        assert!(matches!(
+            builder()
+                .v4(ClientConfig::new())
+                .max_in_flight(0)
+                .validate(),
+            Err(PingClientBuildError::MaxInFlightZero)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_build() -> Result<(), Box<dyn std::error::Error>> {
+        let client = PingClientBuilder::<Client>::new()
+            .v4(ClientConfig::new())
+            .timeout(Duration::from_secs(2))
+            .payload(b"1234".to_vec())
+            .identifier(IdentifierStrategy::ProcessId)
+            .max_in_flight(1)
+            .build()?;
+
+        let (a, b) = tokio::join!(
+            client.ping_with_defaults("127.0.0.1".parse()?, Some(1)),
+            client.ping_with_defaults("127.0.0.2".parse()?, Some(2)),
+        );
+        for ret in [a, b] {
+            match ret? {
+                ProbeOutcome::Reply { size, .. } => {
+                    assert_eq!(size, ICMP_HEADER_SIZE + 2 + 4);
+                }
+                x => panic!("{x:?}"),
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,230 @@
+//! One connected client per destination, the kernel only hands over what comes from the peer.
+//!
+//! No shared demux, errors about the peer (e.g. EHOSTUNREACH) are returned by `ping` as [`PingError::Recv`],
+//! the ICMP messages being consumed by the kernel.
+
+use core::time::Duration;
+use std::{io::Error as IoError, net::IpAddr};
+
+use icmp_client::{
+    AsyncClient, AsyncClientExt as _, AsyncClientWithConfigError, Config as ClientConfig, Stats,
+};
+use icmp_packet::{
+    ip::parse_ipv4_packet_bytes,
+    pnet_packet::{icmp::IcmpTypes, icmpv6::Icmpv6Types},
+    Icmp, Icmpv4, Icmpv6, PayloadLengthDelimitedEchoRequest,
+};
+use tokio::time::Instant;
+
+use crate::{outcome::Received, PingError, ProbeOutcome};
+
+//
+pub struct ConnectedPingClient<C>
+where
+    C: AsyncClient,
+{
+    client: C,
+    ip: IpAddr,
+    is_raw: bool,
+}
+
+impl<C> core::fmt::Debug for ConnectedPingClient<C>
+where
+    C: AsyncClient,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConnectedPingClient")
+            .field("ip", &self.ip)
+            .finish()
+    }
+}
+
+impl<C> ConnectedPingClient<C>
+where
+    C: AsyncClient,
+{
+    pub fn new(
+        ip: IpAddr,
+        client_config: Option<ClientConfig>,
+    ) -> Result<Self, AsyncClientWithConfigError> {
+        let client_config = client_config.unwrap_or_else(|| match ip {
+            IpAddr::V4(_) => ClientConfig::new(),
+            IpAddr::V6(_) => ClientConfig::with_ipv6(),
+        });
+        if client_config.is_ipv6() != ip.is_ipv6() {
+            return Err(IoError::other("client_config invalid").into());
+        }
+        let is_raw = client_config.is_raw();
+
+        let client = C::with_config(&client_config.connect((ip, 0).into()))?;
+
+        Ok(Self { client, ip, is_raw })
+    }
+
+    pub fn ip(&self) -> IpAddr {
+        self.ip
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.client.stats()
+    }
+
+    pub async fn ping(
+        &self,
+        identifier: Option<u16>,
+        sequence_number: Option<u16>,
+        payload: impl AsRef<[u8]>,
+        timeout_dur: Duration,
+    ) -> Result<ProbeOutcome, PingError> {
+        let echo_request = PayloadLengthDelimitedEchoRequest::new(
+            identifier.map(Into::into),
+            sequence_number.map(Into::into),
+            payload,
+        );
+        let echo_request_bytes = match self.ip {
+            IpAddr::V4(_) => echo_request.render_v4_packet_bytes(),
+            IpAddr::V6(_) => echo_request.render_v6_packet_bytes(),
+        };
+
+        let instant_begin = Instant::now();
+        let deadline = instant_begin + timeout_dur;
+
+        if let Err(err) = self.client.send(&echo_request_bytes).await {
+            return Ok(ProbeOutcome::SendFailed(err));
+        }
+
+        let mut buf = [0; 2048];
+        loop {
+            let n = match tokio::time::timeout_at(deadline, self.client.recv(&mut buf)).await {
+                Ok(Ok(n)) => n,
+                Ok(Err(err)) => return Err(PingError::Recv(err)),
+                Err(_) => return Ok(ProbeOutcome::Timeout),
+            };
+            let instant_end = Instant::now();
+
+            let (icmp, size, ipv4_header) = match self.ip {
+                IpAddr::V4(_) => {
+                    let (ipv4_header, bytes) = if self.is_raw {
+                        match parse_ipv4_packet_bytes(&buf[..n]) {
+                            Some((header, bytes)) => (Some(header), bytes),
+                            None => continue,
+                        }
+                    } else {
+                        (None, &buf[..n])
+                    };
+                    if bytes.first() == Some(&IcmpTypes::EchoRequest.0) {
+                        continue;
+                    }
+                    match Icmpv4::parse_from_packet_bytes(bytes) {
+                        Ok(Some(icmpv4)) => (Icmp::V4(icmpv4), bytes.len(), ipv4_header),
+                        Ok(None) => continue,
+                        Err(err) => return Err(PingError::Icmpv4ParseError(err)),
+                    }
+                }
+                IpAddr::V6(_) => {
+                    if buf[..n].first() == Some(&Icmpv6Types::EchoRequest.0) {
+                        continue;
+                    }
+                    match Icmpv6::parse_from_packet_bytes(&buf[..n]) {
+                        Ok(Some(icmpv6)) => (Icmp::V6(icmpv6), n, None),
+                        Ok(None) => continue,
+                        Err(err) => return Err(PingError::Icmpv6ParseError(err)),
+                    }
+                }
+            };
+
+            // A late reply to a previous ping, or with a raw socket, the reply to another pinger of the peer.
+            let is_stale = match &icmp {
+                Icmp::V4(Icmpv4::EchoReply(echo_reply))
+                | Icmp::V6(Icmpv6::EchoReply(echo_reply)) => {
+                    echo_reply.sequence_number != echo_request.sequence_number
+                        || (self.is_raw && echo_reply.identifier != echo_request.identifier)
+                }
+                _ => false,
+            };
+            if is_stale {
+                continue;
+            }
+
+            return Ok(ProbeOutcome::new(
+                Received {
+                    icmp,
+                    from: self.ip,
+                    size,
+                    received_at: instant_end,
+                    ipv4_header,
+                },
+                instant_end.duration_since(instant_begin),
+                echo_request.payload(),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use icmp_packet::ICMP_HEADER_SIZE;
+
+    #[tokio::test]
+    async fn test_ping() -> Result<(), Box<dyn std::error::Error>> {
+        let client = ConnectedPingClient::<icmp_client::impl_tokio::Client>::new(
+            "127.0.0.1".parse().expect("Never"),
+            None,
+        )?;
+
+        for sequence_number in 1..=2 {
+            match client
+                .ping(None, Some(sequence_number), b"1234", Duration::from_secs(2))
+                .await?
+            {
+                ProbeOutcome::Reply { from, size, .. } => {
+                    assert_eq!(from, client.ip());
+                    assert_eq!(size, ICMP_HEADER_SIZE + 2 + 4);
+                }
+                x => panic!("{x:?}"),
+            }
+        }
+
+        assert_eq!(client.stats().packets_sent, 2);
+        assert_eq!(client.stats().packets_received, 2);
+
+        assert!(ConnectedPingClient::<icmp_client::impl_tokio::Client>::new(
+            "127.0.0.1".parse().expect("Never"),
+            Some(ClientConfig::with_ipv6()),
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ping_raw() -> Result<(), Box<dyn std::error::Error>> {
+        let ip = "127.0.0.1".parse().expect("Never");
+        let new = || {
+            ConnectedPingClient::<icmp_client::impl_tokio::Client>::new(
+                ip,
+                Some(ClientConfig::new().socket_type(icmp_client::SocketType::Raw)),
+            )
+        };
+        let (client_a, client_b) = match (new(), new()) {
+            (Ok(a), Ok(b)) => (a, b),
+            (Err(AsyncClientWithConfigError::PermissionDenied(err)), _) => {
+                eprintln!("skipped, needs CAP_NET_RAW, err:{err}");
+                return Ok(());
+            }
+            (Err(err), _) | (_, Err(err)) => return Err(err.into()),
+        };
+
+        // Both raw sockets receive both replies, of the same sequence number.
+        let (a, b) = tokio::join!(
+            client_a.ping(Some(0x1111), Some(1), b"aaaa", Duration::from_secs(2)),
+            client_b.ping(Some(0x2222), Some(1), b"bbbb", Duration::from_secs(2)),
+        );
+        assert!(a?.is_reply());
+        assert!(b?.is_reply());
+
+        Ok(())
+    }
+}
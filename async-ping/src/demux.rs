@@ -0,0 +1,319 @@
+//! The receive side of [`PingClient`](crate::PingClient).
+//!
+//! Each datagram is matched to its probe in place on the receive task, by the echo reply,
+//! or by the echo request quoted in an ICMP error, and parsed only when a probe is pending.
+
+use core::time::Duration;
+use std::{net::IpAddr, sync::Arc};
+
+use icmp_client::{AsyncClient, AsyncClientExt as _};
+use icmp_packet::{
+    ip::{parse_ipv4_packet_bytes, parse_ipv6_packet_bytes},
+    pnet_packet::{
+        icmp::IcmpTypes,
+        icmpv6::Icmpv6Types,
+        ip::{IpNextHeaderProtocol, IpNextHeaderProtocols},
+    },
+    Icmp, Icmpv4, Icmpv6, ICMP_HEADER_SIZE,
+};
+use tokio::time::Instant;
+use tracing::{event, Level};
+
+use crate::{outcome::Received, registry::Key, PingError, RecvFromMap};
+
+//
+/// The largest IP packet, so that nothing is truncated.
+pub(crate) const RECV_BUF_SIZE: usize = 65535;
+
+const RECV_FROM_BACKOFF_MIN: Duration = Duration::from_millis(10);
+const RECV_FROM_BACKOFF_MAX: Duration = Duration::from_secs(1);
+
+/// Delay before the next `recv_from` after a failed one, doubled on each failure in a row.
+#[derive(Debug, Default)]
+struct RecvFromBackoff(Option<Duration>);
+
+impl RecvFromBackoff {
+    fn next(&mut self) -> Duration {
+        let dur = self
+            .0
+            .map(|x| (x * 2).min(RECV_FROM_BACKOFF_MAX))
+            .unwrap_or(RECV_FROM_BACKOFF_MIN);
+        self.0 = Some(dur);
+        dur
+    }
+
+    fn reset(&mut self) {
+        self.0 = None;
+    }
+}
+
+//
+/// Key of the probe the ICMPv4 message is about, `None` for echo requests and the like.
+pub(crate) fn icmpv4_key(bytes: &[u8], source: IpAddr, match_identifier: bool) -> Option<Key> {
+    let (destination, echo_bytes) = match *bytes.first()? {
+        x if x == IcmpTypes::EchoReply.0 => (source, bytes),
+        x if [
+            IcmpTypes::DestinationUnreachable.0,
+            IcmpTypes::SourceQuench.0,
+            IcmpTypes::RedirectMessage.0,
+            IcmpTypes::TimeExceeded.0,
+            IcmpTypes::ParameterProblem.0,
+        ]
+        .contains(&x) =>
+        {
+            let (header, quoted_bytes) = parse_ipv4_packet_bytes(bytes.get(ICMP_HEADER_SIZE..)?)?;
+            if header.protocol != IpNextHeaderProtocols::Icmp.0
+                || quoted_bytes.first() != Some(&IcmpTypes::EchoRequest.0)
+            {
+                return None;
+            }
+            (IpAddr::V4(header.destination), quoted_bytes)
+        }
+        _ => return None,
+    };
+
+    echo_key(destination, echo_bytes, match_identifier)
+}
+
+/// Key of the probe the ICMPv6 message is about, `None` for echo requests and the like.
+pub(crate) fn icmpv6_key(bytes: &[u8], source: IpAddr, match_identifier: bool) -> Option<Key> {
+    let (destination, echo_bytes) = match *bytes.first()? {
+        x if x == Icmpv6Types::EchoReply.0 => (source, bytes),
+        x if [
+            Icmpv6Types::DestinationUnreachable.0,
+            Icmpv6Types::PacketTooBig.0,
+            Icmpv6Types::TimeExceeded.0,
+            Icmpv6Types::ParameterProblem.0,
+        ]
+        .contains(&x) =>
+        {
+            let (header, quoted_bytes) = parse_ipv6_packet_bytes(bytes.get(ICMP_HEADER_SIZE..)?)?;
+            if IpNextHeaderProtocol(header.next_header) != IpNextHeaderProtocols::Icmpv6
+                || quoted_bytes.first() != Some(&Icmpv6Types::EchoRequest.0)
+            {
+                return None;
+            }
+            (IpAddr::V6(header.destination), quoted_bytes)
+        }
+        _ => return None,
+    };
+
+    echo_key(destination, echo_bytes, match_identifier)
+}
+
+fn echo_key(destination: IpAddr, echo_bytes: &[u8], match_identifier: bool) -> Option<Key> {
+    let identifier = u16::from_be_bytes([*echo_bytes.get(4)?, *echo_bytes.get(5)?]);
+    let sequence_number = u16::from_be_bytes([*echo_bytes.get(6)?, *echo_bytes.get(7)?]);
+    Some((
+        destination,
+        match_identifier.then_some(identifier),
+        sequence_number,
+    ))
+}
+
+//
+pub(crate) async fn v4_recv_from_loop<C>(
+    v4_client: Arc<C>,
+    v4_is_raw: bool,
+    v4_recv_from_map: RecvFromMap,
+) where
+    C: AsyncClient,
+{
+    let mut backoff = RecvFromBackoff::default();
+    let mut buf = vec![0; RECV_BUF_SIZE];
+
+    loop {
+        let (n, addr) = match v4_client.recv_from(&mut buf).await {
+            Ok(x) => {
+                backoff.reset();
+                x
+            }
+            Err(err) => {
+                let dur = backoff.next();
+                event!(
+                    Level::ERROR,
+                    "v4_client.recv_from failed, err:{err} retry_in:{dur:?}"
+                );
+                tokio::time::sleep(dur).await;
+                continue;
+            }
+        };
+        let instant_end = Instant::now();
+
+        let (ipv4_header, bytes) = if v4_is_raw {
+            match parse_ipv4_packet_bytes(&buf[..n]) {
+                Some((header, bytes)) => (Some(header), bytes),
+                None => {
+                    event!(Level::WARN, "parse_ipv4_packet_bytes None, addr:{addr}");
+                    continue;
+                }
+            }
+        } else {
+            (None, &buf[..n])
+        };
+
+        // Raw sockets also receive the echo requests.
+        let Some(key) = icmpv4_key(bytes, addr.ip(), v4_is_raw) else {
+            continue;
+        };
+        // Else orphaned, duplicate or late, see `ProbeStats`.
+        let Some(tx) = v4_recv_from_map.take(&key, instant_end) else {
+            continue;
+        };
+
+        let ret = match Icmpv4::parse_from_packet_bytes(bytes) {
+            Ok(Some(icmpv4)) => Ok(Received {
+                icmp: Icmp::V4(icmpv4),
+                from: addr.ip(),
+                size: bytes.len(),
+                received_at: instant_end,
+                ipv4_header,
+            }),
+            Ok(None) => {
+                event!(Level::WARN, "Icmpv4 incomplete, addr:{addr}");
+                continue;
+            }
+            Err(err) => Err(PingError::Icmpv4ParseError(err)),
+        };
+        if tx.send(ret).is_err() {
+            event!(Level::DEBUG, "tx.send failed, key:{key:?}");
+        }
+    }
+}
+
+pub(crate) async fn v6_recv_from_loop<C>(
+    v6_client: Arc<C>,
+    v6_is_raw: bool,
+    v6_recv_from_map: RecvFromMap,
+) where
+    C: AsyncClient,
+{
+    let mut backoff = RecvFromBackoff::default();
+    let mut buf = vec![0; RECV_BUF_SIZE];
+
+    loop {
+        let (n, addr) = match v6_client.recv_from(&mut buf).await {
+            Ok(x) => {
+                backoff.reset();
+                x
+            }
+            Err(err) => {
+                let dur = backoff.next();
+                event!(
+                    Level::ERROR,
+                    "v6_client.recv_from failed, err:{err} retry_in:{dur:?}"
+                );
+                tokio::time::sleep(dur).await;
+                continue;
+            }
+        };
+        let instant_end = Instant::now();
+
+        let bytes = &buf[..n];
+
+        // Raw sockets also receive the echo requests.
+        let Some(key) = icmpv6_key(bytes, addr.ip(), v6_is_raw) else {
+            continue;
+        };
+        // Else orphaned, duplicate or late, see `ProbeStats`.
+        let Some(tx) = v6_recv_from_map.take(&key, instant_end) else {
+            continue;
+        };
+
+        let ret = match Icmpv6::parse_from_packet_bytes(bytes) {
+            Ok(Some(icmpv6)) => Ok(Received {
+                icmp: Icmp::V6(icmpv6),
+                from: addr.ip(),
+                size: bytes.len(),
+                received_at: instant_end,
+                ipv4_header: None,
+            }),
+            Ok(None) => {
+                event!(Level::WARN, "Icmpv6 incomplete, addr:{addr}");
+                continue;
+            }
+            Err(err) => Err(PingError::Icmpv6ParseError(err)),
+        };
+        if tx.send(ret).is_err() {
+            event!(Level::DEBUG, "tx.send failed, key:{key:?}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    use icmp_packet::{
+        icmpv4::{self, render_echo_reply_packet_bytes},
+        ip::render_ipv4_packet_bytes,
+        pnet_packet::icmp::IcmpCode,
+        PayloadLengthDelimitedEchoRequest,
+    };
+
+    #[test]
+    fn test_icmpv4_key() {
+        let local = Ipv4Addr::new(10, 0, 0, 1);
+        let remote = Ipv4Addr::new(10, 0, 0, 2);
+        let echo_request_bytes =
+            PayloadLengthDelimitedEchoRequest::new(Some(1.into()), Some(2.into()), b"1234")
+                .render_v4_packet_bytes();
+
+        assert_eq!(icmpv4_key(&echo_request_bytes, remote.into(), true), None);
+
+        let echo_reply_bytes = render_echo_reply_packet_bytes(&echo_request_bytes).expect("Never");
+        assert_eq!(
+            icmpv4_key(&echo_reply_bytes, remote.into(), true),
+            Some((remote.into(), Some(1), 2))
+        );
+        assert_eq!(
+            icmpv4_key(&echo_reply_bytes, remote.into(), false),
+            Some((remote.into(), None, 2))
+        );
+
+        // From a router, about the echo request to `remote`.
+        let router = Ipv4Addr::new(10, 0, 1, 1);
+        let error_bytes = icmpv4::render_error_packet_bytes(
+            IcmpTypes::TimeExceeded,
+            IcmpCode(0),
+            0,
+            &render_ipv4_packet_bytes(local, remote, 1, &echo_request_bytes),
+        );
+        assert_eq!(
+            icmpv4_key(&error_bytes, router.into(), true),
+            Some((remote.into(), Some(1), 2))
+        );
+        // Quoting the first 8 bytes only.
+        assert_eq!(
+            icmpv4_key(
+                &error_bytes[..ICMP_HEADER_SIZE + 20 + 8],
+                router.into(),
+                true
+            ),
+            Some((remote.into(), Some(1), 2))
+        );
+        assert_eq!(
+            icmpv4_key(
+                &error_bytes[..ICMP_HEADER_SIZE + 20 + 4],
+                router.into(),
+                true
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_recv_from_backoff() {
+        let mut backoff = RecvFromBackoff::default();
+        assert_eq!(backoff.next(), RECV_FROM_BACKOFF_MIN);
+        assert_eq!(backoff.next(), RECV_FROM_BACKOFF_MIN * 2);
+        for _ in 0..10 {
+            backoff.next();
+        }
+        assert_eq!(backoff.next(), RECV_FROM_BACKOFF_MAX);
+        backoff.reset();
+        assert_eq!(backoff.next(), RECV_FROM_BACKOFF_MIN);
+    }
+}
@@ -0,0 +1,460 @@
+//! Host discovery over IPv4 ranges and IPv6 lists, see [`PingClient::discover`](crate::PingClient::discover).
+
+use core::{pin::Pin, str::FromStr, time::Duration};
+use std::{
+    collections::HashSet,
+    io::Error as IoError,
+    net::{AddrParseError, IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+use futures_core::Stream as _;
+use icmp_client::AsyncClient;
+use rand::Rng as _;
+
+use crate::{ProbeOutcome, Scan, TargetStatus};
+
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Ipv4Cidr {
+    network: Ipv4Addr,
+    prefix_len: u8,
+}
+
+impl Ipv4Cidr {
+    /// The host bits of `addr` are cleared.
+    pub fn new(addr: Ipv4Addr, prefix_len: u8) -> Result<Self, Ipv4CidrParseError> {
+        if prefix_len > 32 {
+            return Err(Ipv4CidrParseError::PrefixLenInvalid);
+        }
+        Ok(Self {
+            network: Ipv4Addr::from(u32::from(addr) & Self::mask(prefix_len)),
+            prefix_len,
+        })
+    }
+
+    fn mask(prefix_len: u8) -> u32 {
+        u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0)
+    }
+
+    pub fn network(&self) -> Ipv4Addr {
+        self.network
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    pub fn broadcast(&self) -> Ipv4Addr {
+        Ipv4Addr::from(u32::from(self.network) | !Self::mask(self.prefix_len))
+    }
+
+    pub fn contains(&self, addr: Ipv4Addr) -> bool {
+        u32::from(addr) & Self::mask(self.prefix_len) == u32::from(self.network)
+    }
+
+    /// Without the network and broadcast addresses, but of /31 and /32, RFC 3021.
+    pub fn hosts(&self) -> impl Iterator<Item = Ipv4Addr> {
+        let (first, last) = self.host_range();
+        (first..=last).map(Ipv4Addr::from)
+    }
+
+    fn host_range(&self) -> (u32, u32) {
+        let (first, last) = (u32::from(self.network), u32::from(self.broadcast()));
+        if self.prefix_len < 31 {
+            (first + 1, last - 1)
+        } else {
+            (first, last)
+        }
+    }
+}
+
+impl FromStr for Ipv4Cidr {
+    type Err = Ipv4CidrParseError;
+
+    /// `10.0.0.0/24`, or `10.0.0.1` as a /32.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, prefix_len)) => (
+                addr,
+                prefix_len
+                    .parse()
+                    .map_err(|_| Ipv4CidrParseError::PrefixLenInvalid)?,
+            ),
+            None => (s, 32),
+        };
+        let addr = addr.parse().map_err(Ipv4CidrParseError::AddrInvalid)?;
+        Self::new(addr, prefix_len)
+    }
+}
+
+impl core::fmt::Display for Ipv4Cidr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix_len)
+    }
+}
+
+//
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ipv4CidrParseError {
+    AddrInvalid(AddrParseError),
+    PrefixLenInvalid,
+}
+
+impl core::fmt::Display for Ipv4CidrParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::AddrInvalid(err) => write!(f, "addr invalid, err:{err}"),
+            Self::PrefixLenInvalid => write!(f, "prefix_len invalid, 0 to 32"),
+        }
+    }
+}
+
+impl std::error::Error for Ipv4CidrParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::AddrInvalid(err) => Some(err),
+            Self::PrefixLenInvalid => None,
+        }
+    }
+}
+
+//
+#[derive(Debug, Clone, Default)]
+pub struct DiscoveryTargets {
+    ipv4_cidrs: Vec<Ipv4Cidr>,
+    ipv6_addrs: Vec<Ipv6Addr>,
+    exclude_ipv4_cidrs: Vec<Ipv4Cidr>,
+    exclude_addrs: HashSet<IpAddr>,
+}
+
+impl DiscoveryTargets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn ipv4_cidr(mut self, cidr: Ipv4Cidr) -> Self {
+        self.ipv4_cidrs.push(cidr);
+        self
+    }
+
+    pub fn ipv6_addrs(mut self, addrs: impl IntoIterator<Item = Ipv6Addr>) -> Self {
+        self.ipv6_addrs.extend(addrs);
+        self
+    }
+
+    pub fn exclude_ipv4_cidr(mut self, cidr: Ipv4Cidr) -> Self {
+        self.exclude_ipv4_cidrs.push(cidr);
+        self
+    }
+
+    pub fn exclude_addrs(mut self, addrs: impl IntoIterator<Item = IpAddr>) -> Self {
+        self.exclude_addrs.extend(addrs);
+        self
+    }
+
+    /// Once each, in random order to spread the probes over the subnets.
+    ///
+    /// Lazily, a /0 is not held in memory, see [`DiscoveryAddrs`].
+    pub fn addrs(&self) -> DiscoveryAddrs {
+        DiscoveryAddrs::new(self.clone())
+    }
+}
+
+//
+/// The addresses of [`DiscoveryTargets`] in random order, none of them held.
+///
+/// The indexes, the hosts of every IPv4 range then the IPv6 addresses, are walked by an LCG of
+/// full period modulo the next power of two, those beyond the last are skipped. So are the
+/// excluded addresses and those of a range before.
+#[derive(Debug, Clone)]
+pub struct DiscoveryAddrs {
+    targets: DiscoveryTargets,
+    /// First host of each IPv4 range and the index after its last one.
+    ipv4_ranges: Vec<(u32, u64)>,
+    len: u64,
+    mask: u64,
+    multiplier: u64,
+    increment: u64,
+    index: u64,
+    steps: u64,
+}
+
+impl DiscoveryAddrs {
+    fn new(mut targets: DiscoveryTargets) -> Self {
+        targets.ipv6_addrs.sort_unstable();
+        targets.ipv6_addrs.dedup();
+
+        let mut len = 0;
+        let ipv4_ranges = targets
+            .ipv4_cidrs
+            .iter()
+            .map(|cidr| {
+                let (first, last) = cidr.host_range();
+                len += (last - first) as u64 + 1;
+                (first, len)
+            })
+            .collect();
+        len += targets.ipv6_addrs.len() as u64;
+
+        let size = len
+            .checked_next_power_of_two()
+            .expect("Never when fewer than 2^63 addrs");
+        let mut rng = rand::thread_rng();
+        Self {
+            targets,
+            ipv4_ranges,
+            len,
+            mask: size - 1,
+            // Hull-Dobell, modulo a power of two.
+            multiplier: rng.gen::<u64>() << 2 | 1,
+            increment: rng.gen::<u64>() | 1,
+            index: rng.gen::<u64>() & (size - 1),
+            steps: size,
+        }
+    }
+
+    // `None` when excluded or of a range before.
+    fn addr(&self, index: u64) -> Option<IpAddr> {
+        let targets = &self.targets;
+        let addr = match self.ipv4_ranges.iter().position(|(_, end)| index < *end) {
+            Some(i) => {
+                let start = if i == 0 { 0 } else { self.ipv4_ranges[i - 1].1 };
+                let addr = self.ipv4_ranges[i].0 + (index - start) as u32;
+                if targets.ipv4_cidrs[..i].iter().any(|x| {
+                    let (first, last) = x.host_range();
+                    (first..=last).contains(&addr)
+                }) || targets
+                    .exclude_ipv4_cidrs
+                    .iter()
+                    .any(|x| x.contains(addr.into()))
+                {
+                    return None;
+                }
+                IpAddr::from(Ipv4Addr::from(addr))
+            }
+            None => {
+                let ipv4_len = self.ipv4_ranges.last().map(|(_, end)| *end).unwrap_or(0);
+                IpAddr::from(targets.ipv6_addrs[(index - ipv4_len) as usize])
+            }
+        };
+        (!targets.exclude_addrs.contains(&addr)).then_some(addr)
+    }
+}
+
+impl Iterator for DiscoveryAddrs {
+    type Item = IpAddr;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.steps > 0 {
+            self.steps -= 1;
+            let index = self.index;
+            self.index = self
+                .index
+                .wrapping_mul(self.multiplier)
+                .wrapping_add(self.increment)
+                & self.mask;
+            if index >= self.len {
+                continue;
+            }
+            if let Some(addr) = self.addr(index) {
+                return Some(addr);
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, usize::try_from(self.steps).ok())
+    }
+}
+
+//
+/// In address order.
+#[derive(Debug, Default)]
+pub struct DiscoveryReport {
+    /// With the lowest RTT.
+    pub answered: Vec<(IpAddr, Duration)>,
+    /// With the last ICMP error, e.g. Destination Unreachable of the last router.
+    pub errored: Vec<(IpAddr, ProbeOutcome)>,
+    /// With the last send failure, e.g. no route, local and so nothing about the target.
+    pub send_failed: Vec<(IpAddr, IoError)>,
+    pub silent: Vec<IpAddr>,
+}
+
+impl DiscoveryReport {
+    pub(crate) async fn collect<C>(mut scan: Scan<C>) -> Self
+    where
+        C: AsyncClient + Send + Sync + 'static,
+    {
+        let mut report = Self::default();
+        while let Some(result) = core::future::poll_fn(|cx| Pin::new(&mut scan).poll_next(cx)).await
+        {
+            match result.status {
+                TargetStatus::Alive => report.answered.push((
+                    result.ip,
+                    // The payload mismatches are not in the statistics.
+                    result
+                        .rtts
+                        .iter()
+                        .flatten()
+                        .min()
+                        .copied()
+                        .expect("Never when alive"),
+                )),
+                TargetStatus::Unreachable => match result.error.expect("Never when unreachable") {
+                    ProbeOutcome::SendFailed(err) => report.send_failed.push((result.ip, err)),
+                    outcome => report.errored.push((result.ip, outcome)),
+                },
+                TargetStatus::Timeout => report.silent.push(result.ip),
+            }
+        }
+        report.answered.sort_unstable_by_key(|(ip, _)| *ip);
+        report.errored.sort_unstable_by_key(|(ip, _)| *ip);
+        report.send_failed.sort_unstable_by_key(|(ip, _)| *ip);
+        report.silent.sort_unstable();
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use icmp_client::{
+        impl_sim::{Client, Link, Topology},
+        Config as ClientConfig,
+    };
+
+    use crate::{PingClient, ScanConfig};
+
+    #[test]
+    fn test_ipv4_cidr() {
+        let cidr = "10.0.1.7/24".parse::<Ipv4Cidr>().expect("Never");
+        assert_eq!(cidr.network(), Ipv4Addr::new(10, 0, 1, 0));
+        assert_eq!(cidr.broadcast(), Ipv4Addr::new(10, 0, 1, 255));
+        assert_eq!(cidr.to_string(), "10.0.1.0/24");
+        assert!(cidr.contains(Ipv4Addr::new(10, 0, 1, 255)));
+        assert!(!cidr.contains(Ipv4Addr::new(10, 0, 2, 0)));
+        let hosts = cidr.hosts().collect::<Vec<_>>();
+        assert_eq!(hosts.len(), 254);
+        assert_eq!(hosts[0], Ipv4Addr::new(10, 0, 1, 1));
+        assert_eq!(hosts[253], Ipv4Addr::new(10, 0, 1, 254));
+
+        for (s, n) in [("10.0.0.0/30", 2), ("10.0.0.0/31", 2), ("10.0.0.1", 1)] {
+            let cidr = s.parse::<Ipv4Cidr>().expect("Never");
+            assert_eq!(cidr.hosts().count(), n, "{s}");
+        }
+        let cidr = "0.0.0.0/0".parse::<Ipv4Cidr>().expect("Never");
+        assert_eq!(cidr.broadcast(), Ipv4Addr::BROADCAST);
+        assert!(cidr.contains(Ipv4Addr::new(192, 0, 2, 1)));
+
+        assert_eq!(
+            "10.0.0.0/33".parse::<Ipv4Cidr>(),
+            Err(Ipv4CidrParseError::PrefixLenInvalid)
+        );
+        assert!(matches!(
+            "10.0.0/24".parse::<Ipv4Cidr>(),
+            Err(Ipv4CidrParseError::AddrInvalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_addrs() {
+        let targets = DiscoveryTargets::new()
+            .ipv4_cidr("10.0.0.0/28".parse().expect("Never"))
+            .ipv4_cidr("10.0.0.8/29".parse().expect("Never"))
+            .ipv6_addrs(["2001:db8::1".parse().expect("Never")])
+            .exclude_ipv4_cidr("10.0.0.4/30".parse().expect("Never"))
+            .exclude_addrs([IpAddr::from(Ipv4Addr::new(10, 0, 0, 1))]);
+        let mut addrs = targets.addrs().collect::<Vec<_>>();
+        addrs.sort_unstable();
+        assert_eq!(
+            addrs,
+            [2, 3, 8, 9, 10, 11, 12, 13, 14]
+                .map(|x| IpAddr::from(Ipv4Addr::new(10, 0, 0, x)))
+                .into_iter()
+                .chain(["2001:db8::1".parse().expect("Never")])
+                .collect::<Vec<_>>()
+        );
+
+        // Of every index, whatever the order.
+        let addrs = DiscoveryTargets::new()
+            .ipv4_cidr("10.0.0.0/22".parse().expect("Never"))
+            .addrs()
+            .collect::<HashSet<_>>();
+        assert_eq!(addrs.len(), 1022);
+
+        // Lazily.
+        let addrs = DiscoveryTargets::new()
+            .ipv4_cidr("0.0.0.0/0".parse().expect("Never"))
+            .addrs();
+        assert_eq!(addrs.take(1000).collect::<HashSet<_>>().len(), 1000);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_discover() -> Result<(), Box<dyn std::error::Error>> {
+        let link = Link::new(Duration::from_millis(5));
+        let network = Topology::new()
+            .host(Ipv4Addr::new(10, 0, 0, 1))
+            .router(Ipv4Addr::new(10, 0, 0, 254))
+            .host(Ipv4Addr::new(10, 0, 1, 1))
+            .unreachable_host(Ipv4Addr::new(10, 0, 1, 2))
+            .silent_host(Ipv4Addr::new(10, 0, 1, 3))
+            .host(Ipv4Addr::new(10, 0, 1, 4))
+            .link([10, 0, 0, 1], [10, 0, 0, 254], link)
+            .link([10, 0, 0, 254], [10, 0, 1, 1], link)
+            .link([10, 0, 0, 254], [10, 0, 1, 2], link)
+            .link([10, 0, 0, 254], [10, 0, 1, 3], link)
+            .link([10, 0, 0, 254], [10, 0, 1, 4], link)
+            .build();
+        let client =
+            PingClient::<Client>::new(Some(ClientConfig::new().sim_network(network)), None)?;
+
+        let report = client
+            .discover(
+                &DiscoveryTargets::new()
+                    .ipv4_cidr("10.0.1.0/29".parse()?)
+                    .exclude_addrs([Ipv4Addr::new(10, 0, 1, 4).into()]),
+                ScanConfig::new()
+                    .retries(1)
+                    .timeout(Duration::from_millis(100)),
+            )
+            .await?;
+
+        assert_eq!(
+            report.answered,
+            vec![(
+                IpAddr::from(Ipv4Addr::new(10, 0, 1, 1)),
+                Duration::from_millis(20)
+            )]
+        );
+        let errored = report
+            .errored
+            .iter()
+            .map(|(ip, outcome)| (*ip, outcome))
+            .collect::<Vec<_>>();
+        assert_eq!(errored.len(), 1);
+        assert!(matches!(
+            errored[0],
+            (IpAddr::V4(ip), ProbeOutcome::Unreachable { code: 1, .. }) if ip == Ipv4Addr::new(10, 0, 1, 2)
+        ));
+        // Not in the topology.
+        assert_eq!(
+            report
+                .send_failed
+                .iter()
+                .map(|(ip, _)| *ip)
+                .collect::<Vec<_>>(),
+            vec![
+                IpAddr::from(Ipv4Addr::new(10, 0, 1, 5)),
+                IpAddr::from(Ipv4Addr::new(10, 0, 1, 6))
+            ]
+        );
+        assert_eq!(
+            report.silent,
+            vec![IpAddr::from(Ipv4Addr::new(10, 0, 1, 3))]
+        );
+
+        Ok(())
+    }
+}
@@ -3,29 +3,29 @@ pub use icmp_packet;
 
 use core::time::Duration;
 use std::{
-    collections::HashMap,
     io::{Error as IoError, ErrorKind as IoErrorKind},
-    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
-    sync::Arc,
-    time::Instant,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex as StdMutex,
+    },
 };
 
-use icmp_client::{AsyncClient, AsyncClientWithConfigError, Config as ClientConfig};
+use icmp_client::{
+    AsyncClient, AsyncClientExt as _, AsyncClientWithConfigError, Config as ClientConfig, Stats,
+};
 use icmp_packet::{
-    icmpv4::ParseError as Icmpv4ParseError, icmpv6::ParseError as Icmpv6ParseError, Icmp, Icmpv4,
-    Icmpv6, PayloadLengthDelimitedEchoRequest,
+    icmpv4::ParseError as Icmpv4ParseError, icmpv6::ParseError as Icmpv6ParseError, ip::Ipv4Header,
+    Icmp, Icmpv4, Icmpv6, PayloadLengthDelimitedEchoRequest, PayloadMismatch,
 };
-use tokio::sync::{
-    mpsc::{self, Sender},
-    Mutex,
+use tokio::{
+    sync::{broadcast, Mutex as AsyncMutex, Notify, Semaphore},
+    task::JoinHandle,
+    time::Instant,
 };
-use tracing::{event, Level};
 
 //
-type V4RecvFromMap =
-    Arc<Mutex<HashMap<SocketAddr, Sender<(Result<Icmpv4, Icmpv4ParseError>, Instant)>>>>;
-type V6RecvFromMap =
-    Arc<Mutex<HashMap<SocketAddr, Sender<(Result<Icmpv6, Icmpv6ParseError>, Instant)>>>>;
+pub(crate) type RecvFromMap = Arc<Registry<Result<Received, PingError>>>;
 
 //
 pub struct PingClient<C>
@@ -34,8 +34,21 @@ where
 {
     v4_client: Option<Arc<C>>,
     v6_client: Option<Arc<C>>,
-    v4_recv_from_map: V4RecvFromMap,
-    v6_recv_from_map: V6RecvFromMap,
+    v4_is_raw: bool,
+    v6_is_raw: bool,
+    v4_recv_from_map: RecvFromMap,
+    v6_recv_from_map: RecvFromMap,
+    /// One sender at a time, the clients only wake the last task polling a direction.
+    v4_send_lock: Arc<AsyncMutex<()>>,
+    v6_send_lock: Arc<AsyncMutex<()>>,
+    reply_events: broadcast::Sender<ReplyEvent>,
+    buffer_pool: Arc<BufferPool>,
+    timeout: Duration,
+    payload: Arc<[u8]>,
+    identifier: IdentifierStrategy,
+    semaphore: Option<Arc<Semaphore>>,
+    spawn_recv_from: bool,
+    tasks: Arc<RecvFromTasks>,
 }
 
 impl<C> core::fmt::Debug for PingClient<C>
@@ -55,15 +68,27 @@ where
         Self {
             v4_client: self.v4_client.clone(),
             v6_client: self.v6_client.clone(),
+            v4_is_raw: self.v4_is_raw,
+            v6_is_raw: self.v6_is_raw,
             v4_recv_from_map: self.v4_recv_from_map.clone(),
             v6_recv_from_map: self.v6_recv_from_map.clone(),
+            v4_send_lock: self.v4_send_lock.clone(),
+            v6_send_lock: self.v6_send_lock.clone(),
+            reply_events: self.reply_events.clone(),
+            buffer_pool: self.buffer_pool.clone(),
+            timeout: self.timeout,
+            payload: self.payload.clone(),
+            identifier: self.identifier,
+            semaphore: self.semaphore.clone(),
+            spawn_recv_from: self.spawn_recv_from,
+            tasks: self.tasks.clone(),
         }
     }
 }
 
 impl<C> PingClient<C>
 where
-    C: AsyncClient,
+    C: AsyncClient + Send + Sync + 'static,
 {
     pub fn new(
         v4_client_config: Option<ClientConfig>,
@@ -71,187 +96,154 @@ where
     ) -> Result<Self, AsyncClientWithConfigError> {
         let v4_client = if let Some(mut v4_client_config) = v4_client_config {
             if v4_client_config.is_ipv6() {
-                return Err(IoError::new(IoErrorKind::Other, "v4_client_config invalid").into());
+                return Err(IoError::other("v4_client_config invalid").into());
             }
             if v4_client_config.bind.is_none() {
                 v4_client_config.bind =
                     Some(SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), 0).into());
             }
 
-            Some(Arc::new(C::with_config(&v4_client_config)?))
+            Some((
+                C::with_config(&v4_client_config)?,
+                v4_client_config.is_raw(),
+            ))
         } else {
             None
         };
 
         let v6_client = if let Some(mut v6_client_config) = v6_client_config {
             if !v6_client_config.is_ipv6() {
-                return Err(IoError::new(IoErrorKind::Other, "v4_client_config invalid").into());
+                return Err(IoError::other("v6_client_config invalid").into());
             }
             if v6_client_config.bind.is_none() {
                 v6_client_config.bind =
                     Some(SocketAddrV6::new(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0), 0, 0, 0).into());
             }
 
-            Some(Arc::new(C::with_config(&v6_client_config)?))
+            Some((
+                C::with_config(&v6_client_config)?,
+                v6_client_config.is_raw(),
+            ))
         } else {
             None
         };
 
-        let v4_recv_from_map = Arc::new(Mutex::new(HashMap::new()));
-        let v6_recv_from_map = Arc::new(Mutex::new(HashMap::new()));
+        Ok(Self::with_clients(v4_client, v6_client))
+    }
 
-        Ok(Self {
-            v4_client,
-            v6_client,
-            v4_recv_from_map,
-            v6_recv_from_map,
-        })
+    /// With clients built elsewhere, e.g. `Box<dyn AsyncClient + Send + Sync>` of different backends.
+    ///
+    /// Each client with whether it is raw, a raw IPv4 one receives the IPv4 header,
+    /// the identifier of the replies is then matched too.
+    pub fn with_clients(v4_client: Option<(C, bool)>, v6_client: Option<(C, bool)>) -> Self {
+        let (reply_events, _) = broadcast::channel(REPLY_EVENTS_CAPACITY);
+        let (v4_client, v4_is_raw) = v4_client.map_or((None, false), |(x, y)| (Some(x), y));
+        let (v6_client, v6_is_raw) = v6_client.map_or((None, false), |(x, y)| (Some(x), y));
+        Self {
+            v4_client: v4_client.map(Arc::new),
+            v6_client: v6_client.map(Arc::new),
+            v4_is_raw,
+            v6_is_raw,
+            v4_recv_from_map: Arc::new(Registry::new(reply_events.clone())),
+            v6_recv_from_map: Arc::new(Registry::new(reply_events.clone())),
+            v4_send_lock: Default::default(),
+            v6_send_lock: Default::default(),
+            reply_events,
+            buffer_pool: Arc::new(BufferPool::new(BUFFER_POOL_MAX_LEN)),
+            timeout: DEFAULT_TIMEOUT,
+            payload: vec![0; DEFAULT_PAYLOAD_SIZE].into(),
+            identifier: IdentifierStrategy::default(),
+            semaphore: None,
+            spawn_recv_from: true,
+            tasks: Arc::new(RecvFromTasks::default()),
+        }
     }
 
-    // TODO, Support with spawn and without spawn.
-    pub async fn handle_v4_recv_from(&self) {
-        let v4_client = match self.v4_client.as_ref() {
-            Some(x) => x,
-            None => return,
-        };
+    pub fn builder() -> PingClientBuilder<C> {
+        PingClientBuilder::new()
+    }
+
+    /// Of the v4 and v6 clients together.
+    pub fn stats(&self) -> Stats {
+        self.v4_client
+            .iter()
+            .chain(self.v6_client.iter())
+            .map(|x| x.stats())
+            .fold(Stats::default(), |a, b| a + b)
+    }
+
+    /// Of the v4 and v6 pending probes together.
+    pub fn probe_stats(&self) -> ProbeStats {
+        self.v4_recv_from_map.stats() + self.v6_recv_from_map.stats()
+    }
+
+    /// Duplicate, late and reordered replies from now on, of the v4 and v6 clients together.
+    ///
+    /// Lagging receivers miss the oldest events, [`Self::probe_stats`] counts them all.
+    pub fn reply_events(&self) -> broadcast::Receiver<ReplyEvent> {
+        self.reply_events.subscribe()
+    }
 
-        let mut buf = [0; 2048];
-        let bytes_present_map: Arc<Mutex<HashMap<SocketAddr, Vec<u8>>>> =
-            Arc::new(Mutex::new(HashMap::new()));
+    /// Spawns the receive tasks unless started, done by the first ping.
+    ///
+    /// They are aborted once every clone of the client is dropped, see also [`Self::shutdown`].
+    pub fn start_recv_from(&self) {
+        if !self.spawn_recv_from || self.tasks.is_started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let mut handles = self.tasks.handles.lock().expect("Never when lock");
+        if let Some(v4_client) = self.v4_client.clone() {
+            handles.push(tokio::spawn(v4_recv_from_loop(
+                v4_client,
+                self.v4_is_raw,
+                self.v4_recv_from_map.clone(),
+            )));
+        }
+        if let Some(v6_client) = self.v6_client.clone() {
+            handles.push(tokio::spawn(v6_recv_from_loop(
+                v6_client,
+                self.v6_is_raw,
+                self.v6_recv_from_map.clone(),
+            )));
+        }
+    }
+
+    /// New pings fail with [`PingError::Shutdown`], the ones in flight complete,
+    /// then the receive tasks are stopped.
+    pub async fn shutdown(&self) {
+        self.tasks.is_closing.store(true, Ordering::SeqCst);
 
         loop {
-            match v4_client.recv_from(&mut buf).await {
-                Ok((n, addr)) => {
-                    let instant_end = Instant::now();
-                    let bytes_read = buf[..n].to_owned();
-
-                    let v4_recv_from_map = self.v4_recv_from_map.clone();
-                    let bytes_present_map = bytes_present_map.clone();
-
-                    tokio::spawn(async move {
-                        let bytes = if let Some(mut bytes_present) =
-                            bytes_present_map.lock().await.remove(&addr)
-                        {
-                            bytes_present.extend_from_slice(&bytes_read);
-                            bytes_present
-                        } else {
-                            bytes_read
-                        };
-
-                        match Icmpv4::parse_from_packet_bytes(&bytes) {
-                            Ok(Some(icmpv4)) => {
-                                if let Some(tx) = v4_recv_from_map.lock().await.remove(&addr) {
-                                    if let Err(err) = tx.try_send((Ok(icmpv4), instant_end)) {
-                                        event!(
-                                            Level::ERROR,
-                                            "tx.send failed, err:{err} addr:{addr}"
-                                        );
-                                    }
-                                } else {
-                                    event!(
-                                        Level::WARN,
-                                        "v4_recv_from_map.remove None, addr:{addr}"
-                                    );
-                                }
-                            }
-                            Ok(None) => {
-                                bytes_present_map.lock().await.insert(addr, bytes);
-                            }
-                            Err(err) => {
-                                if let Some(tx) = v4_recv_from_map.lock().await.remove(&addr) {
-                                    if let Err(err) = tx.try_send((Err(err), instant_end)) {
-                                        event!(
-                                            Level::ERROR,
-                                            "tx.send failed, err:{err} addr:{addr}"
-                                        );
-                                    }
-                                } else {
-                                    event!(
-                                        Level::WARN,
-                                        "v4_recv_from_map.remove None, addr:{addr}"
-                                    );
-                                }
-                            }
-                        }
-                    });
-                }
-                Err(err) => {
-                    event!(Level::ERROR, "v4_client.recv_from failed, err:{err}");
-                }
+            let notified = self.tasks.idle.notified();
+            if self.tasks.in_flight.load(Ordering::SeqCst) == 0 {
+                break;
             }
+            notified.await;
+        }
+
+        let handles = core::mem::take(&mut *self.tasks.handles.lock().expect("Never when lock"));
+        for handle in handles {
+            handle.abort();
+            let _ = handle.await;
         }
     }
 
-    pub async fn handle_v6_recv_from(&self) {
-        let v6_client = match self.v6_client.as_ref() {
-            Some(x) => x,
-            None => return,
-        };
+    pub fn is_shutdown(&self) -> bool {
+        self.tasks.is_closing.load(Ordering::SeqCst)
+    }
 
-        let mut buf = [0; 2048];
-        let bytes_present_map: Arc<Mutex<HashMap<SocketAddr, Vec<u8>>>> =
-            Arc::new(Mutex::new(HashMap::new()));
+    /// Runs the receive loop on the current task, with `PingClientBuilder::spawn_recv_from(false)`.
+    pub async fn handle_v4_recv_from(&self) {
+        if let Some(v4_client) = self.v4_client.clone() {
+            v4_recv_from_loop(v4_client, self.v4_is_raw, self.v4_recv_from_map.clone()).await
+        }
+    }
 
-        loop {
-            match v6_client.recv_from(&mut buf).await {
-                Ok((n, addr)) => {
-                    let instant_end = Instant::now();
-                    let bytes_read = buf[..n].to_owned();
-
-                    let v6_recv_from_map = self.v6_recv_from_map.clone();
-                    let bytes_present_map = bytes_present_map.clone();
-
-                    tokio::spawn(async move {
-                        let bytes = if let Some(mut bytes_present) =
-                            bytes_present_map.lock().await.remove(&addr)
-                        {
-                            bytes_present.extend_from_slice(&bytes_read);
-                            bytes_present
-                        } else {
-                            bytes_read
-                        };
-
-                        match Icmpv6::parse_from_packet_bytes(&bytes) {
-                            Ok(Some(icmpv6)) => {
-                                if let Some(tx) = v6_recv_from_map.lock().await.remove(&addr) {
-                                    if let Err(err) = tx.try_send((Ok(icmpv6), instant_end)) {
-                                        event!(
-                                            Level::ERROR,
-                                            "tx.send failed, err:{err} addr:{addr}"
-                                        );
-                                    }
-                                } else {
-                                    event!(
-                                        Level::WARN,
-                                        "v6_recv_from_map.remove None, addr:{addr}"
-                                    );
-                                }
-                            }
-                            Ok(None) => {
-                                bytes_present_map.lock().await.insert(addr, bytes);
-                            }
-                            Err(err) => {
-                                if let Some(tx) = v6_recv_from_map.lock().await.remove(&addr) {
-                                    if let Err(err) = tx.try_send((Err(err), instant_end)) {
-                                        event!(
-                                            Level::ERROR,
-                                            "tx.send failed, err:{err} addr:{addr}"
-                                        );
-                                    }
-                                } else {
-                                    event!(
-                                        Level::WARN,
-                                        "v6_recv_from_map.remove None, addr:{addr}"
-                                    );
-                                }
-                            }
-                        }
-                    });
-                }
-                Err(err) => {
-                    event!(Level::ERROR, "v6_client.recv_from failed, err:{err}");
-                }
-            }
+    /// Runs the receive loop on the current task, with `PingClientBuilder::spawn_recv_from(false)`.
+    pub async fn handle_v6_recv_from(&self) {
+        if let Some(v6_client) = self.v6_client.clone() {
+            v6_recv_from_loop(v6_client, self.v6_is_raw, self.v6_recv_from_map.clone()).await
         }
     }
 
@@ -262,51 +254,119 @@ where
         sequence_number: Option<u16>,
         payload: impl AsRef<[u8]>,
         timeout_dur: Duration,
-    ) -> Result<(Icmp, Duration), PingError> {
+    ) -> Result<ProbeOutcome, PingError> {
+        let payload = payload.as_ref();
+        match self
+            .ping_received(ip, identifier, sequence_number, payload, timeout_dur)
+            .await
+        {
+            Ok((received, rtt)) => Ok(ProbeOutcome::new(received, rtt, payload)),
+            Err(PingError::RecvTimedOut) => Ok(ProbeOutcome::Timeout),
+            Err(PingError::Send(err)) => Ok(ProbeOutcome::SendFailed(err)),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Continuous ping to `ip`, see [`PingSession`].
+    pub fn session(
+        &self,
+        ip: IpAddr,
+        config: PingSessionConfig,
+    ) -> Result<PingSession<C>, PingSessionConfigError> {
+        PingSession::new(self.clone(), ip, config)
+    }
+
+    /// Ping of every target, see [`Scan`].
+    pub fn scan<I>(&self, targets: I, config: ScanConfig) -> Result<Scan<C>, ScanConfigError>
+    where
+        I: IntoIterator<Item = IpAddr>,
+        I::IntoIter: Send + 'static,
+    {
+        Scan::new(self.clone(), targets, config)
+    }
+
+    /// Ping of every address of `targets`, see [`DiscoveryTargets::addrs`].
+    pub async fn discover(
+        &self,
+        targets: &DiscoveryTargets,
+        config: ScanConfig,
+    ) -> Result<DiscoveryReport, ScanConfigError> {
+        Ok(DiscoveryReport::collect(self.scan(targets.addrs(), config)?).await)
+    }
+
+    /// With the timeout, payload and identifier of the builder.
+    pub async fn ping_with_defaults(
+        &self,
+        ip: IpAddr,
+        sequence_number: Option<u16>,
+    ) -> Result<ProbeOutcome, PingError> {
+        self.ping(ip, None, sequence_number, &self.payload, self.timeout)
+            .await
+    }
+
+    async fn ping_received(
+        &self,
+        ip: IpAddr,
+        identifier: Option<u16>,
+        sequence_number: Option<u16>,
+        payload: &[u8],
+        timeout_dur: Duration,
+    ) -> Result<(Received, Duration), PingError> {
+        let _in_flight = InFlightGuard::new(&self.tasks).ok_or(PingError::Shutdown)?;
+        self.start_recv_from();
+
+        let _permit = match self.semaphore.as_ref() {
+            Some(semaphore) => Some(semaphore.acquire().await.expect("Never when not closed")),
+            None => None,
+        };
+
         //
         let echo_request = PayloadLengthDelimitedEchoRequest::new(
-            identifier.map(Into::into),
+            identifier
+                .or_else(|| self.identifier.identifier())
+                .map(Into::into),
             sequence_number.map(Into::into),
             payload,
         );
-        let echo_request_bytes = match ip {
-            IpAddr::V4(_) => echo_request.render_v4_packet_bytes(),
-            IpAddr::V6(_) => echo_request.render_v6_packet_bytes(),
-        };
+        let mut echo_request_bytes = self.buffer_pool.get();
+        match ip {
+            IpAddr::V4(_) => echo_request.render_v4_packet_bytes_into(&mut echo_request_bytes),
+            IpAddr::V6(_) => echo_request.render_v6_packet_bytes_into(&mut echo_request_bytes),
+        }
 
         //
-        let rx = match ip {
-            IpAddr::V4(_) => {
-                let (tx, rx) = mpsc::channel(1);
-
-                self.v4_recv_from_map
-                    .lock()
-                    .await
-                    .insert((ip, 0).into(), tx);
-
-                Ok(rx)
-            }
-            IpAddr::V6(_) => {
-                let (tx, rx) = mpsc::channel(1);
-
-                self.v6_recv_from_map
-                    .lock()
-                    .await
-                    .insert((ip, 0).into(), tx);
-
-                Err(rx)
-            }
+        let (client, recv_from_map, is_raw, send_lock) = match ip {
+            IpAddr::V4(_) => (
+                self.v4_client.as_ref().ok_or(PingError::NoV4Client)?,
+                &self.v4_recv_from_map,
+                self.v4_is_raw,
+                &self.v4_send_lock,
+            ),
+            IpAddr::V6(_) => (
+                self.v6_client.as_ref().ok_or(PingError::NoV6Client)?,
+                &self.v6_recv_from_map,
+                self.v6_is_raw,
+                &self.v6_send_lock,
+            ),
         };
 
-        //
-        let client = match ip {
-            IpAddr::V4(_) => self.v4_client.as_ref().ok_or(PingError::NoV4Client)?,
-            IpAddr::V6(_) => self.v6_client.as_ref().ok_or(PingError::NoV6Client)?,
+        // The send is under the timeout too, a full send buffer included.
+        let deadline =
+            Instant::now() + tokio::time::Duration::from_millis(timeout_dur.as_millis() as u64);
+        let Ok(send_guard) = tokio::time::timeout_at(deadline, send_lock.lock()).await else {
+            return Err(PingError::RecvTimedOut);
         };
 
+        // Unregistered when done, the future dropped included.
+        let identifier = echo_request.identifier.into_inner();
+        let sequence_number = echo_request.sequence_number.into_inner();
         let instant_begin = Instant::now();
+        let (mut pending, rx) = recv_from_map.register(
+            (ip, is_raw.then_some(identifier), sequence_number),
+            instant_begin,
+        );
 
-        {
+        let send_and_recv = async {
             let mut n_write = 0;
             while !echo_request_bytes[n_write..].is_empty() {
                 let n = client
@@ -319,49 +379,46 @@ where
                     return Err(PingError::Send(IoErrorKind::WriteZero.into()));
                 }
             }
-        }
+            drop(send_guard);
+
+            rx.await.unwrap_or(Err(PingError::ReplyIncomplete))
+        };
 
         //
-        match rx {
-            Ok(mut rx) => {
-                match tokio::time::timeout(
-                    tokio::time::Duration::from_millis(timeout_dur.as_millis() as u64),
-                    rx.recv(),
-                )
-                .await
-                {
-                    Ok(Some((Ok(icmpv4), instant_end))) => Ok((
-                        Icmp::V4(icmpv4),
-                        instant_end
-                            .checked_duration_since(instant_begin)
-                            .unwrap_or(instant_begin.elapsed()),
-                    )),
-                    Ok(Some((Err(err), _))) => Err(PingError::Icmpv4ParseError(err)),
-                    Ok(None) => Err(PingError::Unknown("rx.recv None".to_string())),
-                    Err(_) => Err(PingError::RecvTimedOut),
-                }
+        match tokio::time::timeout_at(deadline, send_and_recv).await {
+            Ok(Ok(received)) => {
+                let rtt = received
+                    .received_at
+                    .checked_duration_since(instant_begin)
+                    .unwrap_or(instant_begin.elapsed());
+                Ok((received, rtt))
             }
-            Err(mut rx) => {
-                match tokio::time::timeout(
-                    tokio::time::Duration::from_millis(timeout_dur.as_millis() as u64),
-                    rx.recv(),
-                )
-                .await
-                {
-                    Ok(Some((Ok(icmpv6), instant_end))) => Ok((
-                        Icmp::V6(icmpv6),
-                        instant_end
-                            .checked_duration_since(instant_begin)
-                            .unwrap_or(instant_begin.elapsed()),
-                    )),
-                    Ok(Some((Err(err), _))) => Err(PingError::Icmpv6ParseError(err)),
-                    Ok(None) => Err(PingError::Unknown("rx.recv None".to_string())),
-                    Err(_) => Err(PingError::RecvTimedOut),
-                }
+            Ok(Err(err)) => Err(err),
+            Err(_) => {
+                pending.on_timed_out();
+                Err(PingError::RecvTimedOut)
             }
         }
     }
 
+    /// The ICMP message itself, an echo reply with another payload is a [`PingError::PayloadMismatch`].
+    async fn ping_icmp(
+        &self,
+        ip: IpAddr,
+        identifier: Option<u16>,
+        sequence_number: Option<u16>,
+        payload: impl AsRef<[u8]>,
+        timeout_dur: Duration,
+    ) -> Result<(Icmp, Duration, Option<Ipv4Header>), PingError> {
+        let payload = payload.as_ref();
+        let (received, rtt) = self
+            .ping_received(ip, identifier, sequence_number, payload, timeout_dur)
+            .await?;
+        verify_echo_reply_payload(&received.icmp, payload)?;
+        Ok((received.icmp, rtt, received.ipv4_header))
+    }
+
+    /// The ICMP message itself, see [`Self::ping`] for a [`ProbeOutcome`].
     pub async fn ping_v4(
         &self,
         ip: Ipv4Addr,
@@ -370,15 +427,30 @@ where
         payload: impl AsRef<[u8]>,
         timeout_dur: Duration,
     ) -> Result<(Icmpv4, Duration), PingError> {
-        let (icmp, dur) = self
-            .ping(ip.into(), identifier, sequence_number, payload, timeout_dur)
-            .await?;
-        match icmp {
-            Icmp::V4(icmp) => Ok((icmp, dur)),
-            Icmp::V6(_) => Err(PingError::Unknown("unreachable".to_string())),
+        self.ping_v4_with_ipv4_header(ip, identifier, sequence_number, payload, timeout_dur)
+            .await
+            .map(|(icmpv4, dur, _)| (icmpv4, dur))
+    }
+
+    /// With the IP header of the reply in raw mode, e.g. for its options, see `ClientConfig::ip_options`.
+    pub async fn ping_v4_with_ipv4_header(
+        &self,
+        ip: Ipv4Addr,
+        identifier: Option<u16>,
+        sequence_number: Option<u16>,
+        payload: impl AsRef<[u8]>,
+        timeout_dur: Duration,
+    ) -> Result<(Icmpv4, Duration, Option<Ipv4Header>), PingError> {
+        match self
+            .ping_icmp(ip.into(), identifier, sequence_number, payload, timeout_dur)
+            .await?
+        {
+            (Icmp::V4(icmpv4), dur, ipv4_header) => Ok((icmpv4, dur, ipv4_header)),
+            (Icmp::V6(_), _, _) => unreachable!(),
         }
     }
 
+    /// The ICMP message itself, see [`Self::ping`] for a [`ProbeOutcome`].
     pub async fn ping_v6(
         &self,
         ip: Ipv6Addr,
@@ -387,33 +459,146 @@ where
         payload: impl AsRef<[u8]>,
         timeout_dur: Duration,
     ) -> Result<(Icmpv6, Duration), PingError> {
-        let (icmp, dur) = self
-            .ping(ip.into(), identifier, sequence_number, payload, timeout_dur)
-            .await?;
-        match icmp {
-            Icmp::V4(_) => Err(PingError::Unknown("unreachable".to_string())),
-            Icmp::V6(icmp) => Ok((icmp, dur)),
+        match self
+            .ping_icmp(ip.into(), identifier, sequence_number, payload, timeout_dur)
+            .await?
+        {
+            (Icmp::V6(icmpv6), dur, _) => Ok((icmpv6, dur)),
+            (Icmp::V4(_), _, _) => unreachable!(),
+        }
+    }
+}
+
+/// The receive tasks of a [`PingClient`] and its clones.
+#[derive(Debug, Default)]
+struct RecvFromTasks {
+    handles: StdMutex<Vec<JoinHandle<()>>>,
+    is_started: AtomicBool,
+    is_closing: AtomicBool,
+    in_flight: AtomicUsize,
+    idle: Notify,
+}
+
+impl Drop for RecvFromTasks {
+    fn drop(&mut self) {
+        for handle in self.handles.get_mut().expect("Never when lock").drain(..) {
+            handle.abort();
+        }
+    }
+}
+
+struct InFlightGuard<'a>(&'a RecvFromTasks);
+
+impl<'a> InFlightGuard<'a> {
+    fn new(tasks: &'a RecvFromTasks) -> Option<Self> {
+        tasks.in_flight.fetch_add(1, Ordering::SeqCst);
+        let guard = Self(tasks);
+        // Checked after counting, so `shutdown` cannot miss it.
+        if tasks.is_closing.load(Ordering::SeqCst) {
+            return None;
+        }
+        Some(guard)
+    }
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
         }
     }
 }
 
+/// Against the payload sent, to detect broken middleboxes and links flipping bits.
+pub(crate) fn verify_echo_reply_payload(icmp: &Icmp, payload: &[u8]) -> Result<(), PingError> {
+    match icmp {
+        Icmp::V4(Icmpv4::EchoReply(echo_reply)) | Icmp::V6(Icmpv6::EchoReply(echo_reply)) => {
+            echo_reply
+                .verify_payload(payload)
+                .map_err(PingError::PayloadMismatch)
+        }
+        _ => Ok(()),
+    }
+}
+
 //
 #[derive(Debug)]
 pub enum PingError {
     NoV4Client,
     NoV6Client,
+    /// Not in the [`MultiPingClient`](multi::MultiPingClient).
+    UnknownKey,
     Send(IoError),
+    Recv(IoError),
     Icmpv4ParseError(Icmpv4ParseError),
     Icmpv6ParseError(Icmpv6ParseError),
+    /// Not a complete ICMP message.
+    ReplyIncomplete,
     RecvTimedOut,
-    Unknown(String),
+    /// The echo reply came back with another payload.
+    PayloadMismatch(PayloadMismatch),
+    Shutdown,
 }
+
 impl core::fmt::Display for PingError {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "{self:?}")
+        match self {
+            Self::NoV4Client => write!(f, "no v4 client"),
+            Self::NoV6Client => write!(f, "no v6 client"),
+            Self::UnknownKey => write!(f, "unknown key"),
+            Self::Send(err) => write!(f, "send failed, err:{err}"),
+            Self::Recv(err) => write!(f, "recv failed, err:{err}"),
+            Self::Icmpv4ParseError(err) => write!(f, "icmpv4 parse failed, err:{err}"),
+            Self::Icmpv6ParseError(err) => write!(f, "icmpv6 parse failed, err:{err}"),
+            Self::ReplyIncomplete => write!(f, "reply incomplete"),
+            Self::RecvTimedOut => write!(f, "recv timed out"),
+            Self::PayloadMismatch(err) => write!(f, "payload mismatch, err:{err}"),
+            Self::Shutdown => write!(f, "shutdown"),
+        }
     }
 }
-impl std::error::Error for PingError {}
+
+impl std::error::Error for PingError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Send(err) | Self::Recv(err) => Some(err),
+            Self::Icmpv4ParseError(err) => Some(err),
+            Self::Icmpv6ParseError(err) => Some(err),
+            Self::PayloadMismatch(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+//
+pub mod builder;
+pub use builder::{IdentifierStrategy, PingClientBuildError, PingClientBuilder};
+use builder::{DEFAULT_PAYLOAD_SIZE, DEFAULT_TIMEOUT};
+pub mod connected;
+mod demux;
+pub mod discovery;
+pub use discovery::{
+    DiscoveryAddrs, DiscoveryReport, DiscoveryTargets, Ipv4Cidr, Ipv4CidrParseError,
+};
+pub mod multi;
+pub mod outcome;
+use demux::{v4_recv_from_loop, v6_recv_from_loop};
+pub use outcome::ProbeOutcome;
+use outcome::Received;
+mod pool;
+use pool::{BufferPool, BUFFER_POOL_MAX_LEN};
+pub mod registry;
+pub use registry::{ProbeStats, ReplyEvent, ReplyEventKind};
+use registry::{Registry, REPLY_EVENTS_CAPACITY};
+pub mod responder;
+pub mod scan;
+pub use scan::{Scan, ScanConfig, ScanConfigError, ScanResult, TargetStatus};
+pub mod session;
+pub use session::{
+    PingSession, PingSessionConfig, PingSessionConfigError, PingSessionEvent, PingSummary,
+};
+pub mod statistics;
+pub use statistics::PingStatistics;
 
 #[cfg(test)]
 mod tests {
@@ -424,13 +609,6 @@ mod tests {
         let client =
             PingClient::<icmp_client::impl_tokio::Client>::new(Some(ClientConfig::new()), None)?;
 
-        {
-            let client = client.clone();
-            tokio::spawn(async move {
-                client.handle_v4_recv_from().await;
-            });
-        }
-
         {
             match client
                 .ping(
@@ -442,13 +620,381 @@ mod tests {
                 )
                 .await
             {
-                Ok((icmp, dur)) => {
-                    println!("{dur:?} {icmp:?}");
+                Ok(outcome) => {
+                    println!("{outcome:?}");
+                    assert!(outcome.is_reply());
                 }
                 Err(err) => panic!("{err}"),
             }
         }
 
+        assert_eq!(client.stats().packets_sent, 1);
+        assert_eq!(client.stats().packets_received, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ping_with_dyn_client() -> Result<(), Box<dyn std::error::Error>> {
+        let v4_client: Box<dyn AsyncClient + Send + Sync> = Box::new(
+            icmp_client::impl_tokio::Client::new(&ClientConfig::new().bind("0.0.0.0:0".parse()?))?,
+        );
+        let client = PingClient::with_clients(Some((v4_client, false)), None);
+
+        let (icmpv4, _) = client
+            .ping_v4(
+                "127.0.0.1".parse().expect("Never"),
+                None,
+                Some(1),
+                vec![0; 32],
+                Duration::from_secs(2),
+            )
+            .await?;
+        assert!(matches!(icmpv4, Icmpv4::EchoReply(_)));
+        assert_eq!(client.stats().packets_received, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ping_with_record_route() -> Result<(), Box<dyn std::error::Error>> {
+        use icmp_client::config::SocketType;
+        use icmp_packet::ip_options::{render_record_route_option_bytes, Ipv4Option};
+
+        let client = match PingClient::<icmp_client::impl_tokio::Client>::new(
+            Some(
+                ClientConfig::new()
+                    .socket_type(SocketType::Raw)
+                    .ip_options(render_record_route_option_bytes()),
+            ),
+            None,
+        ) {
+            Ok(x) => x,
+            Err(AsyncClientWithConfigError::PermissionDenied(err)) => {
+                eprintln!("skipped, needs CAP_NET_RAW, err:{err}");
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let (icmpv4, _, ipv4_header) = client
+            .ping_v4_with_ipv4_header(
+                "127.0.0.1".parse().expect("Never"),
+                None,
+                Some(1),
+                vec![0; 32],
+                Duration::from_secs(2),
+            )
+            .await?;
+        assert!(matches!(icmpv4, Icmpv4::EchoReply(_)));
+        match ipv4_header
+            .expect("Never when raw")
+            .parse_options()
+            .as_slice()
+        {
+            [Ipv4Option::RecordRoute(route)] => {
+                assert!(route.contains(&"127.0.0.1".parse().expect("Never")));
+            }
+            x => panic!("{x:?}"),
+        }
+
+        // Also in the outcome, e.g. of a `PingSession`.
+        match client
+            .ping(
+                "127.0.0.1".parse().expect("Never"),
+                None,
+                Some(2),
+                vec![0; 32],
+                Duration::from_secs(2),
+            )
+            .await?
+        {
+            ProbeOutcome::Reply {
+                ip_options: Some(ip_options),
+                ..
+            } => assert!(matches!(
+                icmp_packet::ip_options::parse_ipv4_options(&ip_options).as_slice(),
+                [Ipv4Option::RecordRoute(_)]
+            )),
+            x => panic!("{x:?}"),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_shutdown() -> Result<(), Box<dyn std::error::Error>> {
+        use icmp_client::impl_sim::{Client, Link, Topology};
+
+        let network = Topology::new()
+            .host(Ipv4Addr::new(10, 0, 0, 1))
+            .host(Ipv4Addr::new(10, 0, 0, 2))
+            .link(
+                [10, 0, 0, 1],
+                [10, 0, 0, 2],
+                Link::new(Duration::from_millis(100)),
+            )
+            .build();
+        let client =
+            PingClient::<Client>::new(Some(ClientConfig::new().sim_network(network)), None)?;
+
+        let ip = Ipv4Addr::new(10, 0, 0, 2);
+        let handle = {
+            let client = client.clone();
+            tokio::spawn(async move {
+                client
+                    .ping_v4(ip, None, Some(1), b"1234", Duration::from_secs(1))
+                    .await
+            })
+        };
+        tokio::task::yield_now().await;
+
+        // The ping in flight completes.
+        client.shutdown().await;
+        assert!(client.is_shutdown());
+        assert!(matches!(handle.await?, Ok((Icmpv4::EchoReply(_), _))));
+        assert!(client.tasks.handles.lock().expect("Never").is_empty());
+
+        assert!(matches!(
+            client
+                .ping_v4(ip, None, Some(2), b"1234", Duration::from_secs(1))
+                .await,
+            Err(PingError::Shutdown)
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_probe_stats() -> Result<(), Box<dyn std::error::Error>> {
+        use icmp_client::impl_sim::{Client, Link, Topology};
+
+        let network = Topology::new()
+            .host(Ipv4Addr::new(10, 0, 0, 1))
+            .host(Ipv4Addr::new(10, 0, 0, 2))
+            .host(Ipv4Addr::new(10, 0, 0, 3))
+            .link(
+                [10, 0, 0, 1],
+                [10, 0, 0, 2],
+                Link::new(Duration::from_millis(100)),
+            )
+            .link(
+                [10, 0, 0, 1],
+                [10, 0, 0, 3],
+                Link::new(Duration::from_millis(100)).loss(1.0),
+            )
+            .build();
+        let client =
+            PingClient::<Client>::new(Some(ClientConfig::new().sim_network(network)), None)?;
+
+        // Timed out.
+        assert!(matches!(
+            client
+                .ping_v4(
+                    Ipv4Addr::new(10, 0, 0, 3),
+                    None,
+                    Some(1),
+                    b"1234",
+                    Duration::from_millis(500)
+                )
+                .await,
+            Err(PingError::RecvTimedOut)
+        ));
+        // Dropped before the reply, which is then orphaned.
+        assert!(tokio::time::timeout(
+            Duration::from_millis(50),
+            client.ping_v4(
+                Ipv4Addr::new(10, 0, 0, 2),
+                None,
+                Some(2),
+                b"1234",
+                Duration::from_secs(1)
+            )
+        )
+        .await
+        .is_err());
+        assert_eq!(client.probe_stats().pending, 0);
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        client
+            .ping_v4(
+                Ipv4Addr::new(10, 0, 0, 2),
+                None,
+                Some(3),
+                b"1234",
+                Duration::from_secs(1),
+            )
+            .await?;
+
+        assert_eq!(
+            client.probe_stats(),
+            ProbeStats {
+                pending: 0,
+                completed: 1,
+                timed_out: 1,
+                orphaned: 1,
+                ..Default::default()
+            }
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ping_outcome() -> Result<(), Box<dyn std::error::Error>> {
+        use icmp_client::impl_sim::{Client, Link, Topology};
+
+        let router = Ipv4Addr::new(10, 0, 1, 1);
+        let network = Topology::new()
+            .host(Ipv4Addr::new(10, 0, 0, 1))
+            .router(router)
+            .host(Ipv4Addr::new(10, 0, 2, 1))
+            .unreachable_host(Ipv4Addr::new(10, 0, 2, 2))
+            .link(
+                [10, 0, 0, 1],
+                [10, 0, 1, 1],
+                Link::new(Duration::from_millis(1)),
+            )
+            .link(
+                [10, 0, 1, 1],
+                [10, 0, 2, 1],
+                Link::new(Duration::from_millis(1)).mtu(30),
+            )
+            .link(
+                [10, 0, 1, 1],
+                [10, 0, 2, 2],
+                Link::new(Duration::from_millis(1)),
+            )
+            .build();
+        let client = PingClient::<Client>::new(
+            Some(ClientConfig::new().sim_network(network.clone())),
+            None,
+        )?;
+        let ping = |ip: Ipv4Addr, sequence_number| {
+            client.ping(
+                ip.into(),
+                None,
+                Some(sequence_number),
+                b"1234",
+                Duration::from_secs(1),
+            )
+        };
+
+        match ping(router, 1).await? {
+            ProbeOutcome::Reply { from, rtt, .. } => {
+                assert_eq!(from, IpAddr::from(router));
+                assert_eq!(rtt, Duration::from_millis(2));
+            }
+            x => panic!("{x:?}"),
+        }
+        match ping(Ipv4Addr::new(10, 0, 2, 1), 2).await? {
+            ProbeOutcome::PacketTooBig { from, mtu, .. } => {
+                assert_eq!(from, IpAddr::from(router));
+                assert_eq!(mtu, 30);
+            }
+            x => panic!("{x:?}"),
+        }
+        match ping(Ipv4Addr::new(10, 0, 2, 2), 3).await? {
+            ProbeOutcome::Unreachable { from, code, .. } => {
+                assert_eq!(from, IpAddr::from(router));
+                assert_eq!(code, 1);
+            }
+            x => panic!("{x:?}"),
+        }
+
+        let client =
+            PingClient::<Client>::new(Some(ClientConfig::new().sim_network(network).ttl(1)), None)?;
+        match client
+            .ping(
+                Ipv4Addr::new(10, 0, 2, 2).into(),
+                None,
+                Some(4),
+                b"1234",
+                Duration::from_secs(1),
+            )
+            .await?
+        {
+            ProbeOutcome::TimeExceeded { from, .. } => assert_eq!(from, IpAddr::from(router)),
+            x => panic!("{x:?}"),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reply_events() -> Result<(), Box<dyn std::error::Error>> {
+        use icmp_client::{
+            impl_sim::{Client, Link, Topology},
+            SocketType,
+        };
+
+        use crate::responder::{Config as ResponderConfig, Responder};
+
+        let network = Topology::new()
+            .host(Ipv4Addr::new(10, 0, 0, 1))
+            .silent_host(Ipv4Addr::new(10, 0, 0, 2))
+            .link(
+                [10, 0, 0, 1],
+                [10, 0, 0, 2],
+                Link::new(Duration::from_millis(1)),
+            )
+            .build();
+        let responder = Responder::new(
+            Client::new(
+                &network,
+                &ClientConfig::new()
+                    .socket_type(SocketType::Raw)
+                    .bind((Ipv4Addr::new(10, 0, 0, 2), 0).into()),
+            )?,
+            ResponderConfig::new()
+                .delay(Duration::from_millis(100))
+                .duplicate(1.0),
+        )?;
+        tokio::spawn(async move { responder.run().await });
+
+        let client =
+            PingClient::<Client>::new(Some(ClientConfig::new().sim_network(network)), None)?;
+        let mut events = client.reply_events();
+
+        let ip = Ipv4Addr::new(10, 0, 0, 2);
+        client
+            .ping_v4(ip, None, Some(1), b"1234", Duration::from_secs(1))
+            .await?;
+        assert!(matches!(
+            client
+                .ping_v4(ip, None, Some(2), b"1234", Duration::from_millis(50))
+                .await,
+            Err(PingError::RecvTimedOut)
+        ));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let event = events.recv().await?;
+        assert_eq!(
+            (event.kind, event.sequence_number, event.rtt),
+            (ReplyEventKind::Duplicate, 1, Duration::from_millis(102))
+        );
+        let event = events.recv().await?;
+        assert_eq!(
+            (event.kind, event.sequence_number, event.rtt),
+            (ReplyEventKind::Late, 2, Duration::from_millis(102))
+        );
+        let event = events.recv().await?;
+        assert_eq!(
+            (event.kind, event.sequence_number),
+            (ReplyEventKind::Duplicate, 2)
+        );
+
+        assert_eq!(
+            client.probe_stats(),
+            ProbeStats {
+                completed: 1,
+                timed_out: 1,
+                duplicates: 2,
+                late: 1,
+                ..Default::default()
+            }
+        );
+
         Ok(())
     }
 
@@ -479,13 +1025,6 @@ mod tests {
             }
         };
 
-        {
-            let client = client.clone();
-            tokio::spawn(async move {
-                client.handle_v6_recv_from().await;
-            });
-        }
-
         {
             match client
                 .ping(
@@ -497,8 +1036,9 @@ mod tests {
                 )
                 .await
             {
-                Ok((icmp, dur)) => {
-                    println!("{dur:?} {icmp:?}");
+                Ok(outcome) => {
+                    println!("{outcome:?}");
+                    assert!(outcome.is_reply());
                 }
                 Err(err) => panic!("{err}"),
             }
@@ -506,4 +1046,83 @@ mod tests {
 
         Ok(())
     }
+
+    // Full until `is_ready`, only the last task polling is woken, like tokio.
+    #[derive(Debug, Default)]
+    struct FullState {
+        is_ready: AtomicBool,
+        waker: StdMutex<Option<core::task::Waker>>,
+        sent: AtomicUsize,
+    }
+
+    #[derive(Debug, Default)]
+    struct FullClient(Arc<FullState>);
+
+    impl AsyncClient for FullClient {
+        fn with_config(_config: &ClientConfig) -> Result<Self, AsyncClientWithConfigError> {
+            Ok(Self::default())
+        }
+
+        fn poll_send_to(
+            &self,
+            cx: &mut core::task::Context<'_>,
+            buf: &[u8],
+            _addr: std::net::SocketAddr,
+        ) -> core::task::Poll<Result<usize, IoError>> {
+            if !self.0.is_ready.load(Ordering::SeqCst) {
+                *self.0.waker.lock().expect("Never when lock") = Some(cx.waker().to_owned());
+                return core::task::Poll::Pending;
+            }
+            self.0.sent.fetch_add(1, Ordering::SeqCst);
+            core::task::Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_recv_from(
+            &self,
+            _cx: &mut core::task::Context<'_>,
+            _buf: &mut icmp_client::ReadBuf<'_>,
+        ) -> core::task::Poll<Result<std::net::SocketAddr, IoError>> {
+            core::task::Poll::Pending
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ping_send_buffer_full() -> Result<(), Box<dyn std::error::Error>> {
+        let state = Arc::new(FullState::default());
+        let client = PingClient::with_clients(Some((FullClient(state.clone()), false)), None);
+
+        let ip = Ipv4Addr::new(127, 0, 0, 1).into();
+        let pings = (1..=3)
+            .map(|sequence_number| {
+                let client = client.clone();
+                tokio::spawn(async move {
+                    client
+                        .ping(
+                            ip,
+                            None,
+                            Some(sequence_number),
+                            b"1234",
+                            Duration::from_secs(1),
+                        )
+                        .await
+                })
+            })
+            .collect::<Vec<_>>();
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        state.is_ready.store(true, Ordering::SeqCst);
+        if let Some(waker) = state.waker.lock().expect("Never when lock").take() {
+            waker.wake();
+        }
+
+        // Every one sent at once, none left waiting for its timeout.
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        assert_eq!(state.sent.load(Ordering::SeqCst), 3);
+        for ping in pings {
+            let outcome = tokio::time::timeout(Duration::from_secs(10), ping).await???;
+            assert!(matches!(outcome, ProbeOutcome::Timeout), "{outcome:?}");
+        }
+
+        Ok(())
+    }
 }
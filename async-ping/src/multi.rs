@@ -0,0 +1,242 @@
+//! Several [`PingClient`]s behind one handle, e.g. one per network namespace.
+//!
+//! Rather than clients of several namespaces inside one `PingClient`: the same address is another
+//! host in each namespace, while a `PingClient` matches the replies on the address, identifier and
+//! sequence number only. Its receive tasks, in-flight limit and reply events would all have to be
+//! split per namespace, which is one `PingClient` per namespace, so the key picks the namespace.
+
+use core::{hash::Hash, time::Duration};
+use std::{collections::HashMap, net::IpAddr};
+
+use icmp_client::AsyncClient;
+#[cfg(target_os = "linux")]
+use icmp_client::{netns::Netns, AsyncClientWithConfigError, Config as ClientConfig};
+
+use crate::{PingClient, PingError, ProbeOutcome};
+
+//
+pub struct MultiPingClient<K, C>
+where
+    C: AsyncClient,
+{
+    clients: HashMap<K, PingClient<C>>,
+}
+
+impl<K, C> core::fmt::Debug for MultiPingClient<K, C>
+where
+    K: core::fmt::Debug,
+    C: AsyncClient,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MultiPingClient")
+            .field("keys", &self.clients.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl<K, C> Default for MultiPingClient<K, C>
+where
+    C: AsyncClient,
+{
+    fn default() -> Self {
+        Self {
+            clients: HashMap::new(),
+        }
+    }
+}
+
+impl<K, C> MultiPingClient<K, C>
+where
+    K: Eq + Hash,
+    C: AsyncClient + Send + Sync + 'static,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, key: K, client: PingClient<C>) -> Option<PingClient<C>> {
+        self.clients.insert(key, client)
+    }
+
+    /// A [`PingClient`] whose sockets are created inside `netns`.
+    #[cfg(target_os = "linux")]
+    pub fn insert_with_netns(
+        &mut self,
+        key: K,
+        netns: Netns,
+        v4_client_config: Option<ClientConfig>,
+        v6_client_config: Option<ClientConfig>,
+    ) -> Result<Option<PingClient<C>>, AsyncClientWithConfigError> {
+        let client = PingClient::new(
+            v4_client_config.map(|x| x.netns(netns.clone())),
+            v6_client_config.map(|x| x.netns(netns)),
+        )?;
+        Ok(self.insert(key, client))
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<PingClient<C>> {
+        self.clients.remove(key)
+    }
+
+    pub fn get(&self, key: &K) -> Option<&PingClient<C>> {
+        self.clients.get(key)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.clients.keys()
+    }
+
+    pub async fn ping(
+        &self,
+        key: &K,
+        ip: IpAddr,
+        identifier: Option<u16>,
+        sequence_number: Option<u16>,
+        payload: impl AsRef<[u8]>,
+        timeout_dur: Duration,
+    ) -> Result<ProbeOutcome, PingError> {
+        self.clients
+            .get(key)
+            .ok_or(PingError::UnknownKey)?
+            .ping(ip, identifier, sequence_number, payload, timeout_dur)
+            .await
+    }
+
+    /// Spawns the receive tasks of every client, else done by their first ping.
+    pub fn start_recv_from(&self) {
+        for client in self.clients.values() {
+            client.start_recv_from();
+        }
+    }
+
+    /// See [`PingClient::shutdown`].
+    pub async fn shutdown(&self) {
+        for client in self.clients.values() {
+            client.shutdown().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    use icmp_client::{
+        impl_sim::{Client, Link, Topology},
+        Config as ClientConfig,
+    };
+
+    #[tokio::test(start_paused = true)]
+    async fn test_multi_ping_client() -> Result<(), Box<dyn std::error::Error>> {
+        // The same addresses in two networks, like two namespaces.
+        let mut client = MultiPingClient::<&str, Client>::new();
+        for (key, latency) in [("a", 1), ("b", 5)] {
+            let network = Topology::new()
+                .host(Ipv4Addr::new(10, 0, 0, 1))
+                .host(Ipv4Addr::new(10, 0, 0, 2))
+                .link(
+                    [10, 0, 0, 1],
+                    [10, 0, 0, 2],
+                    Link::new(Duration::from_millis(latency)),
+                )
+                .build();
+            client.insert(
+                key,
+                PingClient::new(Some(ClientConfig::new().sim_network(network)), None)?,
+            );
+        }
+        client.start_recv_from();
+
+        let ip = Ipv4Addr::new(10, 0, 0, 2).into();
+        for (key, dur) in [("a", 2), ("b", 10)] {
+            match client
+                .ping(&key, ip, None, Some(1), b"1234", Duration::from_secs(1))
+                .await?
+            {
+                ProbeOutcome::Reply { rtt, .. } => {
+                    assert_eq!(rtt, Duration::from_millis(dur));
+                }
+                x => panic!("{x:?}"),
+            }
+        }
+
+        assert!(matches!(
+            client
+                .ping(&"c", ip, None, Some(1), b"1234", Duration::from_secs(1))
+                .await,
+            Err(PingError::UnknownKey)
+        ));
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_insert_with_netns() -> Result<(), Box<dyn std::error::Error>> {
+        use std::{io::Error as IoError, sync::mpsc};
+
+        // A thread in a new namespace, lo down there.
+        let (tx, rx) = mpsc::channel();
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+        let handle = std::thread::spawn(move || {
+            let ret = if unsafe { libc::unshare(libc::CLONE_NEWNET) } < 0 {
+                Err(IoError::last_os_error())
+            } else {
+                Ok(Netns::Path(
+                    format!("/proc/{}/task/{}/ns/net", std::process::id(), unsafe {
+                        libc::gettid()
+                    })
+                    .into(),
+                ))
+            };
+            tx.send(ret).expect("Never when send");
+            let _ = done_rx.recv();
+        });
+        let netns = match rx.recv()? {
+            Ok(x) => x,
+            Err(err) if err.raw_os_error() == Some(libc::EPERM) => {
+                eprintln!("skipped, needs CAP_SYS_ADMIN, err:{err}");
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut client = MultiPingClient::<&str, icmp_client::impl_tokio::Client>::new();
+        client.insert("host", PingClient::new(Some(ClientConfig::new()), None)?);
+        // net.ipv4.ping_group_range is "1 0" in a new namespace.
+        match client.insert_with_netns(
+            "netns",
+            netns,
+            Some(ClientConfig::new().socket_type(icmp_client::SocketType::Raw)),
+            None,
+        ) {
+            Ok(_) => {}
+            Err(AsyncClientWithConfigError::PermissionDenied(err)) => {
+                eprintln!("skipped, needs CAP_NET_RAW, err:{err}");
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        let ip = Ipv4Addr::new(127, 0, 0, 1).into();
+        assert!(matches!(
+            client
+                .ping(&"host", ip, None, Some(1), b"1234", Duration::from_secs(1))
+                .await?,
+            ProbeOutcome::Reply { .. }
+        ));
+        assert!(matches!(
+            client
+                .ping(&"netns", ip, None, Some(1), b"1234", Duration::from_secs(1))
+                .await?,
+            ProbeOutcome::SendFailed(_)
+        ));
+
+        drop(done_tx);
+        handle.join().expect("Never");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,311 @@
+//! What became of a probe, see [`PingClient::ping`](crate::PingClient::ping).
+
+use core::time::Duration;
+use std::{io::Error as IoError, net::IpAddr};
+
+use icmp_packet::{
+    ip::Ipv4Header,
+    pnet_packet::{icmp::IcmpTypes, icmpv6::Icmpv6Types},
+    Icmp, Icmpv4, Icmpv6, PayloadMismatch,
+};
+use tokio::time::Instant;
+
+//
+/// ICMPv4 Destination Unreachable code of Fragmentation Needed.
+const ICMPV4_CODE_FRAGMENTATION_NEEDED: u8 = 4;
+
+//
+#[derive(Debug)]
+pub enum ProbeOutcome {
+    /// Echo reply carrying the payload sent.
+    Reply {
+        from: IpAddr,
+        rtt: Duration,
+        /// Of the reply, with raw IPv4 sockets only.
+        ttl: Option<u8>,
+        /// Of the reply when any, with raw IPv4 sockets only, see `ClientConfig::ip_options`.
+        ip_options: Option<Vec<u8>>,
+        /// Of the ICMP message.
+        size: usize,
+    },
+    /// Echo reply carrying another payload, e.g. through a broken middlebox.
+    PayloadMismatch {
+        from: IpAddr,
+        rtt: Duration,
+        mismatch: PayloadMismatch,
+    },
+    /// Destination Unreachable, `code` of ICMPv4 or ICMPv6 as per the family.
+    Unreachable {
+        from: IpAddr,
+        rtt: Duration,
+        code: u8,
+    },
+    TimeExceeded {
+        from: IpAddr,
+        rtt: Duration,
+    },
+    /// Packet Too Big of ICMPv6 or Fragmentation Needed of ICMPv4, `mtu` of the next hop.
+    PacketTooBig {
+        from: IpAddr,
+        rtt: Duration,
+        mtu: u32,
+    },
+    /// Other ICMP errors, e.g. Parameter Problem.
+    Other {
+        from: IpAddr,
+        rtt: Duration,
+        icmp_type: u8,
+        code: u8,
+    },
+    Timeout,
+    SendFailed(IoError),
+}
+
+impl ProbeOutcome {
+    pub(crate) fn new(received: Received, rtt: Duration, payload: &[u8]) -> Self {
+        let from = received.from;
+        match received.icmp {
+            Icmp::V4(Icmpv4::EchoReply(echo_reply)) | Icmp::V6(Icmpv6::EchoReply(echo_reply)) => {
+                match echo_reply.verify_payload(payload) {
+                    Ok(()) => Self::Reply {
+                        from,
+                        rtt,
+                        ttl: received.ipv4_header.as_ref().map(|x| x.ttl),
+                        ip_options: received
+                            .ipv4_header
+                            .map(|x| x.options)
+                            .filter(|x| !x.is_empty()),
+                        size: received.size,
+                    },
+                    Err(mismatch) => Self::PayloadMismatch {
+                        from,
+                        rtt,
+                        mismatch,
+                    },
+                }
+            }
+            Icmp::V4(Icmpv4::Other(icmp_type, code, rest)) => match icmp_type {
+                IcmpTypes::DestinationUnreachable if code.0 == ICMPV4_CODE_FRAGMENTATION_NEEDED => {
+                    // Next-hop MTU in the low 16 bits of the rest of the header, RFC 1191.
+                    let mtu = u16::from_be_bytes([
+                        rest.get(2).copied().unwrap_or_default(),
+                        rest.get(3).copied().unwrap_or_default(),
+                    ]);
+                    Self::PacketTooBig {
+                        from,
+                        rtt,
+                        mtu: mtu.into(),
+                    }
+                }
+                IcmpTypes::DestinationUnreachable => Self::Unreachable {
+                    from,
+                    rtt,
+                    code: code.0,
+                },
+                IcmpTypes::TimeExceeded => Self::TimeExceeded { from, rtt },
+                _ => Self::Other {
+                    from,
+                    rtt,
+                    icmp_type: icmp_type.0,
+                    code: code.0,
+                },
+            },
+            Icmp::V6(Icmpv6::Other(icmp_type, code, rest)) => match icmp_type {
+                Icmpv6Types::DestinationUnreachable => Self::Unreachable {
+                    from,
+                    rtt,
+                    code: code.0,
+                },
+                Icmpv6Types::PacketTooBig => {
+                    let mtu = rest
+                        .get(..4)
+                        .map(|x| u32::from_be_bytes([x[0], x[1], x[2], x[3]]))
+                        .unwrap_or_default();
+                    Self::PacketTooBig { from, rtt, mtu }
+                }
+                Icmpv6Types::TimeExceeded => Self::TimeExceeded { from, rtt },
+                _ => Self::Other {
+                    from,
+                    rtt,
+                    icmp_type: icmp_type.0,
+                    code: code.0,
+                },
+            },
+        }
+    }
+
+    /// Echo reply carrying the payload sent.
+    pub fn is_reply(&self) -> bool {
+        matches!(self, Self::Reply { .. })
+    }
+
+    /// Of the echo reply or the ICMP error.
+    pub fn rtt(&self) -> Option<Duration> {
+        match self {
+            Self::Reply { rtt, .. }
+            | Self::PayloadMismatch { rtt, .. }
+            | Self::Unreachable { rtt, .. }
+            | Self::TimeExceeded { rtt, .. }
+            | Self::PacketTooBig { rtt, .. }
+            | Self::Other { rtt, .. } => Some(*rtt),
+            Self::Timeout | Self::SendFailed(_) => None,
+        }
+    }
+
+    /// Source of the echo reply or the ICMP error, a router for the latter typically.
+    pub fn addr(&self) -> Option<IpAddr> {
+        match self {
+            Self::Reply { from, .. }
+            | Self::PayloadMismatch { from, .. }
+            | Self::Unreachable { from, .. }
+            | Self::TimeExceeded { from, .. }
+            | Self::PacketTooBig { from, .. }
+            | Self::Other { from, .. } => Some(*from),
+            Self::Timeout | Self::SendFailed(_) => None,
+        }
+    }
+}
+
+//
+/// A message as handed to its probe.
+#[derive(Debug)]
+pub(crate) struct Received {
+    pub(crate) icmp: Icmp,
+    pub(crate) from: IpAddr,
+    pub(crate) size: usize,
+    pub(crate) received_at: Instant,
+    /// With raw IPv4 sockets only.
+    pub(crate) ipv4_header: Option<Ipv4Header>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    use icmp_packet::{
+        icmpv4, icmpv6, ip::render_ipv4_packet_bytes, pnet_packet::icmp::IcmpCode,
+        PayloadLengthDelimitedEchoRequest,
+    };
+
+    #[test]
+    fn test_new() {
+        let local = Ipv4Addr::new(10, 0, 0, 1);
+        let remote = Ipv4Addr::new(10, 0, 0, 2);
+        let router = Ipv4Addr::new(10, 0, 1, 1);
+        let rtt = Duration::from_millis(1);
+        let echo_request =
+            PayloadLengthDelimitedEchoRequest::new(Some(1.into()), Some(2.into()), b"1234");
+        let echo_request_bytes = echo_request.render_v4_packet_bytes();
+        let quoted_bytes = render_ipv4_packet_bytes(local, remote, 1, &echo_request_bytes);
+
+        let new = |bytes: &[u8], from: Ipv4Addr| {
+            let icmp = Icmpv4::parse_from_packet_bytes(bytes)
+                .expect("Never")
+                .expect("Never");
+            ProbeOutcome::new(
+                Received {
+                    icmp: Icmp::V4(icmp),
+                    from: from.into(),
+                    size: bytes.len(),
+                    received_at: Instant::now(),
+                    ipv4_header: None,
+                },
+                rtt,
+                b"1234",
+            )
+        };
+
+        let echo_reply_bytes =
+            icmpv4::render_echo_reply_packet_bytes(&echo_request_bytes).expect("Never");
+        match new(&echo_reply_bytes, remote) {
+            ProbeOutcome::Reply {
+                from,
+                rtt: x,
+                ttl,
+                ip_options,
+                size,
+            } => {
+                assert_eq!(from, IpAddr::from(remote));
+                assert_eq!(x, rtt);
+                assert_eq!(ttl, None);
+                assert_eq!(ip_options, None);
+                assert_eq!(size, echo_request_bytes.len());
+            }
+            x => panic!("{x:?}"),
+        }
+
+        match new(
+            &icmpv4::render_error_packet_bytes(
+                IcmpTypes::DestinationUnreachable,
+                IcmpCode(1),
+                0,
+                &quoted_bytes,
+            ),
+            router,
+        ) {
+            ProbeOutcome::Unreachable { from, code, .. } => {
+                assert_eq!(from, IpAddr::from(router));
+                assert_eq!(code, 1);
+            }
+            x => panic!("{x:?}"),
+        }
+
+        match new(
+            &icmpv4::render_error_packet_bytes(
+                IcmpTypes::DestinationUnreachable,
+                IcmpCode(ICMPV4_CODE_FRAGMENTATION_NEEDED),
+                1400,
+                &quoted_bytes,
+            ),
+            router,
+        ) {
+            ProbeOutcome::PacketTooBig { mtu, .. } => assert_eq!(mtu, 1400),
+            x => panic!("{x:?}"),
+        }
+
+        let outcome = new(
+            &icmpv4::render_error_packet_bytes(
+                IcmpTypes::TimeExceeded,
+                IcmpCode(0),
+                0,
+                &quoted_bytes,
+            ),
+            router,
+        );
+        assert!(matches!(outcome, ProbeOutcome::TimeExceeded { .. }));
+        assert!(!outcome.is_reply());
+        assert_eq!(outcome.rtt(), Some(rtt));
+        assert_eq!(outcome.addr(), Some(router.into()));
+
+        // ICMPv6
+        let echo_request_bytes = echo_request.render_v6_packet_bytes();
+        let icmp = Icmpv6::parse_from_packet_bytes(&icmpv6::render_error_packet_bytes(
+            Icmpv6Types::PacketTooBig,
+            icmp_packet::pnet_packet::icmpv6::Icmpv6Code(0),
+            1280,
+            &echo_request_bytes,
+        ))
+        .expect("Never")
+        .expect("Never");
+        match ProbeOutcome::new(
+            Received {
+                icmp: Icmp::V6(icmp),
+                from: "::1".parse().expect("Never"),
+                size: 0,
+                received_at: Instant::now(),
+                ipv4_header: None,
+            },
+            rtt,
+            b"1234",
+        ) {
+            ProbeOutcome::PacketTooBig { mtu, .. } => assert_eq!(mtu, 1280),
+            x => panic!("{x:?}"),
+        }
+
+        let outcome = ProbeOutcome::Timeout;
+        assert_eq!(outcome.rtt(), None);
+        assert_eq!(outcome.addr(), None);
+    }
+}
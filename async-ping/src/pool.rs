@@ -0,0 +1,92 @@
+//! Buffers reused across pings.
+
+use core::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+//
+/// Enough for the pings in flight of a sweep, beyond that the buffers are freed.
+pub(crate) const BUFFER_POOL_MAX_LEN: usize = 1024;
+
+//
+#[derive(Debug)]
+pub(crate) struct BufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+    max_len: usize,
+}
+
+impl BufferPool {
+    /// Keeps `max_len` buffers at most, the others are freed.
+    pub(crate) fn new(max_len: usize) -> Self {
+        Self {
+            buffers: Mutex::new(vec![]),
+            max_len,
+        }
+    }
+
+    pub(crate) fn get(&self) -> PooledBuffer<'_> {
+        let buf = self
+            .buffers
+            .lock()
+            .expect("Never when lock")
+            .pop()
+            .unwrap_or_default();
+        PooledBuffer { pool: self, buf }
+    }
+}
+
+//
+#[derive(Debug)]
+pub(crate) struct PooledBuffer<'a> {
+    pool: &'a BufferPool,
+    buf: Vec<u8>,
+}
+
+impl Deref for PooledBuffer<'_> {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuffer<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buf
+    }
+}
+
+impl Drop for PooledBuffer<'_> {
+    fn drop(&mut self) {
+        let mut buffers = self.pool.buffers.lock().expect("Never when lock");
+        if buffers.len() < self.pool.max_len {
+            let mut buf = core::mem::take(&mut self.buf);
+            buf.clear();
+            buffers.push(buf);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffer_pool() {
+        let pool = BufferPool::new(1);
+
+        let ptr = {
+            let mut buf = pool.get();
+            buf.extend_from_slice(b"1234");
+            buf.as_ptr()
+        };
+        {
+            let buf = pool.get();
+            assert!(buf.is_empty());
+            assert_eq!(buf.as_ptr(), ptr);
+
+            // Over `max_len`, freed.
+            let _ = pool.get();
+        }
+        assert_eq!(pool.buffers.lock().expect("Never").len(), 1);
+    }
+}
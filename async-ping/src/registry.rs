@@ -0,0 +1,435 @@
+//! Pending probes, waiting for their reply.
+//!
+//! Sharded, so that the receive task and the pings rarely wait on the same lock.
+
+use core::time::Duration;
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    hash::{BuildHasher as _, Hash},
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use tokio::{
+    sync::{
+        broadcast,
+        oneshot::{self, Receiver, Sender},
+    },
+    time::Instant,
+};
+
+//
+/// Destination, identifier unless rewritten by the kernel (DGRAM sockets), sequence number.
+pub(crate) type Key = (IpAddr, Option<u16>, u16);
+
+const SHARDS: usize = 16;
+
+/// How long answered and timed out probes are remembered, for duplicates and late replies.
+const RECENT_TTL: Duration = Duration::from_secs(60);
+
+pub(crate) const REPLY_EVENTS_CAPACITY: usize = 1024;
+
+//
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProbeStats {
+    /// Waiting for their reply now.
+    pub pending: u64,
+    /// Replied, ICMP errors included.
+    pub completed: u64,
+    pub timed_out: u64,
+    /// Replies without a pending probe, e.g. arriving after the probe was dropped.
+    pub orphaned: u64,
+    /// Replies to an already answered probe.
+    pub duplicates: u64,
+    /// Replies arriving after the timeout of their probe.
+    pub late: u64,
+    /// Replies arriving after the reply to a later probe to the same destination.
+    pub reordered: u64,
+}
+
+impl core::ops::Add for ProbeStats {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self {
+            pending: self.pending + rhs.pending,
+            completed: self.completed + rhs.completed,
+            timed_out: self.timed_out + rhs.timed_out,
+            orphaned: self.orphaned + rhs.orphaned,
+            duplicates: self.duplicates + rhs.duplicates,
+            late: self.late + rhs.late,
+            reordered: self.reordered + rhs.reordered,
+        }
+    }
+}
+
+//
+/// A reply out of the ordinary, see [`PingClient::reply_events`](crate::PingClient::reply_events).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplyEvent {
+    pub kind: ReplyEventKind,
+    pub ip: IpAddr,
+    /// `None` when rewritten by the kernel (DGRAM sockets).
+    pub identifier: Option<u16>,
+    pub sequence_number: u16,
+    /// From the echo request to this reply, beyond the timeout for late ones.
+    pub rtt: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplyEventKind {
+    /// Of an already answered probe, `DUP!` of ping(8).
+    Duplicate,
+    /// After the timeout of its probe.
+    Late,
+    /// After the reply to a later probe to the same destination, the probe still completes.
+    Reordered,
+}
+
+//
+/// Remembered for `RECENT_TTL` at least and `2 * RECENT_TTL` at most.
+#[derive(Debug)]
+struct Recent<K, V> {
+    current: HashMap<K, V>,
+    previous: HashMap<K, V>,
+    rotated_at: Instant,
+}
+
+impl<K, V> Recent<K, V>
+where
+    K: Eq + Hash,
+{
+    fn new(now: Instant) -> Self {
+        Self {
+            current: HashMap::new(),
+            previous: HashMap::new(),
+            rotated_at: now,
+        }
+    }
+
+    fn rotate(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.rotated_at);
+        if elapsed < RECENT_TTL {
+            return;
+        }
+        self.previous = core::mem::take(&mut self.current);
+        if elapsed >= RECENT_TTL * 2 {
+            self.previous.clear();
+        }
+        self.rotated_at = now;
+    }
+
+    fn insert(&mut self, key: K, value: V, now: Instant) {
+        self.rotate(now);
+        self.previous.remove(&key);
+        self.current.insert(key, value);
+    }
+
+    fn get(&mut self, key: &K, now: Instant) -> Option<&V> {
+        self.rotate(now);
+        self.current.get(key).or_else(|| self.previous.get(key))
+    }
+
+    fn remove(&mut self, key: &K) {
+        self.current.remove(key);
+        self.previous.remove(key);
+    }
+}
+
+//
+#[derive(Debug, Clone, Copy)]
+enum Finished {
+    Answered,
+    TimedOut,
+}
+
+#[derive(Debug)]
+struct Shard<T> {
+    // Token, sent at, sender.
+    pending: HashMap<Key, (u64, Instant, Sender<T>)>,
+    recent: Recent<Key, (Instant, Finished)>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Registry<T> {
+    shards: Box<[Mutex<Shard<T>>]>,
+    hasher: RandomState,
+    // Sent at of the latest answered probe per destination, taken by the receive task only.
+    answered: Mutex<Recent<(IpAddr, Option<u16>), Instant>>,
+    events: broadcast::Sender<ReplyEvent>,
+    // Tells a probe from the later one to the same key.
+    next_token: AtomicU64,
+    completed: AtomicU64,
+    timed_out: AtomicU64,
+    orphaned: AtomicU64,
+    duplicates: AtomicU64,
+    late: AtomicU64,
+    reordered: AtomicU64,
+}
+
+impl<T> Registry<T> {
+    pub(crate) fn new(events: broadcast::Sender<ReplyEvent>) -> Self {
+        let now = Instant::now();
+        Self {
+            shards: (0..SHARDS)
+                .map(|_| {
+                    Mutex::new(Shard {
+                        pending: HashMap::new(),
+                        recent: Recent::new(now),
+                    })
+                })
+                .collect(),
+            hasher: RandomState::new(),
+            answered: Mutex::new(Recent::new(now)),
+            events,
+            next_token: AtomicU64::new(0),
+            completed: AtomicU64::new(0),
+            timed_out: AtomicU64::new(0),
+            orphaned: AtomicU64::new(0),
+            duplicates: AtomicU64::new(0),
+            late: AtomicU64::new(0),
+            reordered: AtomicU64::new(0),
+        }
+    }
+
+    fn shard(&self, key: &Key) -> &Mutex<Shard<T>> {
+        &self.shards[self.hasher.hash_one(key) as usize % SHARDS]
+    }
+
+    /// The entry is removed when the returned guard is dropped,
+    /// i.e. on timeout or when the ping future is dropped.
+    pub(crate) fn register(&self, key: Key, sent_at: Instant) -> (Pending<'_, T>, Receiver<T>) {
+        let (tx, rx) = oneshot::channel();
+        let token = self.next_token.fetch_add(1, Ordering::Relaxed);
+        {
+            let mut shard = self.shard(&key).lock().expect("Never when lock");
+            // The sequence number wrapped around, replies are for this probe now.
+            shard.recent.remove(&key);
+            shard.pending.insert(key, (token, sent_at, tx));
+        }
+        (
+            Pending {
+                registry: self,
+                key,
+                token,
+                sent_at,
+                is_timed_out: false,
+            },
+            rx,
+        )
+    }
+
+    /// The sender of the pending probe of `key`, counted as completed.
+    ///
+    /// Else the reply is counted and reported as duplicate or late, or counted as orphaned.
+    pub(crate) fn take(&self, key: &Key, received_at: Instant) -> Option<Sender<T>> {
+        let (ret, kind, sent_at) = {
+            let mut shard = self.shard(key).lock().expect("Never when lock");
+            match shard.pending.remove(key) {
+                Some((_, sent_at, tx)) => {
+                    shard
+                        .recent
+                        .insert(*key, (sent_at, Finished::Answered), received_at);
+                    (Some(tx), None, sent_at)
+                }
+                None => match shard.recent.get(key, received_at) {
+                    Some((sent_at, Finished::Answered)) => {
+                        (None, Some(ReplyEventKind::Duplicate), *sent_at)
+                    }
+                    Some((sent_at, Finished::TimedOut)) => {
+                        let sent_at = *sent_at;
+                        // Later copies are duplicates.
+                        shard
+                            .recent
+                            .insert(*key, (sent_at, Finished::Answered), received_at);
+                        (None, Some(ReplyEventKind::Late), sent_at)
+                    }
+                    None => {
+                        self.orphaned.fetch_add(1, Ordering::Relaxed);
+                        return None;
+                    }
+                },
+            }
+        };
+
+        let kind = match kind {
+            Some(kind) => Some(kind),
+            None => {
+                self.completed.fetch_add(1, Ordering::Relaxed);
+
+                let mut answered = self.answered.lock().expect("Never when lock");
+                let destination = (key.0, key.1);
+                match answered.get(&destination, received_at) {
+                    Some(x) if *x > sent_at => Some(ReplyEventKind::Reordered),
+                    _ => {
+                        answered.insert(destination, sent_at, received_at);
+                        None
+                    }
+                }
+            }
+        };
+
+        if let Some(kind) = kind {
+            match kind {
+                ReplyEventKind::Duplicate => &self.duplicates,
+                ReplyEventKind::Late => &self.late,
+                ReplyEventKind::Reordered => &self.reordered,
+            }
+            .fetch_add(1, Ordering::Relaxed);
+
+            // Err without receivers.
+            let _ = self.events.send(ReplyEvent {
+                kind,
+                ip: key.0,
+                identifier: key.1,
+                sequence_number: key.2,
+                rtt: received_at.saturating_duration_since(sent_at),
+            });
+        }
+
+        ret
+    }
+
+    pub(crate) fn stats(&self) -> ProbeStats {
+        ProbeStats {
+            pending: self
+                .shards
+                .iter()
+                .map(|x| x.lock().expect("Never when lock").pending.len() as u64)
+                .sum(),
+            completed: self.completed.load(Ordering::Relaxed),
+            timed_out: self.timed_out.load(Ordering::Relaxed),
+            orphaned: self.orphaned.load(Ordering::Relaxed),
+            duplicates: self.duplicates.load(Ordering::Relaxed),
+            late: self.late.load(Ordering::Relaxed),
+            reordered: self.reordered.load(Ordering::Relaxed),
+        }
+    }
+}
+
+//
+pub(crate) struct Pending<'a, T> {
+    registry: &'a Registry<T>,
+    key: Key,
+    token: u64,
+    sent_at: Instant,
+    is_timed_out: bool,
+}
+
+impl<T> Pending<'_, T> {
+    /// Its reply, if any, is then reported as late.
+    pub(crate) fn on_timed_out(&mut self) {
+        self.registry.timed_out.fetch_add(1, Ordering::Relaxed);
+        self.is_timed_out = true;
+    }
+}
+
+impl<T> Drop for Pending<'_, T> {
+    fn drop(&mut self) {
+        let mut shard = self
+            .registry
+            .shard(&self.key)
+            .lock()
+            .expect("Never when lock");
+        // A later probe with the same key may have taken the entry over.
+        if shard.pending.get(&self.key).map(|(x, _, _)| *x) == Some(self.token) {
+            shard.pending.remove(&self.key);
+            if self.is_timed_out {
+                shard
+                    .recent
+                    .insert(self.key, (self.sent_at, Finished::TimedOut), Instant::now());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_registry() {
+        let (events, mut events_rx) = broadcast::channel(16);
+        let registry = Registry::<u8>::new(events);
+        let ip = "127.0.0.1".parse().expect("Never");
+        let key = (ip, None, 1);
+        let now = Instant::now();
+
+        {
+            let (_pending, mut rx) = registry.register(key, now);
+            assert_eq!(registry.stats().pending, 1);
+            assert!(registry.take(&key, now).expect("pending").send(1).is_ok());
+            assert_eq!(rx.try_recv().ok(), Some(1));
+        }
+        assert!(registry.take(&key, now).is_none());
+
+        {
+            let (pending, _rx) = registry.register(key, now);
+            // Taken over by a later probe, which the first one leaves alone.
+            let (mut pending_2, _rx_2) = registry.register(key, now);
+            drop(pending);
+            assert_eq!(registry.stats().pending, 1);
+            pending_2.on_timed_out();
+        }
+        let late_at = now + Duration::from_secs(3);
+        assert!(registry.take(&key, late_at).is_none());
+        assert!(registry.take(&key, late_at).is_none());
+
+        // Other keys
+        let (_pending, _rx) = registry.register((ip, Some(1), 1), now);
+        assert!(registry.take(&(ip, None, 2), now).is_none());
+
+        // Reordered, the reply to 4 first.
+        let (_pending_3, _rx_3) = registry.register((ip, None, 3), now);
+        let (_pending_4, _rx_4) = registry.register((ip, None, 4), now + Duration::from_secs(1));
+        let received_at = now + Duration::from_secs(2);
+        assert!(registry.take(&(ip, None, 4), received_at).is_some());
+        assert!(registry.take(&(ip, None, 3), received_at).is_some());
+
+        // Forgotten after 2 * RECENT_TTL.
+        assert!(registry
+            .take(&(ip, None, 4), now + RECENT_TTL * 3)
+            .is_none());
+
+        assert_eq!(
+            registry.stats(),
+            ProbeStats {
+                pending: 1,
+                completed: 3,
+                timed_out: 1,
+                orphaned: 2,
+                duplicates: 2,
+                late: 1,
+                reordered: 1,
+            }
+        );
+
+        let event = |kind, sequence_number, rtt| ReplyEvent {
+            kind,
+            ip,
+            identifier: None,
+            sequence_number,
+            rtt,
+        };
+        assert_eq!(
+            events_rx.try_recv().ok(),
+            Some(event(ReplyEventKind::Duplicate, 1, Duration::ZERO))
+        );
+        assert_eq!(
+            events_rx.try_recv().ok(),
+            Some(event(ReplyEventKind::Late, 1, Duration::from_secs(3)))
+        );
+        assert_eq!(
+            events_rx.try_recv().ok(),
+            Some(event(ReplyEventKind::Duplicate, 1, Duration::from_secs(3)))
+        );
+        assert_eq!(
+            events_rx.try_recv().ok(),
+            Some(event(ReplyEventKind::Reordered, 3, Duration::from_secs(2)))
+        );
+        assert!(events_rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,601 @@
+//! Echo responder, the other side of [`PingClient`](crate::PingClient).
+//!
+//! Listens with any [`AsyncClient`], typically a `SocketType::Raw` one, and answers echo requests
+//! with configurable delay, loss, duplication, payload mangling and rate limit.
+//! When listening on a raw socket, the kernel answers too, unless disabled with
+//! `net.ipv4.icmp_echo_ignore_all` / `net.ipv6.icmp.echo_ignore_all`.
+
+use core::time::Duration;
+use std::{
+    io::Error as IoError,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use icmp_client::{AsyncClient, AsyncClientExt as _, SocketType};
+use icmp_packet::{
+    icmpv4, icmpv6,
+    ip::parse_ipv4_packet_bytes,
+    pnet_packet::{icmp::IcmpTypes, icmpv6::Icmpv6Types},
+    ICMP_HEADER_SIZE,
+};
+use rand::{rngs::StdRng, Rng as _, SeedableRng as _};
+use tokio::time::Instant;
+use tracing::{event, Level};
+
+//
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mangle {
+    /// Flips one random bit of the payload.
+    FlipBit,
+    /// Keeps at most `n` bytes of the payload.
+    Truncate(usize),
+    /// Overwrites every byte of the payload.
+    Fill(u8),
+}
+
+//
+/// Token bucket, `burst` replies at most, then one more every `interval`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub interval: Duration,
+}
+
+impl RateLimit {
+    pub fn new(burst: u32, interval: Duration) -> Self {
+        Self { burst, interval }
+    }
+}
+
+//
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Of the client listened with, IPv4 raw sockets deliver the IP header.
+    pub socket_type: SocketType,
+    pub delay: Duration,
+    /// Extra delay, uniformly drawn in `0..=jitter`.
+    pub jitter: Duration,
+    pub loss: f64,
+    /// Probability of answering twice.
+    pub duplicate: f64,
+    pub mangle: Option<(Mangle, f64)>,
+    pub rate_limit: Option<RateLimit>,
+    pub seed: Option<u64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            socket_type: SocketType::Raw,
+            delay: Duration::ZERO,
+            jitter: Duration::ZERO,
+            loss: 0.0,
+            duplicate: 0.0,
+            mangle: None,
+            rate_limit: None,
+            seed: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn socket_type(mut self, socket_type: SocketType) -> Self {
+        self.socket_type = socket_type;
+        self
+    }
+
+    pub fn is_raw(&self) -> bool {
+        self.socket_type == SocketType::Raw
+    }
+
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn loss(mut self, loss: f64) -> Self {
+        self.loss = loss;
+        self
+    }
+
+    pub fn duplicate(mut self, probability: f64) -> Self {
+        self.duplicate = probability;
+        self
+    }
+
+    pub fn mangle(mut self, mangle: Mangle, probability: f64) -> Self {
+        self.mangle = Some((mangle, probability));
+        self
+    }
+
+    pub fn rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// The probabilities in `0..=1`.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let is_valid = |x: f64| (0.0..=1.0).contains(&x);
+        if !is_valid(self.loss) {
+            return Err(ConfigError::LossInvalid(self.loss));
+        }
+        if !is_valid(self.duplicate) {
+            return Err(ConfigError::DuplicateInvalid(self.duplicate));
+        }
+        if let Some((_, probability)) = self.mangle {
+            if !is_valid(probability) {
+                return Err(ConfigError::MangleInvalid(probability));
+            }
+        }
+        Ok(())
+    }
+}
+
+//
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigError {
+    LossInvalid(f64),
+    DuplicateInvalid(f64),
+    MangleInvalid(f64),
+}
+
+impl core::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::LossInvalid(x) => write!(f, "loss invalid, 0 to 1, value:{x}"),
+            Self::DuplicateInvalid(x) => write!(f, "duplicate invalid, 0 to 1, value:{x}"),
+            Self::MangleInvalid(x) => write!(f, "mangle probability invalid, 0 to 1, value:{x}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+//
+struct State {
+    rng: StdRng,
+    tokens: u32,
+    tokens_updated_at: Instant,
+}
+
+//
+pub struct Responder<C>
+where
+    C: AsyncClient,
+{
+    client: Arc<C>,
+    config: Config,
+    state: Mutex<State>,
+}
+
+impl<C> core::fmt::Debug for Responder<C>
+where
+    C: AsyncClient,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Responder")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl<C> Responder<C>
+where
+    C: AsyncClient + Send + Sync + 'static,
+{
+    pub fn new(client: C, config: Config) -> Result<Self, ConfigError> {
+        config.validate()?;
+
+        let rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let tokens = config.rate_limit.map(|x| x.burst).unwrap_or_default();
+
+        Ok(Self {
+            client: Arc::new(client),
+            config,
+            state: Mutex::new(State {
+                rng,
+                tokens,
+                tokens_updated_at: Instant::now(),
+            }),
+        })
+    }
+
+    /// Answers echo requests until `recv_from` fails.
+    pub async fn run(&self) -> Result<(), IoError> {
+        let mut buf = [0; 2048];
+
+        loop {
+            let (n, addr) = self.client.recv_from(&mut buf).await?;
+
+            let echo_reply_bytes = match self.handle(&buf[..n], addr) {
+                Some(x) => x,
+                None => continue,
+            };
+            let delay = self.delay();
+            let copies = if self.duplicate() { 2 } else { 1 };
+
+            let client = self.client.clone();
+            tokio::spawn(async move {
+                if !delay.is_zero() {
+                    tokio::time::sleep(delay).await;
+                }
+                for _ in 0..copies {
+                    if let Err(err) = client.send_to(&echo_reply_bytes, addr).await {
+                        event!(Level::ERROR, "client.send_to failed, err:{err} addr:{addr}");
+                    }
+                }
+            });
+        }
+    }
+
+    fn handle(&self, bytes: &[u8], addr: SocketAddr) -> Option<Vec<u8>> {
+        let bytes = match addr {
+            SocketAddr::V4(_) if self.config.is_raw() => parse_ipv4_packet_bytes(bytes)?.1,
+            _ => bytes,
+        };
+
+        let echo_request_type = match addr {
+            SocketAddr::V4(_) => IcmpTypes::EchoRequest.0,
+            SocketAddr::V6(_) => Icmpv6Types::EchoRequest.0,
+        };
+        if bytes.len() < ICMP_HEADER_SIZE || bytes[0] != echo_request_type {
+            return None;
+        }
+
+        let mut state = self.state.lock().expect("Never when lock");
+
+        if self.config.loss > 0.0 && state.rng.gen_bool(self.config.loss) {
+            event!(Level::DEBUG, "dropped by loss, addr:{addr}");
+            return None;
+        }
+
+        if let Some(rate_limit) = self.config.rate_limit {
+            let now = Instant::now();
+            if !rate_limit.interval.is_zero() {
+                let refill = u32::try_from(
+                    now.saturating_duration_since(state.tokens_updated_at)
+                        .as_nanos()
+                        / rate_limit.interval.as_nanos(),
+                )
+                .unwrap_or(u32::MAX);
+                if refill > 0 {
+                    let tokens = state.tokens.saturating_add(refill);
+                    if tokens > rate_limit.burst {
+                        // Full, nothing carried over.
+                        state.tokens = rate_limit.burst;
+                        state.tokens_updated_at = now;
+                    } else {
+                        // The part of an interval left over is kept for the next refill.
+                        state.tokens = tokens;
+                        state.tokens_updated_at += rate_limit.interval * refill;
+                    }
+                }
+            }
+            if state.tokens == 0 {
+                event!(Level::DEBUG, "dropped by rate limit, addr:{addr}");
+                return None;
+            }
+            state.tokens -= 1;
+        }
+
+        let mut echo_request_bytes = bytes.to_vec();
+        if let Some((mangle, probability)) = self.config.mangle {
+            if probability > 0.0 && state.rng.gen_bool(probability) {
+                let payload = &mut echo_request_bytes[ICMP_HEADER_SIZE..];
+                match mangle {
+                    Mangle::FlipBit => {
+                        if !payload.is_empty() {
+                            let i = state.rng.gen_range(0..payload.len() * 8);
+                            payload[i / 8] ^= 1 << (i % 8);
+                        }
+                    }
+                    Mangle::Truncate(n) => {
+                        echo_request_bytes.truncate(ICMP_HEADER_SIZE + n);
+                    }
+                    Mangle::Fill(b) => {
+                        payload.fill(b);
+                    }
+                }
+            }
+        }
+
+        match addr {
+            SocketAddr::V4(_) => icmpv4::render_echo_reply_packet_bytes(&echo_request_bytes),
+            SocketAddr::V6(_) => icmpv6::render_echo_reply_packet_bytes(&echo_request_bytes),
+        }
+    }
+
+    fn duplicate(&self) -> bool {
+        if self.config.duplicate <= 0.0 {
+            return false;
+        }
+
+        let mut state = self.state.lock().expect("Never when lock");
+        state.rng.gen_bool(self.config.duplicate)
+    }
+
+    fn delay(&self) -> Duration {
+        if self.config.jitter.is_zero() {
+            return self.config.delay;
+        }
+
+        let mut state = self.state.lock().expect("Never when lock");
+        self.config.delay
+            + Duration::from_nanos(
+                state
+                    .rng
+                    .gen_range(0..=self.config.jitter.as_nanos().min(u64::MAX as u128) as u64),
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    use icmp_client::{
+        impl_sim::{Link, Topology},
+        Config as ClientConfig, SocketType,
+    };
+
+    use crate::PingClient;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_responder() -> Result<(), Box<dyn std::error::Error>> {
+        let network = Topology::new()
+            .host(Ipv4Addr::new(10, 0, 0, 1))
+            .silent_host(Ipv4Addr::new(10, 0, 0, 2))
+            .link(
+                [10, 0, 0, 1],
+                [10, 0, 0, 2],
+                Link::new(Duration::from_millis(1)),
+            )
+            .build();
+
+        let responder = Responder::new(
+            icmp_client::impl_sim::Client::new(
+                &network,
+                &ClientConfig::new()
+                    .socket_type(SocketType::Raw)
+                    .bind((Ipv4Addr::new(10, 0, 0, 2), 0).into()),
+            )?,
+            Config::new()
+                .delay(Duration::from_millis(100))
+                .rate_limit(RateLimit::new(1, Duration::from_secs(1))),
+        )?;
+        tokio::spawn(async move { responder.run().await });
+
+        let client = PingClient::<icmp_client::impl_sim::Client>::new(
+            Some(ClientConfig::new().sim_network(network)),
+            None,
+        )?;
+
+        let ip = Ipv4Addr::new(10, 0, 0, 2).into();
+        match client
+            .ping_v4(
+                Ipv4Addr::new(10, 0, 0, 2),
+                None,
+                Some(1),
+                b"1234",
+                Duration::from_secs(1),
+            )
+            .await?
+        {
+            (icmp_packet::Icmpv4::EchoReply(echo_reply), dur) => {
+                assert_eq!(echo_reply.payload.inner(), b"1234");
+                assert_eq!(dur, Duration::from_millis(102));
+            }
+            x => panic!("{x:?}"),
+        }
+
+        assert!(matches!(
+            client
+                .ping(ip, None, Some(2), b"1234", Duration::from_millis(500))
+                .await,
+            Ok(crate::ProbeOutcome::Timeout)
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_payload_mismatch() -> Result<(), Box<dyn std::error::Error>> {
+        use icmp_packet::PayloadMismatch;
+
+        use crate::PingError;
+
+        // The mangled bytes include the `len` prefix.
+        for (mangle, payload_mismatch) in [
+            (
+                Mangle::FlipBit,
+                PayloadMismatch::Corrupted {
+                    offset: 2,
+                    bytes: 1,
+                },
+            ),
+            (
+                Mangle::Truncate(4),
+                PayloadMismatch::Truncated {
+                    expected: 4,
+                    actual: 2,
+                },
+            ),
+            (
+                Mangle::Fill(0),
+                PayloadMismatch::LenMismatch {
+                    expected: 4,
+                    actual: 0,
+                },
+            ),
+        ] {
+            let network = Topology::new()
+                .host(Ipv4Addr::new(10, 0, 0, 1))
+                .silent_host(Ipv4Addr::new(10, 0, 0, 2))
+                .link(
+                    [10, 0, 0, 1],
+                    [10, 0, 0, 2],
+                    Link::new(Duration::from_millis(1)),
+                )
+                .build();
+            let responder = Responder::new(
+                icmp_client::impl_sim::Client::new(
+                    &network,
+                    &ClientConfig::new()
+                        .socket_type(SocketType::Raw)
+                        .bind((Ipv4Addr::new(10, 0, 0, 2), 0).into()),
+                )?,
+                Config::new().mangle(mangle, 1.0).seed(1),
+            )?;
+            tokio::spawn(async move { responder.run().await });
+
+            let client = PingClient::<icmp_client::impl_sim::Client>::new(
+                Some(ClientConfig::new().sim_network(network)),
+                None,
+            )?;
+
+            match client
+                .ping_v4(
+                    Ipv4Addr::new(10, 0, 0, 2),
+                    None,
+                    Some(1),
+                    b"\0\0\0\0",
+                    Duration::from_secs(1),
+                )
+                .await
+            {
+                Err(PingError::PayloadMismatch(x)) => assert_eq!(x, payload_mismatch),
+                x => panic!("{x:?}"),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mangle() -> Result<(), Box<dyn std::error::Error>> {
+        let network = Topology::new().host(Ipv4Addr::new(10, 0, 0, 1)).build();
+        let responder = Responder::new(
+            icmp_client::impl_sim::Client::new(&network, &ClientConfig::new())?,
+            Config::new()
+                .socket_type(SocketType::Dgram)
+                .mangle(Mangle::Fill(b'x'), 1.0),
+        )?;
+
+        let echo_request = icmp_packet::PayloadLengthDelimitedEchoRequest::new(
+            Some(1.into()),
+            Some(2.into()),
+            b"1234",
+        );
+        let addr = (Ipv4Addr::new(10, 0, 0, 1), 0).into();
+
+        let echo_reply_bytes = responder
+            .handle(&echo_request.render_v4_packet_bytes(), addr)
+            .ok_or("None")?;
+        assert_eq!(echo_reply_bytes[0], IcmpTypes::EchoReply.0);
+        assert_eq!(&echo_reply_bytes[ICMP_HEADER_SIZE..], b"xxxxxx");
+
+        assert!(responder.handle(&echo_reply_bytes, addr).is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_handle_ip_header() -> Result<(), Box<dyn std::error::Error>> {
+        let network = Topology::new().host(Ipv4Addr::new(10, 0, 0, 1)).build();
+        let client_config = ClientConfig::new().socket_type(SocketType::Raw);
+        let responder = Responder::new(
+            icmp_client::impl_sim::Client::new(&network, &client_config)?,
+            Config::new(),
+        )?;
+
+        let echo_request_bytes = icmp_packet::PayloadLengthDelimitedEchoRequest::new(
+            Some(1.into()),
+            Some(2.into()),
+            b"1234",
+        )
+        .render_v4_packet_bytes();
+        let local = Ipv4Addr::new(10, 0, 0, 1);
+        let addr = (local, 0).into();
+
+        let echo_reply_bytes = responder
+            .handle(
+                &icmp_packet::ip::render_ipv4_packet_bytes(local, local, 64, &echo_request_bytes),
+                addr,
+            )
+            .ok_or("None")?;
+        assert_eq!(echo_reply_bytes[0], IcmpTypes::EchoReply.0);
+
+        // Not guessed from the first byte.
+        assert!(responder.handle(&echo_request_bytes, addr).is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limit() -> Result<(), Box<dyn std::error::Error>> {
+        let network = Topology::new().host(Ipv4Addr::new(10, 0, 0, 1)).build();
+        let responder = Responder::new(
+            icmp_client::impl_sim::Client::new(&network, &ClientConfig::new())?,
+            Config::new()
+                .socket_type(SocketType::Dgram)
+                .rate_limit(RateLimit::new(1, Duration::from_millis(100))),
+        )?;
+
+        let echo_request_bytes = icmp_packet::PayloadLengthDelimitedEchoRequest::new(
+            Some(1.into()),
+            Some(2.into()),
+            b"1234",
+        )
+        .render_v4_packet_bytes();
+        let addr = (Ipv4Addr::new(10, 0, 0, 1), 0).into();
+
+        // Every 60ms for 3s, 10 per second and the burst are answered.
+        let mut answered = 0;
+        for _ in 0..50 {
+            if responder.handle(&echo_request_bytes, addr).is_some() {
+                answered += 1;
+            }
+            tokio::time::advance(Duration::from_millis(60)).await;
+        }
+        assert_eq!(answered, 30);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(
+            Config::new().loss(2.0).validate(),
+            Err(ConfigError::LossInvalid(2.0))
+        );
+        assert_eq!(
+            Config::new().duplicate(-0.1).validate(),
+            Err(ConfigError::DuplicateInvalid(-0.1))
+        );
+        assert!(matches!(
+            Config::new().mangle(Mangle::FlipBit, f64::NAN).validate(),
+            Err(ConfigError::MangleInvalid(_))
+        ));
+        assert_eq!(Config::new().loss(1.0).duplicate(0.0).validate(), Ok(()));
+    }
+}
@@ -0,0 +1,715 @@
+//! Ping of many destinations, like fping(8).
+//!
+//! Targets are probed concurrently over one [`PingClient`], the sends of all of them paced by `interval`.
+
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use std::{
+    io::Error as IoError,
+    net::IpAddr,
+    sync::{Arc, Mutex as StdMutex},
+};
+
+use futures_core::Stream;
+use icmp_client::AsyncClient;
+use tokio::{task::JoinSet, time::Instant};
+
+use crate::{
+    builder::{DEFAULT_PAYLOAD_SIZE, DEFAULT_TIMEOUT},
+    PingClient, PingStatistics, ProbeOutcome,
+};
+
+//
+pub const DEFAULT_INTERVAL: Duration = Duration::from_millis(10);
+pub const DEFAULT_PERIOD: Duration = Duration::from_secs(1);
+pub const DEFAULT_RETRIES: usize = 3;
+pub const DEFAULT_BACKOFF: f64 = 1.5;
+pub const DEFAULT_CONCURRENCY: usize = 256;
+
+//
+#[derive(Debug, Clone)]
+pub struct ScanConfig {
+    /// Probes per target, like `fping -c`.
+    pub count: usize,
+    /// Of a probe timed out, like `fping -r`.
+    pub retries: usize,
+    /// Timeout factor of each retry, like `fping -B`.
+    pub backoff: f64,
+    /// Between two probes to a target, like `fping -p`.
+    pub period: Duration,
+    /// Between two sends of the scan, like `fping -i`.
+    pub interval: Duration,
+    /// Targets probed at once.
+    pub concurrency: usize,
+    /// Of the first try.
+    pub timeout: Duration,
+    pub payload_size: usize,
+}
+
+impl Default for ScanConfig {
+    fn default() -> Self {
+        Self {
+            count: 1,
+            retries: DEFAULT_RETRIES,
+            backoff: DEFAULT_BACKOFF,
+            period: DEFAULT_PERIOD,
+            interval: DEFAULT_INTERVAL,
+            concurrency: DEFAULT_CONCURRENCY,
+            timeout: DEFAULT_TIMEOUT,
+            payload_size: DEFAULT_PAYLOAD_SIZE,
+        }
+    }
+}
+
+impl ScanConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn count(mut self, count: usize) -> Self {
+        self.count = count;
+        self
+    }
+
+    pub fn retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn backoff(mut self, backoff: f64) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn period(mut self, period: Duration) -> Self {
+        self.period = period;
+        self
+    }
+
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Cap of the scan, the inverse of `interval`, 0 for none.
+    pub fn packets_per_second(self, packets_per_second: u32) -> Self {
+        self.interval(
+            Duration::from_secs(1)
+                .checked_div(packets_per_second)
+                .unwrap_or_default(),
+        )
+    }
+
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn payload_size(mut self, payload_size: usize) -> Self {
+        self.payload_size = payload_size;
+        self
+    }
+
+    /// Checked by [`Scan::new`].
+    pub fn validate(&self) -> Result<(), ScanConfigError> {
+        if self.count == 0 {
+            return Err(ScanConfigError::CountZero);
+        }
+        if self.backoff.is_nan() || self.backoff < 1.0 {
+            return Err(ScanConfigError::BackoffInvalid(self.backoff));
+        }
+        if self.concurrency == 0 {
+            return Err(ScanConfigError::ConcurrencyZero);
+        }
+        Ok(())
+    }
+}
+
+//
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScanConfigError {
+    CountZero,
+    BackoffInvalid(f64),
+    ConcurrencyZero,
+}
+
+impl core::fmt::Display for ScanConfigError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::CountZero => write!(f, "count zero"),
+            Self::BackoffInvalid(x) => write!(f, "backoff invalid, 1 at least, value:{x}"),
+            Self::ConcurrencyZero => write!(f, "concurrency zero"),
+        }
+    }
+}
+
+impl std::error::Error for ScanConfigError {}
+
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetStatus {
+    /// At least one echo reply, `error` is a payload mismatch when none carried the payload sent,
+    /// whatever the ICMP errors of the other probes.
+    Alive,
+    /// No echo reply but ICMP errors or send failures.
+    Unreachable,
+    /// Nothing at all.
+    Timeout,
+}
+
+//
+#[derive(Debug)]
+pub struct ScanResult {
+    pub ip: IpAddr,
+    pub status: TargetStatus,
+    /// One per probe, `None` when lost after the retries, like `fping -C`.
+    pub rtts: Vec<Option<Duration>>,
+    /// As per `status`, the last payload mismatch when `Alive`, the last ICMP error or send failure
+    /// when `Unreachable`.
+    pub error: Option<ProbeOutcome>,
+    /// Of every try, the retries included.
+    pub statistics: PingStatistics,
+}
+
+//
+/// Hands out send times `interval` apart.
+#[derive(Debug)]
+struct Pacer {
+    interval: Duration,
+    next_at: StdMutex<Instant>,
+}
+
+impl Pacer {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next_at: StdMutex::new(Instant::now()),
+        }
+    }
+
+    async fn wait(&self) {
+        if self.interval.is_zero() {
+            return;
+        }
+        let at = {
+            let mut next_at = self.next_at.lock().expect("Never when not poisoned");
+            let at = (*next_at).max(Instant::now());
+            *next_at = at + self.interval;
+            at
+        };
+        tokio::time::sleep_until(at).await
+    }
+}
+
+//
+/// A [`Stream`] of [`ScanResult`], one per target as they complete.
+pub struct Scan<C>
+where
+    C: AsyncClient,
+{
+    client: PingClient<C>,
+    config: Arc<ScanConfig>,
+    payload: Arc<[u8]>,
+    pacer: Arc<Pacer>,
+    /// Taken as the probes are spawned.
+    targets: Box<dyn Iterator<Item = IpAddr> + Send>,
+    probes: JoinSet<ScanResult>,
+}
+
+impl<C> core::fmt::Debug for Scan<C>
+where
+    C: AsyncClient,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Scan")
+            .field("config", &self.config)
+            .field("in_flight", &self.probes.len())
+            .finish()
+    }
+}
+
+impl<C> Scan<C>
+where
+    C: AsyncClient + Send + Sync + 'static,
+{
+    pub fn new<I>(
+        client: PingClient<C>,
+        targets: I,
+        config: ScanConfig,
+    ) -> Result<Self, ScanConfigError>
+    where
+        I: IntoIterator<Item = IpAddr>,
+        I::IntoIter: Send + 'static,
+    {
+        config.validate()?;
+
+        Ok(Self {
+            client,
+            payload: vec![0; config.payload_size].into(),
+            pacer: Arc::new(Pacer::new(config.interval)),
+            config: Arc::new(config),
+            targets: Box::new(targets.into_iter()),
+            probes: JoinSet::new(),
+        })
+    }
+}
+
+impl<C> Stream for Scan<C>
+where
+    C: AsyncClient + Send + Sync + 'static,
+{
+    type Item = ScanResult;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        while this.probes.len() < this.config.concurrency {
+            let Some(ip) = this.targets.next() else {
+                break;
+            };
+            this.probes.spawn(scan_target(
+                this.client.clone(),
+                ip,
+                this.config.clone(),
+                this.payload.clone(),
+                this.pacer.clone(),
+            ));
+        }
+
+        match this.probes.poll_join_next(cx) {
+            Poll::Ready(Some(Ok(result))) => Poll::Ready(Some(result)),
+            Poll::Ready(Some(Err(err))) if err.is_panic() => {
+                std::panic::resume_unwind(err.into_panic())
+            }
+            // Cancelled, the runtime shutting down.
+            Poll::Ready(Some(Err(_))) => {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+async fn scan_target<C>(
+    client: PingClient<C>,
+    ip: IpAddr,
+    config: Arc<ScanConfig>,
+    payload: Arc<[u8]>,
+    pacer: Arc<Pacer>,
+) -> ScanResult
+where
+    C: AsyncClient + Send + Sync + 'static,
+{
+    let mut result = ScanResult {
+        ip,
+        status: TargetStatus::Timeout,
+        rtts: Vec::with_capacity(config.count),
+        error: None,
+        statistics: PingStatistics::new(),
+    };
+    let mut sequence_number = 0_u16;
+    let mut next_at = None;
+    let mut has_reply = false;
+    let mut mismatch = None;
+    let mut last_error = None;
+
+    for _ in 0..config.count {
+        if let Some(next_at) = next_at {
+            tokio::time::sleep_until(next_at).await;
+        }
+
+        let mut timeout = config.timeout;
+        let mut rtt = None;
+        for try_index in 0..=config.retries {
+            pacer.wait().await;
+            if try_index == 0 {
+                next_at = Some(Instant::now() + config.period);
+            }
+
+            let outcome = client
+                .ping(ip, None, Some(sequence_number), &payload, timeout)
+                .await
+                .unwrap_or_else(|err| ProbeOutcome::SendFailed(IoError::other(err)));
+            sequence_number = sequence_number.wrapping_add(1);
+            result.statistics.record(&outcome);
+
+            match outcome {
+                ProbeOutcome::Reply { rtt: x, .. } => {
+                    has_reply = true;
+                    rtt = Some(x);
+                    break;
+                }
+                ProbeOutcome::PayloadMismatch { rtt: x, .. } => {
+                    mismatch = Some(outcome);
+                    rtt = Some(x);
+                    break;
+                }
+                ProbeOutcome::Timeout => timeout = timeout.mul_f64(config.backoff),
+                // An answer, retrying would get the same.
+                outcome => {
+                    last_error = Some(outcome);
+                    break;
+                }
+            }
+        }
+        result.rtts.push(rtt);
+    }
+
+    (result.status, result.error) = match (has_reply, mismatch, last_error) {
+        (true, _, _) => (TargetStatus::Alive, None),
+        // Answered anyway, e.g. through a broken middlebox.
+        (false, Some(mismatch), _) => (TargetStatus::Alive, Some(mismatch)),
+        (false, None, Some(error)) => (TargetStatus::Unreachable, Some(error)),
+        (false, None, None) => (TargetStatus::Timeout, None),
+    };
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::task::Waker;
+    use std::{
+        collections::{HashMap, VecDeque},
+        net::{Ipv4Addr, SocketAddr},
+    };
+
+    use icmp_client::{
+        impl_sim::{Client, Link, Topology},
+        AsyncClientWithConfigError, Config as ClientConfig, ReadBuf, SocketType,
+    };
+    use icmp_packet::{
+        icmpv4,
+        ip::render_ipv4_packet_bytes,
+        pnet_packet::icmp::{IcmpCode, IcmpTypes},
+    };
+
+    use crate::responder::{Config as ResponderConfig, Mangle, Responder};
+
+    async fn next<C>(scan: &mut Scan<C>) -> Option<ScanResult>
+    where
+        C: AsyncClient + Send + Sync + 'static,
+    {
+        core::future::poll_fn(|cx| Pin::new(&mut *scan).poll_next(cx)).await
+    }
+
+    //
+    #[derive(Debug, Clone, Copy)]
+    enum Answer {
+        Reply,
+        PayloadMismatch,
+        Unreachable,
+    }
+
+    #[derive(Debug, Default)]
+    struct ScriptedState {
+        answers: VecDeque<Answer>,
+        replies: VecDeque<(Vec<u8>, SocketAddr)>,
+        waker: Option<Waker>,
+    }
+
+    /// Answers each echo request as scripted, like a DGRAM socket.
+    #[derive(Debug, Default)]
+    struct ScriptedClient(StdMutex<ScriptedState>);
+
+    impl ScriptedClient {
+        fn new(answers: impl IntoIterator<Item = Answer>) -> Self {
+            let client = Self::default();
+            client.0.lock().expect("Never when lock").answers = answers.into_iter().collect();
+            client
+        }
+    }
+
+    impl AsyncClient for ScriptedClient {
+        fn with_config(_config: &ClientConfig) -> Result<Self, AsyncClientWithConfigError> {
+            Ok(Self::default())
+        }
+
+        fn poll_send_to(
+            &self,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+            addr: SocketAddr,
+        ) -> Poll<Result<usize, IoError>> {
+            let IpAddr::V4(destination) = addr.ip() else {
+                return Poll::Ready(Err(IoError::other("not IPv4")));
+            };
+
+            let mut state = self.0.lock().expect("Never when lock");
+            let bytes = match state.answers.pop_front() {
+                Some(Answer::Reply) => icmpv4::render_echo_reply_packet_bytes(buf),
+                Some(Answer::PayloadMismatch) => {
+                    let mut echo_request_bytes = buf.to_vec();
+                    if let Some(x) = echo_request_bytes.last_mut() {
+                        *x ^= 0xff;
+                    }
+                    icmpv4::render_echo_reply_packet_bytes(&echo_request_bytes)
+                }
+                Some(Answer::Unreachable) => Some(icmpv4::render_error_packet_bytes(
+                    IcmpTypes::DestinationUnreachable,
+                    IcmpCode(1),
+                    0,
+                    &render_ipv4_packet_bytes(Ipv4Addr::new(10, 0, 0, 1), destination, 64, buf),
+                )),
+                None => None,
+            };
+            if let Some(bytes) = bytes {
+                state.replies.push_back((bytes, addr));
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            }
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_recv_from(
+            &self,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<Result<SocketAddr, IoError>> {
+            let mut state = self.0.lock().expect("Never when lock");
+            match state.replies.pop_front() {
+                Some((bytes, addr)) => {
+                    buf.put_slice(&bytes);
+                    Poll::Ready(Ok(addr))
+                }
+                None => {
+                    state.waker = Some(cx.waker().to_owned());
+                    Poll::Pending
+                }
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_scan() -> Result<(), Box<dyn std::error::Error>> {
+        let link = Link::new(Duration::from_millis(5));
+        let network = Topology::new()
+            .host(Ipv4Addr::new(10, 0, 0, 1))
+            .router(Ipv4Addr::new(10, 0, 0, 254))
+            .host(Ipv4Addr::new(10, 0, 1, 2))
+            .unreachable_host(Ipv4Addr::new(10, 0, 1, 3))
+            .silent_host(Ipv4Addr::new(10, 0, 1, 4))
+            .link([10, 0, 0, 1], [10, 0, 0, 254], link)
+            .link([10, 0, 0, 254], [10, 0, 1, 2], link)
+            .link([10, 0, 0, 254], [10, 0, 1, 3], link)
+            .link([10, 0, 0, 254], [10, 0, 1, 4], link)
+            .build();
+        let client =
+            PingClient::<Client>::new(Some(ClientConfig::new().sim_network(network)), None)?;
+
+        let started_at = Instant::now();
+        let mut scan = client.scan(
+            [2, 3, 4].map(|x| IpAddr::from(Ipv4Addr::new(10, 0, 1, x))),
+            ScanConfig::new()
+                .count(2)
+                .retries(1)
+                .backoff(2.0)
+                .period(Duration::from_millis(500))
+                .packets_per_second(100)
+                .timeout(Duration::from_millis(100)),
+        )?;
+        let mut results = HashMap::new();
+        while let Some(result) = next(&mut scan).await {
+            results.insert(result.ip, result);
+        }
+        assert_eq!(results.len(), 3);
+
+        let alive = &results[&IpAddr::from(Ipv4Addr::new(10, 0, 1, 2))];
+        assert_eq!(alive.status, TargetStatus::Alive);
+        assert_eq!(alive.rtts, vec![Some(Duration::from_millis(20)); 2]);
+        assert!(alive.error.is_none());
+        assert_eq!(alive.statistics.transmitted, 2);
+
+        let unreachable = &results[&IpAddr::from(Ipv4Addr::new(10, 0, 1, 3))];
+        assert_eq!(unreachable.status, TargetStatus::Unreachable);
+        assert_eq!(unreachable.rtts, vec![None; 2]);
+        assert!(matches!(
+            unreachable.error,
+            Some(ProbeOutcome::Unreachable { .. })
+        ));
+        // No retry of an answer.
+        assert_eq!(unreachable.statistics.transmitted, 2);
+        assert_eq!(unreachable.statistics.errors, 2);
+
+        let silent = &results[&IpAddr::from(Ipv4Addr::new(10, 0, 1, 4))];
+        assert_eq!(silent.status, TargetStatus::Timeout);
+        assert_eq!(silent.rtts, vec![None; 2]);
+        assert_eq!(silent.statistics.transmitted, 4);
+
+        // The second probe of the silent one at 20 + 500ms, its retry after 100ms, timed out after 200ms.
+        assert_eq!(started_at.elapsed(), Duration::from_millis(820));
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_scan_config_invalid() -> Result<(), Box<dyn std::error::Error>> {
+        let network = Topology::new().host(Ipv4Addr::new(10, 0, 0, 1)).build();
+        let client =
+            PingClient::<Client>::new(Some(ClientConfig::new().sim_network(network)), None)?;
+        let ips = [IpAddr::from(Ipv4Addr::new(10, 0, 0, 2))];
+
+        assert!(matches!(
+            client.scan(ips, ScanConfig::new().count(0)),
+            Err(ScanConfigError::CountZero)
+        ));
+        assert!(matches!(
+            client.scan(ips, ScanConfig::new().backoff(0.5)),
+            Err(ScanConfigError::BackoffInvalid(x)) if x == 0.5
+        ));
+        assert!(matches!(
+            client.scan(ips, ScanConfig::new().backoff(f64::NAN)),
+            Err(ScanConfigError::BackoffInvalid(_))
+        ));
+        assert!(matches!(
+            client.scan(ips, ScanConfig::new().concurrency(0)),
+            Err(ScanConfigError::ConcurrencyZero)
+        ));
+        // No cap.
+        assert_eq!(
+            ScanConfig::new().packets_per_second(0).interval,
+            Duration::ZERO
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_scan_pacing() -> Result<(), Box<dyn std::error::Error>> {
+        let link = Link::new(Duration::from_millis(1));
+        let network = Topology::new()
+            .host(Ipv4Addr::new(10, 0, 0, 1))
+            .host(Ipv4Addr::new(10, 0, 0, 2))
+            .host(Ipv4Addr::new(10, 0, 0, 3))
+            .link([10, 0, 0, 1], [10, 0, 0, 2], link)
+            .link([10, 0, 0, 1], [10, 0, 0, 3], link)
+            .build();
+        let client =
+            PingClient::<Client>::new(Some(ClientConfig::new().sim_network(network)), None)?;
+
+        // One target at a time, the period shorter than the interval.
+        let started_at = Instant::now();
+        let mut scan = client.scan(
+            [2, 3].map(|x| IpAddr::from(Ipv4Addr::new(10, 0, 0, x))),
+            ScanConfig::new()
+                .count(5)
+                .period(Duration::ZERO)
+                .interval(Duration::from_millis(100))
+                .concurrency(1),
+        )?;
+
+        let result = next(&mut scan).await.ok_or("None")?;
+        assert_eq!(result.ip, IpAddr::from(Ipv4Addr::new(10, 0, 0, 2)));
+        assert_eq!(result.rtts, vec![Some(Duration::from_millis(2)); 5]);
+        assert_eq!(started_at.elapsed(), Duration::from_millis(402));
+
+        let result = next(&mut scan).await.ok_or("None")?;
+        assert_eq!(result.ip, IpAddr::from(Ipv4Addr::new(10, 0, 0, 3)));
+        assert_eq!(result.statistics.received, 5);
+        // The last send at 900ms.
+        assert_eq!(started_at.elapsed(), Duration::from_millis(902));
+        assert!(next(&mut scan).await.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_scan_payload_mismatch() -> Result<(), Box<dyn std::error::Error>> {
+        let network = Topology::new()
+            .host(Ipv4Addr::new(10, 0, 0, 1))
+            .silent_host(Ipv4Addr::new(10, 0, 0, 2))
+            .link(
+                [10, 0, 0, 1],
+                [10, 0, 0, 2],
+                Link::new(Duration::from_millis(1)),
+            )
+            .build();
+        let responder = Responder::new(
+            Client::new(
+                &network,
+                &ClientConfig::new()
+                    .socket_type(SocketType::Raw)
+                    .bind((Ipv4Addr::new(10, 0, 0, 2), 0).into()),
+            )?,
+            ResponderConfig::new().mangle(Mangle::Fill(0xff), 1.0),
+        )?;
+        tokio::spawn(async move { responder.run().await });
+
+        let client =
+            PingClient::<Client>::new(Some(ClientConfig::new().sim_network(network)), None)?;
+        let mut scan = client.scan(
+            [IpAddr::from(Ipv4Addr::new(10, 0, 0, 2))],
+            ScanConfig::new().count(2).period(Duration::ZERO),
+        )?;
+
+        let result = next(&mut scan).await.ok_or("None")?;
+        assert_eq!(result.status, TargetStatus::Alive);
+        assert_eq!(result.rtts, vec![Some(Duration::from_millis(2)); 2]);
+        assert!(matches!(
+            result.error,
+            Some(ProbeOutcome::PayloadMismatch { .. })
+        ));
+        // No retry of an answer.
+        assert_eq!(result.statistics.transmitted, 2);
+        assert!(next(&mut scan).await.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_scan_alive_error() -> Result<(), Box<dyn std::error::Error>> {
+        let ip = IpAddr::from(Ipv4Addr::new(10, 0, 0, 2));
+        let config = ScanConfig::new()
+            .count(2)
+            .retries(0)
+            .period(Duration::ZERO)
+            .interval(Duration::ZERO);
+
+        // A clean reply in another round.
+        for answers in [
+            [Answer::PayloadMismatch, Answer::Reply],
+            [Answer::Unreachable, Answer::Reply],
+        ] {
+            let client =
+                PingClient::with_clients(Some((ScriptedClient::new(answers), false)), None);
+            let result = next(&mut client.scan([ip], config.clone())?)
+                .await
+                .ok_or("None")?;
+            assert_eq!(result.status, TargetStatus::Alive, "{answers:?}");
+            assert!(result.error.is_none(), "{answers:?}");
+        }
+
+        // No clean reply, the mismatch kept over the later ICMP error.
+        let client = PingClient::with_clients(
+            Some((
+                ScriptedClient::new([Answer::PayloadMismatch, Answer::Unreachable]),
+                false,
+            )),
+            None,
+        );
+        let result = next(&mut client.scan([ip], config)?).await.ok_or("None")?;
+        assert_eq!(result.status, TargetStatus::Alive);
+        assert_eq!(result.rtts.len(), 2);
+        assert!(result.rtts[0].is_some() && result.rtts[1].is_none());
+        assert!(matches!(
+            result.error,
+            Some(ProbeOutcome::PayloadMismatch { .. })
+        ));
+        assert_eq!(result.statistics.transmitted, 2);
+
+        Ok(())
+    }
+}
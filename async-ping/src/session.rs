@@ -0,0 +1,638 @@
+//! Continuous ping to one destination, like ping(8).
+//!
+//! Probes are sent every `interval` whatever the replies, so they overlap when the RTT is longer.
+
+use core::{
+    future::Future as _,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use std::{
+    collections::HashSet,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+
+use futures_core::Stream;
+use icmp_client::AsyncClient;
+use tokio::{
+    sync::{broadcast, mpsc},
+    task::{JoinHandle, JoinSet},
+    time::{Instant, Interval, MissedTickBehavior, Sleep},
+};
+
+use crate::{
+    builder::{DEFAULT_PAYLOAD_SIZE, DEFAULT_TIMEOUT},
+    PingClient, PingError, PingStatistics, ProbeOutcome, ReplyEvent, ReplyEventKind,
+};
+
+//
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+
+//
+#[derive(Debug, Clone)]
+pub struct PingSessionConfig {
+    pub interval: Duration,
+    /// Probes to send, `None` until the deadline or forever.
+    pub count: Option<u64>,
+    /// From the start, no probe is waited for beyond it.
+    pub deadline: Option<Duration>,
+    /// Of each probe.
+    pub timeout: Duration,
+    pub payload_size: usize,
+    /// Repeated to fill the payload, like `ping -p`.
+    pub payload_pattern: Vec<u8>,
+    pub sequence_start: u16,
+    /// Else the one of the client, or a random one, the same for every probe.
+    pub identifier: Option<u16>,
+}
+
+impl Default for PingSessionConfig {
+    fn default() -> Self {
+        Self {
+            interval: DEFAULT_INTERVAL,
+            count: None,
+            deadline: None,
+            timeout: DEFAULT_TIMEOUT,
+            payload_size: DEFAULT_PAYLOAD_SIZE,
+            payload_pattern: vec![0],
+            sequence_start: 0,
+            identifier: None,
+        }
+    }
+}
+
+impl PingSessionConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn count(mut self, count: u64) -> Self {
+        self.count = Some(count);
+        self
+    }
+
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn payload_size(mut self, payload_size: usize) -> Self {
+        self.payload_size = payload_size;
+        self
+    }
+
+    pub fn payload_pattern(mut self, payload_pattern: impl Into<Vec<u8>>) -> Self {
+        self.payload_pattern = payload_pattern.into();
+        self
+    }
+
+    pub fn sequence_start(mut self, sequence_start: u16) -> Self {
+        self.sequence_start = sequence_start;
+        self
+    }
+
+    pub fn identifier(mut self, identifier: u16) -> Self {
+        self.identifier = Some(identifier);
+        self
+    }
+
+    /// Checked by [`PingSession::new`].
+    pub fn validate(&self) -> Result<(), PingSessionConfigError> {
+        if self.interval.is_zero() {
+            return Err(PingSessionConfigError::IntervalZero);
+        }
+        if self.payload_pattern.is_empty() {
+            return Err(PingSessionConfigError::PayloadPatternEmpty);
+        }
+        Ok(())
+    }
+
+    fn payload(&self) -> Vec<u8> {
+        self.payload_pattern
+            .iter()
+            .copied()
+            .cycle()
+            .take(self.payload_size)
+            .collect()
+    }
+}
+
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PingSessionConfigError {
+    IntervalZero,
+    PayloadPatternEmpty,
+}
+
+impl core::fmt::Display for PingSessionConfigError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::IntervalZero => write!(f, "interval zero"),
+            Self::PayloadPatternEmpty => write!(f, "payload_pattern empty"),
+        }
+    }
+}
+
+impl std::error::Error for PingSessionConfigError {}
+
+//
+#[derive(Debug)]
+pub enum PingSessionEvent {
+    /// One per probe, as they complete.
+    Probe {
+        sequence_number: u16,
+        outcome: Result<ProbeOutcome, PingError>,
+    },
+    /// Duplicate, late or reordered reply to a probe of the session.
+    Reply(ReplyEvent),
+    /// The last one, once every probe completed.
+    Summary(PingSummary),
+}
+
+//
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PingSummary {
+    /// Of the completed probes.
+    pub statistics: PingStatistics,
+    pub duplicates: u64,
+    pub late: u64,
+    pub reordered: u64,
+    pub elapsed: Duration,
+}
+
+//
+/// A [`Stream`] of [`PingSessionEvent`], the probes are sent while it is polled.
+pub struct PingSession<C>
+where
+    C: AsyncClient,
+{
+    client: PingClient<C>,
+    ip: IpAddr,
+    config: PingSessionConfig,
+    identifier: u16,
+    payload: Arc<[u8]>,
+    started_at: Instant,
+    interval: Interval,
+    deadline: Option<Pin<Box<Sleep>>>,
+    next_sequence_number: u16,
+    /// Sequence numbers sent, the reply events of the other probes to `ip` are not of the session.
+    sent: Arc<Mutex<HashSet<u16>>>,
+    is_sending: bool,
+    probes: JoinSet<(u16, Result<ProbeOutcome, PingError>)>,
+    transmitted: u64,
+    reply_events_rx: mpsc::UnboundedReceiver<ReplyEvent>,
+    reply_events_task: JoinHandle<()>,
+    summary: PingSummary,
+    is_finished: bool,
+}
+
+impl<C> core::fmt::Debug for PingSession<C>
+where
+    C: AsyncClient,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PingSession")
+            .field("ip", &self.ip)
+            .field("config", &self.config)
+            .field("summary", &self.summary)
+            .finish()
+    }
+}
+
+impl<C> PingSession<C>
+where
+    C: AsyncClient + Send + Sync + 'static,
+{
+    pub fn new(
+        client: PingClient<C>,
+        ip: IpAddr,
+        config: PingSessionConfig,
+    ) -> Result<Self, PingSessionConfigError> {
+        config.validate()?;
+
+        let identifier = config
+            .identifier
+            .or_else(|| client.identifier.identifier())
+            .unwrap_or_else(rand::random);
+        let payload = config.payload().into();
+        let started_at = Instant::now();
+
+        let mut interval = tokio::time::interval_at(started_at, config.interval);
+        // On schedule, the missed probes are sent at once.
+        interval.set_missed_tick_behavior(MissedTickBehavior::Burst);
+
+        let sent = Arc::new(Mutex::new(HashSet::new()));
+        let (reply_events_tx, reply_events_rx) = mpsc::unbounded_channel();
+        let mut reply_events = client.reply_events();
+        let sent_ = sent.clone();
+        let reply_events_task = tokio::spawn(async move {
+            loop {
+                let event = match reply_events.recv().await {
+                    Ok(x) => x,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                // DGRAM sockets rewrite the identifier, then only the sequence number is matched.
+                if event.ip == ip
+                    && event.identifier.map(|x| x == identifier) != Some(false)
+                    && sent_
+                        .lock()
+                        .expect("Never when sent lock")
+                        .contains(&event.sequence_number)
+                    && reply_events_tx.send(event).is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            ip,
+            identifier,
+            payload,
+            started_at,
+            interval,
+            deadline: config
+                .deadline
+                .map(|x| Box::pin(tokio::time::sleep_until(started_at + x))),
+            next_sequence_number: config.sequence_start,
+            sent,
+            is_sending: config.count != Some(0),
+            probes: JoinSet::new(),
+            transmitted: 0,
+            reply_events_rx,
+            reply_events_task,
+            summary: PingSummary::default(),
+            is_finished: false,
+            client,
+            config,
+        })
+    }
+
+    pub fn ip(&self) -> IpAddr {
+        self.ip
+    }
+
+    /// So far, the final one is also the last event.
+    pub fn summary(&self) -> PingSummary {
+        let mut summary = self.summary.clone();
+        if !self.is_finished {
+            summary.elapsed = self.started_at.elapsed();
+        }
+        summary
+    }
+
+    fn send(&mut self) {
+        let now = Instant::now();
+        let timeout = match self.config.deadline {
+            Some(deadline) => self
+                .config
+                .timeout
+                .min((self.started_at + deadline).saturating_duration_since(now)),
+            None => self.config.timeout,
+        };
+
+        let client = self.client.clone();
+        let ip = self.ip;
+        let identifier = self.identifier;
+        let sequence_number = self.next_sequence_number;
+        let payload = self.payload.clone();
+        self.sent
+            .lock()
+            .expect("Never when sent lock")
+            .insert(sequence_number);
+        self.probes.spawn(async move {
+            let outcome = client
+                .ping(
+                    ip,
+                    Some(identifier),
+                    Some(sequence_number),
+                    payload,
+                    timeout,
+                )
+                .await;
+            (sequence_number, outcome)
+        });
+
+        self.next_sequence_number = self.next_sequence_number.wrapping_add(1);
+        self.transmitted += 1;
+        if self.config.count == Some(self.transmitted) {
+            self.is_sending = false;
+        }
+    }
+}
+
+impl<C> Stream for PingSession<C>
+where
+    C: AsyncClient + Send + Sync + 'static,
+{
+    type Item = PingSessionEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        if this.is_finished {
+            return Poll::Ready(None);
+        }
+
+        if this.is_sending {
+            if let Some(deadline) = this.deadline.as_mut() {
+                if deadline.as_mut().poll(cx).is_ready() {
+                    this.is_sending = false;
+                }
+            }
+        }
+        while this.is_sending && this.interval.poll_tick(cx).is_ready() {
+            this.send();
+        }
+
+        if let Poll::Ready(Some(event)) = this.reply_events_rx.poll_recv(cx) {
+            match event.kind {
+                ReplyEventKind::Duplicate => this.summary.duplicates += 1,
+                ReplyEventKind::Late => this.summary.late += 1,
+                ReplyEventKind::Reordered => this.summary.reordered += 1,
+            }
+            return Poll::Ready(Some(PingSessionEvent::Reply(event)));
+        }
+
+        match this.probes.poll_join_next(cx) {
+            Poll::Ready(Some(Ok((sequence_number, outcome)))) => {
+                match &outcome {
+                    Ok(outcome) => this.summary.statistics.record(outcome),
+                    Err(_) => this.summary.statistics.record_failure(),
+                }
+                Poll::Ready(Some(PingSessionEvent::Probe {
+                    sequence_number,
+                    outcome,
+                }))
+            }
+            Poll::Ready(Some(Err(err))) if err.is_panic() => {
+                std::panic::resume_unwind(err.into_panic())
+            }
+            // Cancelled, the runtime shutting down.
+            Poll::Ready(Some(Err(_))) => {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+            Poll::Ready(None) if !this.is_sending => {
+                this.summary = this.summary();
+                this.is_finished = true;
+                this.reply_events_task.abort();
+                Poll::Ready(Some(PingSessionEvent::Summary(this.summary.clone())))
+            }
+            _ => Poll::Pending,
+        }
+    }
+}
+
+impl<C> Drop for PingSession<C>
+where
+    C: AsyncClient,
+{
+    fn drop(&mut self) {
+        self.reply_events_task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    use icmp_client::{
+        impl_sim::{Client, Link, Topology},
+        Config as ClientConfig, SocketType,
+    };
+
+    use crate::responder::{Config as ResponderConfig, Responder};
+
+    async fn next(session: &mut PingSession<Client>) -> Option<PingSessionEvent> {
+        core::future::poll_fn(|cx| Pin::new(&mut *session).poll_next(cx)).await
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_session() -> Result<(), Box<dyn std::error::Error>> {
+        let network = Topology::new()
+            .host(Ipv4Addr::new(10, 0, 0, 1))
+            .silent_host(Ipv4Addr::new(10, 0, 0, 2))
+            .link(
+                [10, 0, 0, 1],
+                [10, 0, 0, 2],
+                Link::new(Duration::from_millis(1)),
+            )
+            .build();
+        // Replies after 2.5 intervals, twice.
+        let responder = Responder::new(
+            Client::new(
+                &network,
+                &ClientConfig::new()
+                    .socket_type(SocketType::Raw)
+                    .bind((Ipv4Addr::new(10, 0, 0, 2), 0).into()),
+            )?,
+            ResponderConfig::new()
+                .delay(Duration::from_millis(248))
+                .duplicate(1.0),
+        )?;
+        tokio::spawn(async move { responder.run().await });
+
+        let client =
+            PingClient::<Client>::new(Some(ClientConfig::new().sim_network(network)), None)?;
+        let ip = Ipv4Addr::new(10, 0, 0, 2).into();
+        let mut session = client.session(
+            ip,
+            PingSessionConfig::new()
+                .interval(Duration::from_millis(100))
+                .count(4)
+                .payload_size(5)
+                .payload_pattern(*b"ab")
+                .sequence_start(u16::MAX),
+        )?;
+
+        assert_eq!(session.payload.as_ref(), b"ababa");
+
+        let started_at = Instant::now();
+        let mut sequence_numbers = vec![];
+        let mut duplicates = 0;
+        let summary = loop {
+            match next(&mut session).await.ok_or("None")? {
+                PingSessionEvent::Probe {
+                    sequence_number,
+                    outcome,
+                } => {
+                    match outcome? {
+                        ProbeOutcome::Reply { rtt, .. } => {
+                            assert_eq!(rtt, Duration::from_millis(250))
+                        }
+                        x => panic!("{x:?}"),
+                    }
+                    // On schedule, whatever the replies.
+                    assert_eq!(
+                        started_at.elapsed(),
+                        Duration::from_millis(250 + 100 * sequence_numbers.len() as u64)
+                    );
+                    sequence_numbers.push(sequence_number);
+                }
+                PingSessionEvent::Reply(event) => {
+                    assert_eq!(event.kind, ReplyEventKind::Duplicate);
+                    duplicates += 1;
+                }
+                PingSessionEvent::Summary(summary) => break summary,
+            }
+        };
+        assert_eq!(sequence_numbers, vec![u16::MAX, 0, 1, 2]);
+        assert_eq!(
+            summary,
+            PingSummary {
+                statistics: summary.statistics.clone(),
+                duplicates,
+                late: 0,
+                reordered: 0,
+                elapsed: Duration::from_millis(550),
+            }
+        );
+        assert_eq!(summary.statistics.transmitted, 4);
+        assert_eq!(summary.statistics.received, 4);
+        assert_eq!(summary.statistics.errors, 0);
+        assert_eq!(
+            summary.statistics.rtt_p50(),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(summary.statistics.jitter(), Some(Duration::ZERO));
+        assert!(duplicates >= 3);
+        assert_eq!(summary.statistics.loss(), 0.0);
+        assert!(next(&mut session).await.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_session_same_ip() -> Result<(), Box<dyn std::error::Error>> {
+        let network = Topology::new()
+            .host(Ipv4Addr::new(10, 0, 0, 1))
+            .silent_host(Ipv4Addr::new(10, 0, 0, 2))
+            .link(
+                [10, 0, 0, 1],
+                [10, 0, 0, 2],
+                Link::new(Duration::from_millis(1)),
+            )
+            .build();
+        let responder = Responder::new(
+            Client::new(
+                &network,
+                &ClientConfig::new()
+                    .socket_type(SocketType::Raw)
+                    .bind((Ipv4Addr::new(10, 0, 0, 2), 0).into()),
+            )?,
+            ResponderConfig::new().duplicate(1.0),
+        )?;
+        tokio::spawn(async move { responder.run().await });
+
+        // DGRAM, the identifier of the replies is not matched.
+        let client =
+            PingClient::<Client>::new(Some(ClientConfig::new().sim_network(network)), None)?;
+        let ip = Ipv4Addr::new(10, 0, 0, 2).into();
+        let run = |sequence_start: u16| {
+            let mut session = client
+                .session(
+                    ip,
+                    PingSessionConfig::new()
+                        .interval(Duration::from_millis(100))
+                        .count(3)
+                        .sequence_start(sequence_start),
+                )
+                .expect("Never when valid");
+            async move {
+                loop {
+                    match next(&mut session).await.expect("Never") {
+                        PingSessionEvent::Probe { .. } => {}
+                        PingSessionEvent::Reply(event) => {
+                            assert!((sequence_start..sequence_start + 3)
+                                .contains(&event.sequence_number));
+                        }
+                        PingSessionEvent::Summary(summary) => break summary,
+                    }
+                }
+            }
+        };
+
+        let (a, b) = tokio::join!(run(0), run(100));
+        for summary in [a, b] {
+            assert_eq!(summary.statistics.received, 3);
+            assert!((1..=3).contains(&summary.duplicates));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_session_config_invalid() -> Result<(), Box<dyn std::error::Error>> {
+        let network = Topology::new().host(Ipv4Addr::new(10, 0, 0, 1)).build();
+        let client =
+            PingClient::<Client>::new(Some(ClientConfig::new().sim_network(network)), None)?;
+        let ip = Ipv4Addr::new(10, 0, 0, 2).into();
+
+        assert!(matches!(
+            client.session(ip, PingSessionConfig::new().interval(Duration::ZERO)),
+            Err(PingSessionConfigError::IntervalZero)
+        ));
+        assert!(matches!(
+            client.session(ip, PingSessionConfig::new().payload_pattern(vec![])),
+            Err(PingSessionConfigError::PayloadPatternEmpty)
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_session_deadline() -> Result<(), Box<dyn std::error::Error>> {
+        let network = Topology::new()
+            .host(Ipv4Addr::new(10, 0, 0, 1))
+            .host(Ipv4Addr::new(10, 0, 0, 2))
+            .link(
+                [10, 0, 0, 1],
+                [10, 0, 0, 2],
+                Link::new(Duration::from_millis(100)).loss(1.0),
+            )
+            .build();
+        let client =
+            PingClient::<Client>::new(Some(ClientConfig::new().sim_network(network)), None)?;
+
+        let started_at = Instant::now();
+        let mut session = client.session(
+            Ipv4Addr::new(10, 0, 0, 2).into(),
+            PingSessionConfig::new()
+                .interval(Duration::from_millis(400))
+                .deadline(Duration::from_secs(1))
+                .timeout(Duration::from_secs(5)),
+        )?;
+        let summary = loop {
+            match next(&mut session).await.ok_or("None")? {
+                PingSessionEvent::Probe { outcome, .. } => {
+                    assert!(matches!(outcome, Ok(ProbeOutcome::Timeout)))
+                }
+                PingSessionEvent::Reply(x) => panic!("{x:?}"),
+                PingSessionEvent::Summary(summary) => break summary,
+            }
+        };
+        // Sent at 0, 400 and 800ms, none waited for beyond the deadline.
+        assert_eq!(summary.statistics.transmitted, 3);
+        assert_eq!(summary.statistics.received, 0);
+        assert_eq!(summary.statistics.loss(), 1.0);
+        assert_eq!(started_at.elapsed(), Duration::from_secs(1));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,339 @@
+//! Statistics of probe outcomes, like the summary of ping(8).
+//!
+//! [`PingStatistics`] of several workers can be merged, see [`PingStatistics::merge`].
+
+use core::time::Duration;
+
+use crate::ProbeOutcome;
+
+//
+/// Sub-buckets per power of two, 1 / 2^5 relative error at most.
+const HISTOGRAM_SUB_BUCKET_BITS: u32 = 5;
+const HISTOGRAM_SUB_BUCKETS: u64 = 1 << HISTOGRAM_SUB_BUCKET_BITS;
+
+/// Gain of the interarrival jitter estimator, RFC 3550 section 6.4.1.
+const JITTER_GAIN: f64 = 1.0 / 16.0;
+
+//
+/// Log-linear histogram of RTTs in microseconds, exact below `HISTOGRAM_SUB_BUCKETS`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+}
+
+impl Histogram {
+    fn index(value: u64) -> usize {
+        if value < HISTOGRAM_SUB_BUCKETS {
+            return value as usize;
+        }
+        let shift = 63 - value.leading_zeros() - HISTOGRAM_SUB_BUCKET_BITS;
+        let sub_bucket = (value >> shift) - HISTOGRAM_SUB_BUCKETS;
+        ((shift as u64 + 1) * HISTOGRAM_SUB_BUCKETS + sub_bucket) as usize
+    }
+
+    /// Middle of the bucket.
+    fn value(index: usize) -> u64 {
+        let index = index as u64;
+        if index < HISTOGRAM_SUB_BUCKETS {
+            return index;
+        }
+        let shift = index / HISTOGRAM_SUB_BUCKETS - 1;
+        let sub_bucket = index % HISTOGRAM_SUB_BUCKETS;
+        ((HISTOGRAM_SUB_BUCKETS + sub_bucket) << shift) + ((1 << shift) >> 1)
+    }
+
+    fn record(&mut self, value: u64) {
+        let index = Self::index(value);
+        if self.counts.len() <= index {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += 1;
+        self.total += 1;
+    }
+
+    fn merge(&mut self, other: &Self) {
+        if self.counts.len() < other.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (a, b) in self.counts.iter_mut().zip(&other.counts) {
+            *a += b;
+        }
+        self.total += other.total;
+    }
+
+    fn quantile(&self, q: f64) -> Option<u64> {
+        if self.total == 0 {
+            return None;
+        }
+        let rank = ((q * self.total as f64).ceil() as u64).clamp(1, self.total);
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Some(Self::value(index));
+            }
+        }
+        None
+    }
+}
+
+//
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PingStatistics {
+    pub transmitted: u64,
+    /// Echo replies carrying the payload sent.
+    pub received: u64,
+    /// ICMP errors, payload mismatches and send failures.
+    pub errors: u64,
+    rtt_min: Option<Duration>,
+    rtt_max: Option<Duration>,
+    // In seconds.
+    rtt_sum: f64,
+    rtt_sum_squares: f64,
+    last_rtt: Option<Duration>,
+    // In seconds, over `variation_samples` consecutive replies.
+    jitter: f64,
+    ipdv_sum_abs: f64,
+    ipdv_max_abs: Duration,
+    variation_samples: u64,
+    histogram: Histogram,
+}
+
+impl PingStatistics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, outcome: &ProbeOutcome) {
+        self.transmitted += 1;
+        match outcome {
+            ProbeOutcome::Reply { rtt, .. } => self.record_rtt(*rtt),
+            ProbeOutcome::Timeout => {}
+            _ => self.errors += 1,
+        }
+    }
+
+    /// The probe could not be sent, counted as an error.
+    pub fn record_failure(&mut self) {
+        self.transmitted += 1;
+        self.errors += 1;
+    }
+
+    fn record_rtt(&mut self, rtt: Duration) {
+        self.received += 1;
+        self.rtt_min = Some(self.rtt_min.map_or(rtt, |x| x.min(rtt)));
+        self.rtt_max = Some(self.rtt_max.map_or(rtt, |x| x.max(rtt)));
+        self.rtt_sum += rtt.as_secs_f64();
+        self.rtt_sum_squares += rtt.as_secs_f64() * rtt.as_secs_f64();
+
+        // The RTT stands in for the transit time, the clocks of both ends are not synchronized.
+        if let Some(last_rtt) = self.last_rtt {
+            let ipdv = rtt.as_secs_f64() - last_rtt.as_secs_f64();
+            self.jitter += (ipdv.abs() - self.jitter) * JITTER_GAIN;
+            self.ipdv_sum_abs += ipdv.abs();
+            self.ipdv_max_abs = self.ipdv_max_abs.max(rtt.abs_diff(last_rtt));
+            self.variation_samples += 1;
+        }
+        self.last_rtt = Some(rtt);
+
+        self.histogram
+            .record(rtt.as_micros().min(u64::MAX as u128) as u64);
+    }
+
+    /// Adds the statistics of another worker.
+    ///
+    /// The jitter is then the mean of both, weighted by their samples.
+    pub fn merge(&mut self, other: &Self) {
+        self.transmitted += other.transmitted;
+        self.received += other.received;
+        self.errors += other.errors;
+        self.rtt_min = match (self.rtt_min, other.rtt_min) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.rtt_max = self.rtt_max.max(other.rtt_max);
+        self.rtt_sum += other.rtt_sum;
+        self.rtt_sum_squares += other.rtt_sum_squares;
+        self.last_rtt = self.last_rtt.or(other.last_rtt);
+        let variation_samples = self.variation_samples + other.variation_samples;
+        if variation_samples > 0 {
+            self.jitter = (self.jitter * self.variation_samples as f64
+                + other.jitter * other.variation_samples as f64)
+                / variation_samples as f64;
+        }
+        self.ipdv_sum_abs += other.ipdv_sum_abs;
+        self.ipdv_max_abs = self.ipdv_max_abs.max(other.ipdv_max_abs);
+        self.variation_samples = variation_samples;
+        self.histogram.merge(&other.histogram);
+    }
+
+    /// In `0.0..=1.0`, the errors are lost too.
+    pub fn loss(&self) -> f64 {
+        if self.transmitted == 0 {
+            return 0.0;
+        }
+        1.0 - self.received as f64 / self.transmitted as f64
+    }
+
+    pub fn rtt_min(&self) -> Option<Duration> {
+        self.rtt_min
+    }
+
+    pub fn rtt_avg(&self) -> Option<Duration> {
+        (self.received > 0).then(|| Duration::from_secs_f64(self.rtt_sum / self.received as f64))
+    }
+
+    pub fn rtt_max(&self) -> Option<Duration> {
+        self.rtt_max
+    }
+
+    /// Standard deviation, the mdev of ping(8).
+    pub fn rtt_mdev(&self) -> Option<Duration> {
+        (self.received > 0).then(|| {
+            let avg = self.rtt_sum / self.received as f64;
+            let variance = self.rtt_sum_squares / self.received as f64 - avg * avg;
+            Duration::from_secs_f64(variance.max(0.0).sqrt())
+        })
+    }
+
+    /// Interarrival jitter of RFC 3550.
+    pub fn jitter(&self) -> Option<Duration> {
+        (self.variation_samples > 0).then(|| Duration::from_secs_f64(self.jitter))
+    }
+
+    /// Mean of the absolute IP packet delay variation between consecutive replies, RFC 3393.
+    pub fn ipdv_mean(&self) -> Option<Duration> {
+        (self.variation_samples > 0)
+            .then(|| Duration::from_secs_f64(self.ipdv_sum_abs / self.variation_samples as f64))
+    }
+
+    pub fn ipdv_max(&self) -> Option<Duration> {
+        (self.variation_samples > 0).then_some(self.ipdv_max_abs)
+    }
+
+    /// `q` in `0.0..=1.0`, 3% relative error at most, within the min and max.
+    pub fn rtt_quantile(&self, q: f64) -> Option<Duration> {
+        let value = Duration::from_micros(self.histogram.quantile(q)?);
+        Some(value.clamp(self.rtt_min?, self.rtt_max?))
+    }
+
+    pub fn rtt_p50(&self) -> Option<Duration> {
+        self.rtt_quantile(0.5)
+    }
+
+    pub fn rtt_p90(&self) -> Option<Duration> {
+        self.rtt_quantile(0.9)
+    }
+
+    pub fn rtt_p99(&self) -> Option<Duration> {
+        self.rtt_quantile(0.99)
+    }
+}
+
+impl core::ops::Add for PingStatistics {
+    type Output = Self;
+
+    fn add(mut self, rhs: Self) -> Self::Output {
+        self.merge(&rhs);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    fn reply(ms: u64) -> ProbeOutcome {
+        ProbeOutcome::Reply {
+            from: Ipv4Addr::new(10, 0, 0, 2).into(),
+            rtt: Duration::from_millis(ms),
+            ttl: None,
+            ip_options: None,
+            size: 42,
+        }
+    }
+
+    fn assert_near(a: Option<Duration>, b: Duration) {
+        let a = a.expect("Some");
+        assert!(
+            a.abs_diff(b) <= Duration::from_micros(1),
+            "{a:?} not near {b:?}"
+        );
+    }
+
+    #[test]
+    fn test_histogram() {
+        for value in [0, 1, 31, 32, 33, 63, 64, 65, 1000, 123_456_789] {
+            let index = Histogram::index(value);
+            let middle = Histogram::value(index);
+            assert!(
+                middle.abs_diff(value) as f64 <= value as f64 / 32.0,
+                "{value}"
+            );
+            assert!(Histogram::index(value + 1) >= index);
+        }
+    }
+
+    #[test]
+    fn test_statistics() {
+        let mut statistics = PingStatistics::new();
+        assert_eq!(statistics.loss(), 0.0);
+        assert_eq!(statistics.rtt_avg(), None);
+        assert_eq!(statistics.jitter(), None);
+
+        for ms in [10, 20, 30] {
+            statistics.record(&reply(ms));
+        }
+        statistics.record(&ProbeOutcome::Timeout);
+
+        assert_eq!(statistics.transmitted, 4);
+        assert_eq!(statistics.received, 3);
+        assert_eq!(statistics.loss(), 0.25);
+        assert_eq!(statistics.rtt_min(), Some(Duration::from_millis(10)));
+        assert_near(statistics.rtt_avg(), Duration::from_millis(20));
+        assert_eq!(statistics.rtt_max(), Some(Duration::from_millis(30)));
+        // sqrt((100 + 400 + 900) / 3 - 400)
+        assert_near(statistics.rtt_mdev(), Duration::from_nanos(8_164_966));
+        // 10 / 16, then + (10 - 0.625) / 16
+        assert_near(statistics.jitter(), Duration::from_nanos(1_210_938));
+        assert_near(statistics.ipdv_mean(), Duration::from_millis(10));
+        assert_eq!(statistics.ipdv_max(), Some(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn test_quantile_and_merge() {
+        let mut a = PingStatistics::new();
+        let mut b = PingStatistics::new();
+        let mut all = PingStatistics::new();
+        for ms in 1..=100 {
+            if ms % 2 == 0 { &mut a } else { &mut b }.record(&reply(ms));
+            all.record(&reply(ms));
+        }
+
+        for (q, ms) in [(0.5, 50), (0.9, 90), (0.99, 99)] {
+            let value = all.rtt_quantile(q).expect("Some");
+            assert!(
+                value.abs_diff(Duration::from_millis(ms)) <= Duration::from_millis(ms) / 32,
+                "{q} {value:?}"
+            );
+        }
+        assert_eq!(all.rtt_quantile(0.0), Some(Duration::from_millis(1)));
+        assert!(all.rtt_quantile(1.0) <= all.rtt_max());
+
+        let merged = a.clone() + b.clone();
+        assert_eq!(merged.transmitted, all.transmitted);
+        assert_eq!(merged.rtt_min(), all.rtt_min());
+        assert_eq!(merged.rtt_max(), all.rtt_max());
+        assert_near(merged.rtt_avg(), all.rtt_avg().expect("Some"));
+        assert_near(merged.rtt_mdev(), all.rtt_mdev().expect("Some"));
+        assert_eq!(merged.histogram, all.histogram);
+        assert_eq!(merged.rtt_p99(), all.rtt_p99());
+        // Every other one per worker, 2ms apart.
+        assert_eq!(merged.ipdv_max(), Some(Duration::from_millis(2)));
+
+        assert_eq!(PingStatistics::new() + a.clone(), a);
+    }
+}
@@ -0,0 +1,101 @@
+/*
+sudo cargo run -p icmp-client-cli --bin icmp_client_privsep_helper -- --listen /run/icmp-client.sock --mode 0660
+Or
+cargo install icmp-client-cli
+sudo setcap cap_net_raw+ep $(which icmp_client_privsep_helper)
+icmp_client_privsep_helper --fd 3
+
+Namespaces are refused unless allowed, binds allowed to any local address unless restricted
+icmp_client_privsep_helper --listen /run/icmp-client.sock --allow-netns /var/run/netns/blue --allow-bind 10.0.0.1
+*/
+
+#[cfg(unix)]
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    use std::{
+        env, fs,
+        os::{
+            fd::{FromRawFd as _, OwnedFd},
+            unix::{
+                fs::PermissionsExt as _,
+                net::{UnixListener, UnixStream},
+            },
+        },
+        thread,
+    };
+
+    use icmp_client::privsep::{serve_with_config, ServeConfig};
+
+    let mut listen: Option<String> = None;
+    let mut mode: Option<u32> = None;
+    let mut fd: Option<i32> = None;
+    let mut serve_config = ServeConfig::new();
+    let mut bind_allowlist = vec![];
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listen" => {
+                listen = Some(args.next().ok_or("args listen missing")?);
+            }
+            // Octal, e.g. 0660
+            "--mode" => {
+                let value = args.next().ok_or("args mode missing")?;
+                mode = Some(u32::from_str_radix(&value, 8)?);
+            }
+            // A Unix stream socket inherited from the parent process, e.g. one end of a socketpair.
+            "--fd" => {
+                fd = Some(args.next().ok_or("args fd missing")?.parse()?);
+            }
+            "--allow-bind" => {
+                bind_allowlist.push(args.next().ok_or("args allow-bind missing")?.parse()?);
+            }
+            #[cfg(target_os = "linux")]
+            "--allow-netns" => {
+                let path = args.next().ok_or("args allow-netns missing")?;
+                serve_config =
+                    serve_config.allow_netns(icmp_client::netns::Netns::Path(path.into()));
+            }
+            #[cfg(target_os = "linux")]
+            "--allow-netns-pid" => {
+                let pid = args.next().ok_or("args allow-netns-pid missing")?.parse()?;
+                serve_config = serve_config.allow_netns(icmp_client::netns::Netns::Pid(pid));
+            }
+            _ => return Err(format!("args {arg} unknown").into()),
+        }
+    }
+    if !bind_allowlist.is_empty() {
+        serve_config = serve_config.bind_allowlist(bind_allowlist);
+    }
+
+    match (listen, fd) {
+        (Some(path), None) => {
+            let _ = fs::remove_file(&path);
+            let listener = UnixListener::bind(&path)?;
+            if let Some(mode) = mode {
+                fs::set_permissions(&path, fs::Permissions::from_mode(mode))?;
+            }
+
+            for stream in listener.incoming() {
+                let stream = stream?;
+                let serve_config = serve_config.clone();
+                thread::spawn(move || {
+                    if let Err(err) = serve_with_config(&stream, &serve_config) {
+                        eprintln!("serve failed, err:{err}");
+                    }
+                });
+            }
+            Ok(())
+        }
+        (None, Some(fd)) => {
+            let stream = UnixStream::from(unsafe { OwnedFd::from_raw_fd(fd) });
+            serve_with_config(&stream, &serve_config)?;
+            Ok(())
+        }
+        _ => Err("args listen or fd required".into()),
+    }
+}
+
+#[cfg(not(unix))]
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    Err("unsupported".into())
+}
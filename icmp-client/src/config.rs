@@ -1,14 +1,47 @@
 use core::num::NonZeroU32;
 use std::net::SocketAddr;
 
+//
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SocketType {
+    /// Unprivileged ICMP socket, see `net.ipv4.ping_group_range` on Linux.
+    #[default]
+    Dgram,
+    /// Needs root or CAP_NET_RAW, IPv4 datagrams are received with their IP header.
+    Raw,
+}
+
 //
 #[derive(Debug, Clone, Default)]
 pub struct Config {
-    is_ipv6: bool,
+    pub(crate) is_ipv6: bool,
+    pub socket_type: SocketType,
     pub bind: Option<SocketAddr>,
+    /// Only this peer is sent to and received from, with `send` and `recv`.
+    pub connect: Option<SocketAddr>,
     pub interface_index: Option<NonZeroU32>,
     pub ttl: Option<u32>,
+    /// IP_OPTIONS, e.g. `icmp_packet::ip_options::render_record_route_option_bytes()`, IPv4 only.
+    pub ip_options: Option<Vec<u8>>,
+    /// SO_RCVBUF, raise it when probing many hosts at once, see `AsyncClient::stats`.
+    pub recv_buffer_size: Option<usize>,
+    /// SO_SNDBUF
+    pub send_buffer_size: Option<usize>,
     pub fib: Option<u32>,
+    /// ICMP types received, Linux only.
+    pub icmp_filter: Option<Vec<u8>>,
+    /// Identifier of the echo replies and ICMP error types received, with a BPF program, Linux only.
+    pub echo_reply_filter: Option<(u16, Vec<u8>)>,
+    /// Network namespace the socket is created in.
+    #[cfg(target_os = "linux")]
+    pub netns: Option<crate::netns::Netns>,
+    #[cfg(feature = "impl_sim")]
+    pub sim_network: Option<crate::impl_sim::Network>,
+    #[cfg(feature = "impl_tun")]
+    pub tun_name: Option<String>,
+    /// MAC address of the gateway, or of the peer on the same link.
+    #[cfg(feature = "impl_packet")]
+    pub packet_next_hop_mac: Option<[u8; 6]>,
 }
 
 impl Config {
@@ -26,14 +59,28 @@ impl Config {
     pub fn is_ipv6(&self) -> bool {
         self.bind.map(|x| x.is_ipv6()).unwrap_or(self.is_ipv6)
     }
+
+    pub fn is_raw(&self) -> bool {
+        self.socket_type == SocketType::Raw
+    }
 }
 
 impl Config {
+    pub fn socket_type(mut self, socket_type: SocketType) -> Self {
+        self.socket_type = socket_type;
+        self
+    }
+
     pub fn bind(mut self, bind: SocketAddr) -> Self {
         self.bind = Some(bind);
         self
     }
 
+    pub fn connect(mut self, connect: SocketAddr) -> Self {
+        self.connect = Some(connect);
+        self
+    }
+
     pub fn interface_index(mut self, interface_index: NonZeroU32) -> Self {
         self.interface_index = Some(interface_index);
         self
@@ -44,8 +91,57 @@ impl Config {
         self
     }
 
+    pub fn ip_options(mut self, ip_options: impl Into<Vec<u8>>) -> Self {
+        self.ip_options = Some(ip_options.into());
+        self
+    }
+
+    pub fn recv_buffer_size(mut self, recv_buffer_size: usize) -> Self {
+        self.recv_buffer_size = Some(recv_buffer_size);
+        self
+    }
+
+    pub fn send_buffer_size(mut self, send_buffer_size: usize) -> Self {
+        self.send_buffer_size = Some(send_buffer_size);
+        self
+    }
+
     pub fn fib(mut self, fib: u32) -> Self {
         self.fib = Some(fib);
         self
     }
+
+    pub fn icmp_filter(mut self, icmp_types: impl Into<Vec<u8>>) -> Self {
+        self.icmp_filter = Some(icmp_types.into());
+        self
+    }
+
+    pub fn echo_reply_filter(mut self, identifier: u16, error_types: impl Into<Vec<u8>>) -> Self {
+        self.echo_reply_filter = Some((identifier, error_types.into()));
+        self
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn netns(mut self, netns: crate::netns::Netns) -> Self {
+        self.netns = Some(netns);
+        self
+    }
+
+    #[cfg(feature = "impl_sim")]
+    pub fn sim_network(mut self, sim_network: crate::impl_sim::Network) -> Self {
+        self.sim_network = Some(sim_network);
+        self
+    }
+
+    #[cfg(feature = "impl_tun")]
+    pub fn tun_name(mut self, tun_name: impl Into<String>) -> Self {
+        self.tun_name = Some(tun_name.into());
+        self
+    }
+
+    #[cfg(feature = "impl_packet")]
+    pub fn packet_next_hop_mac(mut self, packet_next_hop_mac: [u8; 6]) -> Self {
+        self.packet_next_hop_mac = Some(packet_next_hop_mac);
+        self
+    }
 }
@@ -0,0 +1,258 @@
+//! One client for both address families, the socket is picked by the destination address.
+//!
+//! Each family is opened on its first `send_to`, `recv_from` waits on the opened ones.
+
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll, Waker},
+};
+use std::{
+    io::{Error as IoError, ErrorKind as IoErrorKind},
+    net::SocketAddr,
+    sync::{Mutex, OnceLock},
+};
+
+use crate::{config::Config, AsyncClient, AsyncClientWithConfigError, ReadBuf, Stats};
+
+//
+#[derive(Debug)]
+pub struct DualStackClient<C> {
+    v4_config: Config,
+    v6_config: Config,
+    v4_client: OnceLock<C>,
+    v6_client: OnceLock<C>,
+    // Serializes the opening, and wakes `recv_from` once a family is opened.
+    opening: Mutex<Option<Waker>>,
+    // Alternates the family polled first, so one cannot starve the other.
+    v6_first: AtomicBool,
+}
+
+impl<C> DualStackClient<C>
+where
+    C: AsyncClient,
+{
+    pub fn new(v4_config: Config, v6_config: Config) -> Self {
+        Self {
+            v4_config: Config {
+                is_ipv6: false,
+                ..v4_config
+            },
+            v6_config: Config {
+                is_ipv6: true,
+                ..v6_config
+            },
+            v4_client: OnceLock::new(),
+            v6_client: OnceLock::new(),
+            opening: Mutex::new(None),
+            v6_first: AtomicBool::new(false),
+        }
+    }
+
+    pub fn v4_client(&self) -> Option<&C> {
+        self.v4_client.get()
+    }
+
+    pub fn v6_client(&self) -> Option<&C> {
+        self.v6_client.get()
+    }
+
+    /// Opens the family of `addr` unless already open.
+    pub fn open(&self, addr: &SocketAddr) -> Result<&C, AsyncClientWithConfigError> {
+        let (client, config) = if addr.is_ipv6() {
+            (&self.v6_client, &self.v6_config)
+        } else {
+            (&self.v4_client, &self.v4_config)
+        };
+        if let Some(client) = client.get() {
+            return Ok(client);
+        }
+
+        let mut opening = self.opening.lock().expect("Never when lock");
+        if client.get().is_none() {
+            let _ = client.set(C::with_config(config)?);
+            if let Some(waker) = opening.take() {
+                waker.wake();
+            }
+        }
+        Ok(client.get().expect("Never when set"))
+    }
+}
+
+impl<C> AsyncClient for DualStackClient<C>
+where
+    C: AsyncClient,
+{
+    /// `config` for both families, its `bind` and `connect` only for theirs.
+    fn with_config(config: &Config) -> Result<Self, AsyncClientWithConfigError> {
+        let for_family = |is_ipv6: bool| {
+            let mut config = config.clone();
+            config.bind = config.bind.filter(|x| x.is_ipv6() == is_ipv6);
+            config.connect = config.connect.filter(|x| x.is_ipv6() == is_ipv6);
+            config
+        };
+        Ok(Self::new(for_family(false), for_family(true)))
+    }
+
+    fn poll_send_to(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        addr: SocketAddr,
+    ) -> Poll<Result<usize, IoError>> {
+        let client = self.open(&addr).map_err(|err| match err {
+            AsyncClientWithConfigError::IcmpV6ProtocolNotSupported(err)
+            | AsyncClientWithConfigError::OtherIoError(err) => err,
+            AsyncClientWithConfigError::PermissionDenied(err) => {
+                IoError::new(IoErrorKind::PermissionDenied, err.to_string())
+            }
+        })?;
+        client.poll_send_to(cx, buf, addr)
+    }
+
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<SocketAddr, IoError>> {
+        let v6_first = self.v6_first.fetch_xor(true, Ordering::Relaxed);
+        let clients = if v6_first {
+            [self.v6_client.get(), self.v4_client.get()]
+        } else {
+            [self.v4_client.get(), self.v6_client.get()]
+        };
+
+        if clients.iter().all(|x| x.is_some()) {
+            return poll_recv_from_any(clients.into_iter().flatten(), cx, buf);
+        }
+
+        // A family may be opened meanwhile, then it has to be polled too.
+        let mut opening = self.opening.lock().expect("Never when lock");
+        match poll_recv_from_any(
+            [self.v4_client.get(), self.v6_client.get()]
+                .into_iter()
+                .flatten(),
+            cx,
+            buf,
+        ) {
+            Poll::Ready(ret) => Poll::Ready(ret),
+            Poll::Pending => {
+                *opening = Some(cx.waker().to_owned());
+                Poll::Pending
+            }
+        }
+    }
+
+    fn stats(&self) -> Stats {
+        [self.v4_client.get(), self.v6_client.get()]
+            .into_iter()
+            .flatten()
+            .map(|x| x.stats())
+            .fold(Stats::default(), |acc, x| acc + x)
+    }
+}
+
+fn poll_recv_from_any<'a, C>(
+    clients: impl Iterator<Item = &'a C>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+) -> Poll<Result<SocketAddr, IoError>>
+where
+    C: AsyncClient + 'a,
+{
+    for client in clients {
+        if let Poll::Ready(ret) = client.poll_recv_from(cx, buf) {
+            return Poll::Ready(ret);
+        }
+    }
+    Poll::Pending
+}
+
+#[cfg(feature = "impl_tokio")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::time::Duration;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use icmp_packet::{Icmpv4, Icmpv6, PayloadLengthDelimitedEchoRequest};
+
+    use crate::{impl_tokio::Client, AsyncClientExt as _};
+
+    #[tokio::test]
+    async fn test_client() -> Result<(), Box<dyn std::error::Error>> {
+        let client = DualStackClient::<Client>::with_config(&Config::new())?;
+        assert!(client.v4_client().is_none() && client.v6_client().is_none());
+
+        // Pending until a family is opened.
+        let mut buf = vec![0; 1024];
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), client.recv_from(&mut buf))
+                .await
+                .is_err()
+        );
+
+        let echo_request =
+            PayloadLengthDelimitedEchoRequest::new(Some(1.into()), Some(2.into()), b"1234");
+
+        client
+            .send_to(
+                &echo_request.render_v4_packet_bytes(),
+                (Ipv4Addr::LOCALHOST, 0),
+            )
+            .await?;
+        assert!(client.v4_client().is_some() && client.v6_client().is_none());
+        let (n, addr) = client.recv_from(&mut buf).await?;
+        assert_eq!(addr, (Ipv4Addr::LOCALHOST, 0).into());
+        assert!(matches!(
+            Icmpv4::parse_from_packet_bytes(&buf[..n]),
+            Ok(Some(Icmpv4::EchoReply(_)))
+        ));
+
+        client
+            .send_to(
+                &echo_request.render_v6_packet_bytes(),
+                (Ipv6Addr::LOCALHOST, 0),
+            )
+            .await?;
+        assert!(client.v6_client().is_some());
+        let (n, addr) = client.recv_from(&mut buf).await?;
+        assert_eq!(addr, (Ipv6Addr::LOCALHOST, 0).into());
+        assert!(matches!(
+            Icmpv6::parse_from_packet_bytes(&buf[..n]),
+            Ok(Some(Icmpv6::EchoReply(_)))
+        ));
+
+        assert_eq!(client.stats().packets_sent, 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_recv_from_woken_on_open() -> Result<(), Box<dyn std::error::Error>> {
+        let client = std::sync::Arc::new(DualStackClient::<Client>::with_config(&Config::new())?);
+
+        let handle = {
+            let client = client.clone();
+            tokio::spawn(async move {
+                let mut buf = vec![0; 1024];
+                client.recv_from(&mut buf).await.map(|(_, addr)| addr)
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let echo_request =
+            PayloadLengthDelimitedEchoRequest::new(Some(1.into()), Some(2.into()), b"1234");
+        client
+            .send_to(
+                &echo_request.render_v4_packet_bytes(),
+                (Ipv4Addr::LOCALHOST, 0),
+            )
+            .await?;
+
+        let addr = tokio::time::timeout(Duration::from_secs(2), handle).await???;
+        assert_eq!(addr, (Ipv4Addr::LOCALHOST, 0).into());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+//! The futures of [`AsyncClient`], named so that nothing is boxed.
+
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use std::{io::Error as IoError, net::SocketAddr};
+
+use crate::{AsyncClient, ReadBuf};
+
+//
+pub trait AsyncClientExt: AsyncClient {
+    fn send_to<'a, A: Into<SocketAddr>>(&'a self, buf: &'a [u8], addr: A) -> SendTo<'a, Self> {
+        SendTo {
+            client: self,
+            buf,
+            addr: addr.into(),
+        }
+    }
+
+    fn recv_from<'a>(&'a self, buf: &'a mut [u8]) -> RecvFrom<'a, Self> {
+        RecvFrom { client: self, buf }
+    }
+
+    /// On a client connected with `Config::connect`.
+    fn send<'a>(&'a self, buf: &'a [u8]) -> SendConnected<'a, Self> {
+        SendConnected { client: self, buf }
+    }
+
+    /// On a client connected with `Config::connect`, errors about the peer are returned here.
+    fn recv<'a>(&'a self, buf: &'a mut [u8]) -> RecvConnected<'a, Self> {
+        RecvConnected { client: self, buf }
+    }
+}
+
+impl<C> AsyncClientExt for C where C: AsyncClient + ?Sized {}
+
+//
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct SendTo<'a, C: ?Sized> {
+    client: &'a C,
+    buf: &'a [u8],
+    addr: SocketAddr,
+}
+
+impl<C> Future for SendTo<'_, C>
+where
+    C: AsyncClient + ?Sized,
+{
+    type Output = Result<usize, IoError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.client.poll_send_to(cx, self.buf, self.addr)
+    }
+}
+
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct RecvFrom<'a, C: ?Sized> {
+    client: &'a C,
+    buf: &'a mut [u8],
+}
+
+impl<C> Future for RecvFrom<'_, C>
+where
+    C: AsyncClient + ?Sized,
+{
+    type Output = Result<(usize, SocketAddr), IoError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut buf = ReadBuf::new(this.buf);
+        this.client
+            .poll_recv_from(cx, &mut buf)
+            .map_ok(|addr| (buf.filled().len(), addr))
+    }
+}
+
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct SendConnected<'a, C: ?Sized> {
+    client: &'a C,
+    buf: &'a [u8],
+}
+
+impl<C> Future for SendConnected<'_, C>
+where
+    C: AsyncClient + ?Sized,
+{
+    type Output = Result<usize, IoError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.client.poll_send(cx, self.buf)
+    }
+}
+
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct RecvConnected<'a, C: ?Sized> {
+    client: &'a C,
+    buf: &'a mut [u8],
+}
+
+impl<C> Future for RecvConnected<'_, C>
+where
+    C: AsyncClient + ?Sized,
+{
+    type Output = Result<usize, IoError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut buf = ReadBuf::new(this.buf);
+        this.client
+            .poll_recv(cx, &mut buf)
+            .map_ok(|()| buf.filled().len())
+    }
+}
@@ -0,0 +1,189 @@
+//! Kernel-side filtering, so a raw socket on a busy host is not woken up by every ICMP packet.
+//!
+//! Linux only, see `Config::icmp_filter` and `Config::echo_reply_filter`.
+
+use std::io::Error as IoError;
+
+//
+/// Destination Unreachable, Time Exceeded and Parameter Problem.
+pub const ICMPV4_ERROR_TYPES: &[u8] = &[3, 11, 12];
+/// Destination Unreachable, Packet Too Big, Time Exceeded and Parameter Problem.
+pub const ICMPV6_ERROR_TYPES: &[u8] = &[1, 2, 3, 4];
+
+const ICMPV4_ECHO_REPLY: u8 = 0;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+
+// linux/icmp.h and linux/icmpv6.h
+const ICMP_FILTER: libc::c_int = 1;
+const ICMP6_FILTER: libc::c_int = 1;
+
+//
+/// Only the `icmp_types` are received, ICMP_FILTER for IPv4 raw sockets (types below 32), ICMP6_FILTER for IPv6.
+pub fn set_icmp_filter(
+    socket: &socket2::Socket,
+    is_ipv6: bool,
+    icmp_types: &[u8],
+) -> Result<(), IoError> {
+    use std::os::fd::AsRawFd as _;
+
+    // On Linux a set bit blocks the type.
+    let ret = if is_ipv6 {
+        let mut data = [u32::MAX; 8];
+        for icmp_type in icmp_types {
+            data[(icmp_type >> 5) as usize] &= !(1 << (icmp_type & 31));
+        }
+        unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::IPPROTO_ICMPV6,
+                ICMP6_FILTER,
+                data.as_ptr() as *const _,
+                core::mem::size_of_val(&data) as libc::socklen_t,
+            )
+        }
+    } else {
+        let mut data = u32::MAX;
+        for icmp_type in icmp_types.iter().filter(|x| **x < 32) {
+            data &= !(1 << icmp_type);
+        }
+        unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_RAW,
+                ICMP_FILTER,
+                &data as *const u32 as *const _,
+                core::mem::size_of_val(&data) as libc::socklen_t,
+            )
+        }
+    };
+    if ret < 0 {
+        return Err(IoError::last_os_error());
+    }
+    Ok(())
+}
+
+/// Classic BPF program admitting the echo replies with `identifier` and the `error_types`.
+///
+/// IPv4 raw sockets see the IP header first, it is skipped with its IHL.
+pub fn echo_reply_bpf_program(
+    is_ipv6: bool,
+    is_raw: bool,
+    identifier: u16,
+    error_types: &[u8],
+) -> Vec<libc::sock_filter> {
+    use libc::{
+        BPF_B, BPF_H, BPF_IMM, BPF_IND, BPF_JEQ, BPF_JMP, BPF_K, BPF_LD, BPF_LDX, BPF_MSH, BPF_RET,
+    };
+
+    fn stmt(code: u32, k: u32) -> libc::sock_filter {
+        libc::sock_filter {
+            code: code as u16,
+            jt: 0,
+            jf: 0,
+            k,
+        }
+    }
+    fn jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+        libc::sock_filter {
+            code: code as u16,
+            jt,
+            jf,
+            k,
+        }
+    }
+
+    assert!(error_types.len() < u8::MAX as usize);
+    let n = error_types.len() as u8;
+
+    let echo_reply_type = if is_ipv6 {
+        ICMPV6_ECHO_REPLY
+    } else {
+        ICMPV4_ECHO_REPLY
+    };
+
+    let mut program = vec![
+        // X = offset of the ICMP header
+        if !is_ipv6 && is_raw {
+            stmt(BPF_LDX | BPF_B | BPF_MSH, 0)
+        } else {
+            stmt(BPF_LDX | BPF_IMM, 0)
+        },
+        // A = type
+        stmt(BPF_LD | BPF_B | BPF_IND, 0),
+        jump(BPF_JMP | BPF_JEQ | BPF_K, echo_reply_type as u32, 0, 2),
+        // A = identifier
+        stmt(BPF_LD | BPF_H | BPF_IND, 4),
+        jump(BPF_JMP | BPF_JEQ | BPF_K, identifier as u32, n + 1, n),
+    ];
+    for (i, error_type) in error_types.iter().enumerate() {
+        program.push(jump(
+            BPF_JMP | BPF_JEQ | BPF_K,
+            *error_type as u32,
+            n - i as u8,
+            0,
+        ));
+    }
+    program.push(stmt(BPF_RET | BPF_K, 0));
+    program.push(stmt(BPF_RET | BPF_K, u32::MAX));
+
+    program
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::time::Duration;
+    use std::{
+        io::ErrorKind as IoErrorKind,
+        net::{Ipv4Addr, SocketAddr},
+    };
+
+    use icmp_packet::PayloadLengthDelimitedEchoRequest;
+    use socket2::{Domain, Protocol, Socket, Type};
+
+    #[test]
+    fn test_echo_reply_filter() -> Result<(), Box<dyn std::error::Error>> {
+        let socket = match Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4)) {
+            Ok(x) => x,
+            Err(err) if err.kind() == IoErrorKind::PermissionDenied => {
+                eprintln!("skipped, needs CAP_NET_RAW, err:{err}");
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        };
+        socket.set_read_timeout(Some(Duration::from_millis(500)))?;
+        set_icmp_filter(&socket, false, &[ICMPV4_ECHO_REPLY])?;
+        socket.attach_filter(&echo_reply_bpf_program(
+            false,
+            true,
+            0x1234,
+            ICMPV4_ERROR_TYPES,
+        ))?;
+
+        let addr: SocketAddr = (Ipv4Addr::LOCALHOST, 0).into();
+        for identifier in [0x4321, 0x1234] {
+            let echo_request = PayloadLengthDelimitedEchoRequest::new(
+                Some(identifier.into()),
+                Some(1.into()),
+                b"1234",
+            );
+            socket.send_to(&echo_request.render_v4_packet_bytes(), &addr.into())?;
+        }
+
+        // Only the echo reply with our identifier, the echo requests and the other reply are dropped.
+        let mut buf = [core::mem::MaybeUninit::new(0); 1024];
+        let (n, _) = socket.recv_from(&mut buf)?;
+        let bytes = buf[..n]
+            .iter()
+            .map(|x| unsafe { x.assume_init() })
+            .collect::<Vec<_>>();
+        let icmp_bytes = &bytes[((bytes[0] & 0x0f) as usize * 4)..];
+        assert_eq!(icmp_bytes[0], ICMPV4_ECHO_REPLY);
+        assert_eq!(&icmp_bytes[4..6], &0x1234_u16.to_be_bytes());
+
+        assert!(socket.recv_from(&mut buf).is_err());
+
+        Ok(())
+    }
+}
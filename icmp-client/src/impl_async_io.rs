@@ -1,3 +1,4 @@
+use core::task::{ready, Context, Poll};
 use std::{
     io::Error as IoError,
     net::{SocketAddr, UdpSocket},
@@ -5,14 +6,19 @@ use std::{
 };
 
 use async_io::Async;
-use async_trait::async_trait;
 
-use crate::{config::Config, utils::new_std_udp_socket, AsyncClient, AsyncClientWithConfigError};
+use crate::{
+    config::Config,
+    stats::StatsCounter,
+    utils::{apply_config, new_std_udp_socket, socket2_socket_to_std_udp_socket},
+    AsyncClient, AsyncClientWithConfigError, ReadBuf, Stats,
+};
 
 //
 #[derive(Debug, Clone)]
 pub struct Client {
     inner: Arc<Async<UdpSocket>>,
+    stats: Arc<StatsCounter>,
 }
 
 impl Client {
@@ -21,25 +27,150 @@ impl Client {
         let inner = Async::new(udp_socket)?;
         Ok(Self {
             inner: Arc::new(inner),
+            stats: Default::default(),
         })
     }
+
+    /// Adopts an ICMP socket opened elsewhere, e.g. by a privileged parent.
+    ///
+    /// With `config`, its options (bind, ttl, ...) are applied to the socket.
+    pub fn from_socket2(
+        socket: socket2::Socket,
+        config: Option<&Config>,
+    ) -> Result<Self, AsyncClientWithConfigError> {
+        apply_config(&socket, config.unwrap_or(&Config::new()))?;
+        let udp_socket = socket2_socket_to_std_udp_socket(socket);
+        let inner = Async::new(udp_socket)?;
+        Ok(Self {
+            inner: Arc::new(inner),
+            stats: Default::default(),
+        })
+    }
+
+    pub fn from_std(
+        udp_socket: UdpSocket,
+        config: Option<&Config>,
+    ) -> Result<Self, AsyncClientWithConfigError> {
+        Self::from_socket2(udp_socket.into(), config)
+    }
+
+    /// Adopts an inherited file descriptor, e.g. from systemd socket activation,
+    /// with `unsafe { OwnedFd::from_raw_fd(3) }`.
+    #[cfg(unix)]
+    pub fn from_fd(
+        fd: std::os::fd::OwnedFd,
+        config: Option<&Config>,
+    ) -> Result<Self, AsyncClientWithConfigError> {
+        Self::from_socket2(fd.into(), config)
+    }
+
+    // Retries `f` until the socket is not WouldBlock.
+    fn poll_io<T>(
+        &self,
+        cx: &mut Context<'_>,
+        is_read: bool,
+        mut f: impl FnMut(&UdpSocket) -> Result<T, IoError>,
+    ) -> Poll<Result<T, IoError>> {
+        loop {
+            match f(self.inner.get_ref()) {
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {}
+                ret => return Poll::Ready(ret),
+            }
+            if is_read {
+                ready!(self.inner.poll_readable(cx))?;
+            } else {
+                ready!(self.inner.poll_writable(cx))?;
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn poll_recv_from_with_drops(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<Option<SocketAddr>, IoError>> {
+        use std::os::fd::AsRawFd as _;
+
+        let (n, addr, drops) = ready!(self.poll_io(cx, true, |socket| {
+            crate::utils::recv_from_with_drops(socket.as_raw_fd(), buf.unfilled_mut())
+        }))?;
+        buf.advance(n);
+        self.stats.on_received(drops);
+        Poll::Ready(Ok(addr))
+    }
 }
 
-#[async_trait]
 impl AsyncClient for Client {
     fn with_config(config: &Config) -> Result<Self, AsyncClientWithConfigError> {
         Client::new(config)
     }
 
-    async fn send_to<A: Into<SocketAddr> + Send>(
+    fn poll_send_to(
         &self,
+        cx: &mut Context<'_>,
         buf: &[u8],
-        addr: A,
-    ) -> Result<usize, IoError> {
-        self.inner.send_to(buf, addr.into()).await
+        addr: SocketAddr,
+    ) -> Poll<Result<usize, IoError>> {
+        let n = ready!(self.poll_io(cx, false, |socket| socket.send_to(buf, addr)))?;
+        self.stats.on_sent();
+        Poll::Ready(Ok(n))
     }
-    async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), IoError> {
-        self.inner.recv_from(buf).await
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<SocketAddr, IoError>> {
+        #[cfg(target_os = "linux")]
+        {
+            let addr = ready!(self.poll_recv_from_with_drops(cx, buf))?;
+            Poll::Ready(addr.ok_or_else(|| IoError::other("addr missing")))
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let (n, addr) =
+                ready!(self.poll_io(cx, true, |socket| socket.recv_from(buf.unfilled_mut())))?;
+            buf.advance(n);
+            self.stats.on_received(None);
+            Poll::Ready(Ok(addr))
+        }
+    }
+
+    fn stats(&self) -> Stats {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::fd::AsRawFd as _;
+
+            self.stats
+                .get(crate::utils::socket_drops(self.inner.as_raw_fd()))
+        }
+        #[cfg(not(target_os = "linux"))]
+        self.stats.get(None)
+    }
+
+    fn poll_send(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize, IoError>> {
+        let n = ready!(self.poll_io(cx, false, |socket| socket.send(buf)))?;
+        self.stats.on_sent();
+        Poll::Ready(Ok(n))
+    }
+    fn poll_recv(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<(), IoError>> {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::fd::AsRawFd as _;
+
+            let ret = ready!(self.poll_recv_from_with_drops(cx, buf));
+            if ret.is_err() {
+                crate::utils::drain_error_queue(self.inner.as_raw_fd());
+            }
+            Poll::Ready(ret.map(|_| ()))
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let n = ready!(self.poll_io(cx, true, |socket| socket.recv(buf.unfilled_mut())))?;
+            buf.advance(n);
+            self.stats.on_received(None);
+            Poll::Ready(Ok(()))
+        }
     }
 }
 
@@ -0,0 +1,567 @@
+//! Client over an AF_PACKET socket, for high-rate sweeps, needs CAP_NET_RAW.
+//!
+//! Whole Ethernet frames are built around the outgoing ICMP message and sent bypassing the qdisc,
+//! with a `send` per frame, there is no TX ring. Incoming frames are read from a TPACKET_V3 ring
+//! mapped in memory, without a syscall per packet.
+//! What is handed out is the same as with `impl_tun`, so `PingClient<impl_packet::Client>`
+//! correlates the replies like with the sockets.
+//!
+//! The source address is the `bind` ip of the config, the interface is `interface_index`,
+//! and the frames go to `packet_next_hop_mac`, there is no ARP / NDP here.
+//! `recv_buffer_size` is the size of the ring, the kernel retires a block at the latest every
+//! [`RING_BLOCK_TIMEOUT_MS`], which adds up to that much to the measured round-trip time.
+//!
+//! AF_XDP is not implemented, it needs an XDP program on the interface and the UMEM to be managed.
+
+use core::task::{ready, Context, Poll};
+use std::{
+    io::{Error as IoError, ErrorKind as IoErrorKind},
+    net::{IpAddr, SocketAddr},
+    os::fd::{AsRawFd as _, FromRawFd as _, OwnedFd, RawFd},
+    sync::{
+        atomic::{fence, AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use icmp_packet::pnet_packet::ethernet::{
+    EtherType, EtherTypes, EthernetPacket, MutableEthernetPacket,
+};
+use tokio::io::unix::AsyncFd;
+
+use crate::{
+    config::Config,
+    ip_stack::{parse_ip_packet_bytes, render_ip_packet_bytes},
+    stats::StatsCounter,
+    AsyncClient, AsyncClientWithConfigError, ReadBuf, Stats,
+};
+
+//
+pub const DEFAULT_TTL: u8 = 64;
+
+pub const RING_BLOCK_SIZE: usize = 1 << 20;
+pub const RING_BLOCK_NR_DEFAULT: usize = 8;
+pub const RING_FRAME_SIZE: usize = 2048;
+pub const RING_BLOCK_TIMEOUT_MS: u32 = 1;
+
+const ETHERNET_HEADER_SIZE: usize = 14;
+// enum tpacket_versions
+const TPACKET_V3: libc::c_int = 2;
+
+//
+#[derive(Debug, Clone)]
+pub struct Client {
+    inner: Arc<Inner>,
+    stats: Arc<StatsCounter>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    fd: AsyncFd<OwnedFd>,
+    ring: Mutex<Ring>,
+    local: IpAddr,
+    local_mac: [u8; 6],
+    next_hop_mac: [u8; 6],
+    ttl: u8,
+    is_raw: bool,
+    kernel_drops: AtomicU32,
+}
+
+impl Client {
+    pub fn new(
+        interface_index: u32,
+        next_hop_mac: [u8; 6],
+        config: &Config,
+    ) -> Result<Self, AsyncClientWithConfigError> {
+        let local = match config.bind.map(|x| x.ip()) {
+            Some(ip) if !ip.is_unspecified() => ip,
+            _ => {
+                return Err(
+                    IoError::new(IoErrorKind::InvalidInput, "config bind ip required").into(),
+                )
+            }
+        };
+        let ether_type = match local {
+            IpAddr::V4(_) => EtherTypes::Ipv4,
+            IpAddr::V6(_) => EtherTypes::Ipv6,
+        };
+
+        let fd = open_packet_socket(ether_type)?;
+        let block_nr = config
+            .recv_buffer_size
+            .map(|x| (x / RING_BLOCK_SIZE).max(1))
+            .unwrap_or(RING_BLOCK_NR_DEFAULT);
+        let ring = Ring::new(fd.as_raw_fd(), block_nr)?;
+        let local_mac = bind_packet_socket(fd.as_raw_fd(), ether_type, interface_index)?;
+
+        // SAFETY: `OwnedFd` keeps the fd open as long as the `AsyncFd`.
+        let fd = unsafe { AsyncFd::register(fd).map_err(IoError::from)? };
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                fd,
+                ring: Mutex::new(ring),
+                local,
+                local_mac,
+                next_hop_mac,
+                ttl: config
+                    .ttl
+                    .map(|x| x.min(u8::MAX as u32) as u8)
+                    .unwrap_or(DEFAULT_TTL),
+                is_raw: config.is_raw(),
+                kernel_drops: AtomicU32::new(0),
+            }),
+            stats: Default::default(),
+        })
+    }
+
+    pub fn local_addr(&self) -> IpAddr {
+        self.inner.local
+    }
+
+    pub fn local_mac(&self) -> [u8; 6] {
+        self.inner.local_mac
+    }
+
+    fn render_frame_bytes(&self, buf: &[u8], destination: IpAddr) -> Result<Vec<u8>, IoError> {
+        let ip_bytes = render_ip_packet_bytes(self.inner.local, self.inner.ttl, buf, destination)?;
+
+        let mut bytes = vec![0; ETHERNET_HEADER_SIZE + ip_bytes.len()];
+        let mut ethernet_packet =
+            MutableEthernetPacket::new(&mut bytes).expect("Never when MutableEthernetPacket::new");
+        ethernet_packet.set_destination(self.inner.next_hop_mac.into());
+        ethernet_packet.set_source(self.inner.local_mac.into());
+        ethernet_packet.set_ethertype(match self.inner.local {
+            IpAddr::V4(_) => EtherTypes::Ipv4,
+            IpAddr::V6(_) => EtherTypes::Ipv6,
+        });
+        bytes[ETHERNET_HEADER_SIZE..].copy_from_slice(&ip_bytes);
+        Ok(bytes)
+    }
+
+    // Copies the next ICMP message of the ring into `buf`, `None` when the ring is drained.
+    fn try_recv_from(&self, buf: &mut ReadBuf<'_>) -> Option<SocketAddr> {
+        let mut ring = self.inner.ring.lock().expect("Never when ring lock");
+        while let Some(frame) = ring.next_frame() {
+            let ip_bytes = match EthernetPacket::new(frame) {
+                Some(ethernet_packet)
+                    if matches!(
+                        (ethernet_packet.get_ethertype(), self.inner.local),
+                        (EtherTypes::Ipv4, IpAddr::V4(_)) | (EtherTypes::Ipv6, IpAddr::V6(_))
+                    ) =>
+                {
+                    &frame[ETHERNET_HEADER_SIZE..]
+                }
+                _ => continue,
+            };
+            if let Some((start, end, source)) =
+                parse_ip_packet_bytes(self.inner.local, self.inner.is_raw, ip_bytes)
+            {
+                buf.put_slice(&ip_bytes[start..end]);
+                self.stats.on_received(None);
+                return Some((source, 0).into());
+            }
+        }
+        None
+    }
+}
+
+impl AsyncClient for Client {
+    fn with_config(config: &Config) -> Result<Self, AsyncClientWithConfigError> {
+        let interface_index = config.interface_index.ok_or_else(|| {
+            IoError::new(IoErrorKind::InvalidInput, "config interface_index missing")
+        })?;
+        let next_hop_mac = config.packet_next_hop_mac.ok_or_else(|| {
+            IoError::new(
+                IoErrorKind::InvalidInput,
+                "config packet_next_hop_mac missing",
+            )
+        })?;
+        Client::new(interface_index.get(), next_hop_mac, config)
+    }
+
+    fn poll_send_to(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        addr: SocketAddr,
+    ) -> Poll<Result<usize, IoError>> {
+        let bytes = self.render_frame_bytes(buf, addr.ip())?;
+
+        loop {
+            let mut guard = ready!(self.inner.fd.poll_write_ready(cx))?;
+            match guard.try_io(|fd| {
+                let n = unsafe {
+                    libc::send(
+                        fd.as_raw_fd(),
+                        bytes.as_ptr() as *const _,
+                        bytes.len(),
+                        libc::MSG_DONTWAIT,
+                    )
+                };
+                if n < 0 {
+                    Err(IoError::last_os_error())
+                } else {
+                    Ok(n as usize)
+                }
+            }) {
+                Ok(ret) => {
+                    ret?;
+                    self.stats.on_sent();
+                    return Poll::Ready(Ok(buf.len()));
+                }
+                Err(_would_block) => continue,
+            }
+        }
+    }
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<SocketAddr, IoError>> {
+        loop {
+            let mut guard = ready!(self.inner.fd.poll_read_ready(cx))?;
+            if let Some(addr) = self.try_recv_from(buf) {
+                return Poll::Ready(Ok(addr));
+            }
+            // Only cleared if no block was retired since the readiness was reported.
+            guard.clear_ready();
+        }
+    }
+
+    fn stats(&self) -> Stats {
+        // Reset by the kernel on each read.
+        let mut tpacket_stats: libc::tpacket_stats_v3 = unsafe { core::mem::zeroed() };
+        let mut len = core::mem::size_of_val(&tpacket_stats) as libc::socklen_t;
+        if unsafe {
+            libc::getsockopt(
+                self.inner.fd.as_raw_fd(),
+                libc::SOL_PACKET,
+                libc::PACKET_STATISTICS,
+                &mut tpacket_stats as *mut libc::tpacket_stats_v3 as *mut _,
+                &mut len,
+            )
+        } == 0
+        {
+            self.inner
+                .kernel_drops
+                .fetch_add(tpacket_stats.tp_drops, Ordering::Relaxed);
+        }
+        self.stats
+            .get(Some(self.inner.kernel_drops.load(Ordering::Relaxed)))
+    }
+}
+
+//
+#[derive(Debug)]
+struct Ring {
+    ptr: *mut u8,
+    block_nr: usize,
+    // Block being read, the number of its packets read and the offset of the next one.
+    block: usize,
+    packet: u32,
+    offset: usize,
+}
+
+// SAFETY: the mapping is only accessed behind the `Mutex` of `Inner`.
+unsafe impl Send for Ring {}
+
+impl Ring {
+    fn new(fd: RawFd, block_nr: usize) -> Result<Self, IoError> {
+        setsockopt(fd, libc::PACKET_VERSION, &TPACKET_V3)?;
+
+        let req = libc::tpacket_req3 {
+            tp_block_size: RING_BLOCK_SIZE as libc::c_uint,
+            tp_block_nr: block_nr as libc::c_uint,
+            tp_frame_size: RING_FRAME_SIZE as libc::c_uint,
+            tp_frame_nr: (RING_BLOCK_SIZE / RING_FRAME_SIZE * block_nr) as libc::c_uint,
+            tp_retire_blk_tov: RING_BLOCK_TIMEOUT_MS,
+            tp_sizeof_priv: 0,
+            tp_feature_req_word: 0,
+        };
+        setsockopt(fd, libc::PACKET_RX_RING, &req)?;
+
+        let ptr = unsafe {
+            libc::mmap(
+                core::ptr::null_mut(),
+                RING_BLOCK_SIZE * block_nr,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(IoError::last_os_error());
+        }
+
+        Ok(Self {
+            ptr: ptr as *mut u8,
+            block_nr,
+            block: 0,
+            packet: 0,
+            offset: 0,
+        })
+    }
+
+    fn block_desc(&self) -> *mut libc::tpacket_block_desc {
+        unsafe { self.ptr.add(self.block * RING_BLOCK_SIZE) as *mut libc::tpacket_block_desc }
+    }
+
+    // The frames of the blocks handed to user space, the outgoing ones skipped.
+    fn next_frame(&mut self) -> Option<&[u8]> {
+        loop {
+            let block_desc = self.block_desc();
+            let bh1 = unsafe { core::ptr::addr_of_mut!((*block_desc).hdr.bh1) };
+            let block_status = unsafe { core::ptr::read_volatile(&(*bh1).block_status) };
+            if block_status & libc::TP_STATUS_USER == 0 {
+                return None;
+            }
+            fence(Ordering::Acquire);
+
+            let num_pkts = unsafe { (*bh1).num_pkts };
+            if self.packet >= num_pkts {
+                // Back to the kernel.
+                fence(Ordering::Release);
+                unsafe {
+                    core::ptr::write_volatile(&mut (*bh1).block_status, libc::TP_STATUS_KERNEL)
+                };
+                self.block = (self.block + 1) % self.block_nr;
+                self.packet = 0;
+                self.offset = 0;
+                continue;
+            }
+            if self.packet == 0 {
+                self.offset = unsafe { (*bh1).offset_to_first_pkt } as usize;
+            }
+
+            let block =
+                unsafe { core::slice::from_raw_parts(block_desc as *const u8, RING_BLOCK_SIZE) };
+            let header = unsafe {
+                core::ptr::read_unaligned(block[self.offset..].as_ptr() as *const libc::tpacket3_hdr)
+            };
+            // The sockaddr_ll follows the aligned tpacket3_hdr.
+            let sockaddr_ll = unsafe {
+                core::ptr::read_unaligned(
+                    block[self.offset + tpacket_align(core::mem::size_of::<libc::tpacket3_hdr>())..]
+                        .as_ptr() as *const libc::sockaddr_ll,
+                )
+            };
+            let start = self.offset + header.tp_mac as usize;
+            let end = (start + header.tp_snaplen as usize).min(RING_BLOCK_SIZE);
+
+            self.packet += 1;
+            self.offset += header.tp_next_offset as usize;
+
+            if sockaddr_ll.sll_pkttype == libc::PACKET_OUTGOING {
+                continue;
+            }
+            return Some(&block[start..end]);
+        }
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr as *mut _, RING_BLOCK_SIZE * self.block_nr) };
+    }
+}
+
+//
+fn tpacket_align(x: usize) -> usize {
+    const TPACKET_ALIGNMENT: usize = 16;
+    (x + TPACKET_ALIGNMENT - 1) & !(TPACKET_ALIGNMENT - 1)
+}
+
+fn setsockopt<T>(fd: RawFd, name: libc::c_int, value: &T) -> Result<(), IoError> {
+    if unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_PACKET,
+            name,
+            value as *const T as *const _,
+            core::mem::size_of::<T>() as libc::socklen_t,
+        )
+    } < 0
+    {
+        return Err(IoError::last_os_error());
+    }
+    Ok(())
+}
+
+fn open_packet_socket(ether_type: EtherType) -> Result<OwnedFd, IoError> {
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_PACKET,
+            libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+            ether_type.0.to_be() as libc::c_int,
+        )
+    };
+    if fd < 0 {
+        return Err(IoError::last_os_error());
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    setsockopt(
+        fd.as_raw_fd(),
+        libc::PACKET_QDISC_BYPASS,
+        &(1 as libc::c_int),
+    )?;
+    // Since Linux 4.20, the outgoing frames are skipped anyway.
+    let _ = setsockopt(
+        fd.as_raw_fd(),
+        libc::PACKET_IGNORE_OUTGOING,
+        &(1 as libc::c_int),
+    );
+
+    Ok(fd)
+}
+
+// Returns the MAC address of the interface.
+fn bind_packet_socket(
+    fd: RawFd,
+    ether_type: EtherType,
+    interface_index: u32,
+) -> Result<[u8; 6], IoError> {
+    let mut sockaddr_ll: libc::sockaddr_ll = unsafe { core::mem::zeroed() };
+    sockaddr_ll.sll_family = libc::AF_PACKET as libc::c_ushort;
+    sockaddr_ll.sll_protocol = ether_type.0.to_be();
+    sockaddr_ll.sll_ifindex = interface_index as libc::c_int;
+    let mut len = core::mem::size_of_val(&sockaddr_ll) as libc::socklen_t;
+    if unsafe {
+        libc::bind(
+            fd,
+            &sockaddr_ll as *const libc::sockaddr_ll as *const _,
+            len,
+        )
+    } < 0
+    {
+        return Err(IoError::last_os_error());
+    }
+
+    if unsafe {
+        libc::getsockname(
+            fd,
+            &mut sockaddr_ll as *mut libc::sockaddr_ll as *mut _,
+            &mut len,
+        )
+    } < 0
+    {
+        return Err(IoError::last_os_error());
+    }
+    let mut mac = [0; 6];
+    mac.copy_from_slice(&sockaddr_ll.sll_addr[..6]);
+    Ok(mac)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::AsyncClientExt as _;
+
+    use core::time::Duration;
+    use std::net::Ipv4Addr;
+
+    use icmp_packet::{Icmpv4, PayloadLengthDelimitedEchoRequest};
+
+    #[test]
+    fn test_client() -> Result<(), Box<dyn std::error::Error>> {
+        // Frames injected on lo, from and to 127.0.0.1, are martians without route_localnet
+        // and accept_local, so in a new namespace.
+        std::thread::spawn(|| -> Result<(), String> {
+            match (|| -> Result<(), Box<dyn std::error::Error>> {
+                if unsafe { libc::unshare(libc::CLONE_NEWNET) } < 0 {
+                    return Err(IoError::last_os_error().into());
+                }
+                set_lo_up()?;
+                for name in ["route_localnet", "accept_local"] {
+                    std::fs::write(format!("/proc/sys/net/ipv4/conf/lo/{name}"), "1")?;
+                }
+
+                tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()?
+                    .block_on(ping_on_lo())
+            })() {
+                Ok(()) => Ok(()),
+                Err(err) if is_permission_denied(err.as_ref()) => {
+                    eprintln!(
+                        "skipped, needs CAP_SYS_ADMIN, CAP_NET_ADMIN and CAP_NET_RAW, err:{err}"
+                    );
+                    Ok(())
+                }
+                Err(err) => Err(err.to_string()),
+            }
+        })
+        .join()
+        .expect("Never")?;
+
+        Ok(())
+    }
+
+    async fn ping_on_lo() -> Result<(), Box<dyn std::error::Error>> {
+        // The frames on lo carry zero MAC addresses.
+        let interface_index = unsafe { libc::if_nametoindex(c"lo".as_ptr()) };
+        let local = Ipv4Addr::new(127, 0, 0, 1);
+        let client = Client::new(
+            interface_index,
+            [0; 6],
+            &Config::new().bind((local, 0).into()),
+        )?;
+        assert_eq!(client.local_mac(), [0; 6]);
+
+        for sequence_number in 1..=3 {
+            let echo_request = PayloadLengthDelimitedEchoRequest::new(
+                Some(1.into()),
+                Some(sequence_number.into()),
+                b"1234",
+            );
+            client
+                .send_to(&echo_request.render_v4_packet_bytes(), (local, 0))
+                .await?;
+
+            let mut buf = vec![0; 1024];
+            let (n, addr) =
+                tokio::time::timeout(Duration::from_secs(2), client.recv_from(&mut buf)).await??;
+            assert_eq!(addr, (local, 0).into());
+            match Icmpv4::parse_from_packet_bytes(&buf[..n]) {
+                Ok(Some(Icmpv4::EchoReply(echo_reply))) => {
+                    assert_eq!(echo_reply.sequence_number, echo_request.sequence_number);
+                }
+                x => panic!("{x:?}"),
+            }
+        }
+
+        let stats = client.stats();
+        assert_eq!((stats.packets_sent, stats.packets_received), (3, 3));
+
+        Ok(())
+    }
+
+    fn is_permission_denied(err: &(dyn std::error::Error + 'static)) -> bool {
+        let mut err = Some(err);
+        while let Some(x) = err {
+            if x.downcast_ref::<IoError>().map(|x| x.kind()) == Some(IoErrorKind::PermissionDenied)
+            {
+                return true;
+            }
+            err = x.source();
+        }
+        false
+    }
+
+    fn set_lo_up() -> Result<(), IoError> {
+        let socket = std::net::UdpSocket::bind("0.0.0.0:0")?;
+        let mut ifreq: libc::ifreq = unsafe { core::mem::zeroed() };
+        for (dst, src) in ifreq.ifr_name.iter_mut().zip(b"lo") {
+            *dst = *src as libc::c_char;
+        }
+        ifreq.ifr_ifru.ifru_flags =
+            (libc::IFF_UP | libc::IFF_LOOPBACK | libc::IFF_RUNNING) as libc::c_short;
+        if unsafe { libc::ioctl(socket.as_raw_fd(), libc::SIOCSIFFLAGS, &ifreq) } < 0 {
+            return Err(IoError::last_os_error());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,899 @@
+//! Simulated routed network, for testing path tools deterministically.
+//!
+//! A [`Topology`] is a graph of hosts and routers joined by links with latency, loss and MTU.
+//! Packets sent by a [`Client`] are forwarded hop by hop along the shortest path,
+//! routers decrement the TTL and answer with Time Exceeded or Destination Unreachable
+//! quoting the original datagram, equal cost paths are picked by hashing the flow,
+//! and ICMP errors can be rate limited per router.
+//!
+//! Like the DGRAM sockets, a client receives echo replies and ICMP errors without the IP header.
+//! With `SocketType::Raw` it receives every ICMP packet reaching its host, IPv4 ones with the IP header.
+//! Time is `tokio::time`, so tests with a paused clock are reproducible.
+
+use core::{
+    task::{Context, Poll},
+    time::Duration,
+};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    hash::{Hash as _, Hasher as _},
+    io::{Error as IoError, ErrorKind as IoErrorKind},
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+};
+
+use icmp_packet::{
+    icmpv4, icmpv6,
+    ip::{render_ipv4_packet_bytes, render_ipv6_packet_bytes},
+    pnet_packet::{
+        icmp::{IcmpCode, IcmpType, IcmpTypes},
+        icmpv6::{Icmpv6Code, Icmpv6Types},
+    },
+};
+use rand::{rngs::StdRng, Rng as _, SeedableRng as _};
+use tokio::{sync::mpsc, time::Instant};
+
+use crate::{config::Config, AsyncClient, AsyncClientWithConfigError, ReadBuf};
+
+//
+pub const DEFAULT_TTL: u8 = 64;
+
+type Datagram = (Vec<u8>, SocketAddr);
+
+//
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Link {
+    pub latency: Duration,
+    pub loss: f64,
+    pub mtu: Option<u16>,
+}
+
+impl Link {
+    pub fn new(latency: Duration) -> Self {
+        Self {
+            latency,
+            loss: 0.0,
+            mtu: None,
+        }
+    }
+
+    /// Routers answer bigger packets with Fragmentation Needed / Packet Too Big.
+    pub fn mtu(mut self, mtu: u16) -> Self {
+        self.mtu = Some(mtu);
+        self
+    }
+
+    pub fn loss(mut self, loss: f64) -> Self {
+        assert!((0.0..=1.0).contains(&loss));
+        self.loss = loss;
+        self
+    }
+}
+
+//
+/// Token bucket, `burst` errors at most, then one more every `interval`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IcmpRateLimit {
+    pub burst: u32,
+    pub interval: Duration,
+}
+
+impl IcmpRateLimit {
+    pub fn new(burst: u32, interval: Duration) -> Self {
+        Self { burst, interval }
+    }
+}
+
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Node {
+    Host {
+        up: bool,
+        echo_reply: bool,
+    },
+    Router {
+        icmp_rate_limit: Option<IcmpRateLimit>,
+    },
+}
+
+//
+#[derive(Debug, Clone, Default)]
+pub struct Topology {
+    addrs: Vec<IpAddr>,
+    nodes: HashMap<IpAddr, Node>,
+    links: HashMap<IpAddr, Vec<(IpAddr, Link)>>,
+    seed: u64,
+}
+
+impl Topology {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn host(self, addr: impl Into<IpAddr>) -> Self {
+        self.node(
+            addr.into(),
+            Node::Host {
+                up: true,
+                echo_reply: true,
+            },
+        )
+    }
+
+    /// Host that is down, the last router in front of it answers with Destination Unreachable.
+    pub fn unreachable_host(self, addr: impl Into<IpAddr>) -> Self {
+        self.node(
+            addr.into(),
+            Node::Host {
+                up: false,
+                echo_reply: false,
+            },
+        )
+    }
+
+    /// Host that is up but does not answer echo requests.
+    pub fn silent_host(self, addr: impl Into<IpAddr>) -> Self {
+        self.node(
+            addr.into(),
+            Node::Host {
+                up: true,
+                echo_reply: false,
+            },
+        )
+    }
+
+    pub fn router(self, addr: impl Into<IpAddr>) -> Self {
+        self.node(
+            addr.into(),
+            Node::Router {
+                icmp_rate_limit: None,
+            },
+        )
+    }
+
+    pub fn router_with_icmp_rate_limit(
+        self,
+        addr: impl Into<IpAddr>,
+        icmp_rate_limit: IcmpRateLimit,
+    ) -> Self {
+        self.node(
+            addr.into(),
+            Node::Router {
+                icmp_rate_limit: Some(icmp_rate_limit),
+            },
+        )
+    }
+
+    /// Bidirectional link, both ends must be added before.
+    pub fn link(mut self, a: impl Into<IpAddr>, b: impl Into<IpAddr>, link: Link) -> Self {
+        let (a, b) = (a.into(), b.into());
+        assert!(self.nodes.contains_key(&a) && self.nodes.contains_key(&b));
+        assert_eq!(a.is_ipv6(), b.is_ipv6());
+
+        self.links.entry(a).or_default().push((b, link));
+        self.links.entry(b).or_default().push((a, link));
+        self
+    }
+
+    /// Seed of the loss generator.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn build(self) -> Network {
+        Network::new(self)
+    }
+
+    fn node(mut self, addr: IpAddr, node: Node) -> Self {
+        if self.nodes.insert(addr, node).is_none() {
+            self.addrs.push(addr);
+        }
+        self
+    }
+
+    // Shortest path next hops from `from` to `to`, only routers forward.
+    fn next_hops(&self, from: IpAddr, to: IpAddr) -> Vec<IpAddr> {
+        let mut distances = HashMap::from([(to, 0_usize)]);
+        let mut queue = VecDeque::from([to]);
+        while let Some(addr) = queue.pop_front() {
+            if addr != to && !matches!(self.nodes.get(&addr), Some(Node::Router { .. })) {
+                continue;
+            }
+            let distance = distances[&addr];
+            for (neighbor, _) in self.links.get(&addr).into_iter().flatten() {
+                if !distances.contains_key(neighbor) {
+                    distances.insert(*neighbor, distance + 1);
+                    queue.push_back(*neighbor);
+                }
+            }
+        }
+
+        let distance = match distances.get(&from) {
+            Some(x) if *x > 0 => *x,
+            _ => return vec![],
+        };
+        let mut next_hops = self
+            .links
+            .get(&from)
+            .into_iter()
+            .flatten()
+            .map(|(neighbor, _)| *neighbor)
+            .filter(|neighbor| distances.get(neighbor) == Some(&(distance - 1)))
+            .filter(|neighbor| {
+                *neighbor == to || matches!(self.nodes.get(neighbor), Some(Node::Router { .. }))
+            })
+            .collect::<Vec<_>>();
+        next_hops.sort();
+        next_hops.dedup();
+        next_hops
+    }
+
+    fn link_between(&self, a: IpAddr, b: IpAddr) -> Option<Link> {
+        self.links
+            .get(&a)?
+            .iter()
+            .find(|(neighbor, _)| *neighbor == b)
+            .map(|(_, link)| *link)
+    }
+}
+
+//
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Packet {
+    source: IpAddr,
+    destination: IpAddr,
+    ttl: u8,
+    icmp_bytes: Vec<u8>,
+}
+
+impl Packet {
+    fn render_ip_packet_bytes(&self) -> Vec<u8> {
+        match (self.source, self.destination) {
+            (IpAddr::V4(source), IpAddr::V4(destination)) => {
+                render_ipv4_packet_bytes(source, destination, self.ttl, &self.icmp_bytes)
+            }
+            (IpAddr::V6(source), IpAddr::V6(destination)) => {
+                render_ipv6_packet_bytes(source, destination, self.ttl, &self.icmp_bytes)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn icmp_type(&self) -> Option<u8> {
+        self.icmp_bytes.first().copied()
+    }
+
+    fn is_echo_request(&self) -> bool {
+        match self.destination {
+            IpAddr::V4(_) => self.icmp_type() == Some(IcmpTypes::EchoRequest.0),
+            IpAddr::V6(_) => self.icmp_type() == Some(Icmpv6Types::EchoRequest.0),
+        }
+    }
+
+    fn is_echo_reply(&self) -> bool {
+        match self.destination {
+            IpAddr::V4(_) => self.icmp_type() == Some(IcmpTypes::EchoReply.0),
+            IpAddr::V6(_) => self.icmp_type() == Some(Icmpv6Types::EchoReply.0),
+        }
+    }
+
+    // RFC 1812 4.3.2.7, no error about an error.
+    fn is_icmp_error(&self) -> bool {
+        match self.destination {
+            IpAddr::V4(_) => matches!(
+                self.icmp_type().map(IcmpType),
+                Some(
+                    IcmpTypes::DestinationUnreachable
+                        | IcmpTypes::TimeExceeded
+                        | IcmpTypes::ParameterProblem
+                        | IcmpTypes::SourceQuench
+                        | IcmpTypes::RedirectMessage
+                )
+            ),
+            IpAddr::V6(_) => self.icmp_type().map(|x| x < 128).unwrap_or(true),
+        }
+    }
+
+    fn flow_hash(&self, router: IpAddr) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.source.hash(&mut hasher);
+        self.destination.hash(&mut hasher);
+        self.icmp_bytes.get(4..6).hash(&mut hasher);
+        router.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IcmpError {
+    TimeExceeded,
+    NetUnreachable,
+    HostUnreachable,
+    PacketTooBig(u16),
+}
+
+//
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Delivery {
+    delay: Duration,
+    packet: Packet,
+}
+
+//
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: u32,
+    updated_at: Instant,
+}
+
+//
+struct Socket {
+    tx: mpsc::UnboundedSender<Datagram>,
+    is_raw: bool,
+}
+
+impl Socket {
+    fn deliver(&self, packet: &Packet) -> bool {
+        let bytes = if self.is_raw {
+            match packet.destination {
+                IpAddr::V4(_) => packet.render_ip_packet_bytes(),
+                IpAddr::V6(_) => packet.icmp_bytes.clone(),
+            }
+        } else if packet.is_echo_reply() || packet.is_icmp_error() {
+            packet.icmp_bytes.clone()
+        } else {
+            return !self.tx.is_closed();
+        };
+        self.tx.send((bytes, (packet.source, 0).into())).is_ok()
+    }
+}
+
+//
+struct State {
+    topology: Topology,
+    rng: StdRng,
+    token_buckets: HashMap<IpAddr, TokenBucket>,
+    sockets: HashMap<IpAddr, Vec<Socket>>,
+}
+
+impl State {
+    // Walks the packet through the topology, returns the packets reaching their destinations.
+    fn transmit(&mut self, packet: Packet, now: Instant) -> Result<Vec<Delivery>, IoError> {
+        if packet.source != packet.destination
+            && self
+                .topology
+                .next_hops(packet.source, packet.destination)
+                .is_empty()
+        {
+            return Err(IoError::other("network unreachable"));
+        }
+
+        let mut deliveries = vec![];
+        let mut queue = VecDeque::from([(packet, Duration::ZERO)]);
+        while let Some((packet, delay)) = queue.pop_front() {
+            let mut packet = packet;
+            let mut delay = delay;
+            let mut node = packet.source;
+
+            loop {
+                if node == packet.destination {
+                    if let Some(reply) = self.echo_reply(&packet) {
+                        queue.push_back((reply, delay));
+                    }
+                    deliveries.push(Delivery { delay, packet });
+                    break;
+                }
+
+                if node != packet.source {
+                    if packet.ttl <= 1 {
+                        if let Some(error) =
+                            self.icmp_error(node, &packet, IcmpError::TimeExceeded, now + delay)
+                        {
+                            queue.push_back((error, delay));
+                        }
+                        break;
+                    }
+                    packet.ttl -= 1;
+                }
+
+                let next_hops = self.topology.next_hops(node, packet.destination);
+                let next_hop = if next_hops.is_empty() {
+                    if let Some(error) =
+                        self.icmp_error(node, &packet, IcmpError::NetUnreachable, now + delay)
+                    {
+                        queue.push_back((error, delay));
+                    }
+                    break;
+                } else {
+                    next_hops[(packet.flow_hash(node) % next_hops.len() as u64) as usize]
+                };
+
+                if matches!(
+                    self.topology.nodes.get(&next_hop),
+                    Some(Node::Host { up: false, .. })
+                ) {
+                    if let Some(error) =
+                        self.icmp_error(node, &packet, IcmpError::HostUnreachable, now + delay)
+                    {
+                        queue.push_back((error, delay));
+                    }
+                    break;
+                }
+
+                let link = self
+                    .topology
+                    .link_between(node, next_hop)
+                    .expect("Never when link_between");
+                if let Some(mtu) = link.mtu {
+                    if packet.render_ip_packet_bytes().len() > mtu as usize {
+                        if node == packet.source {
+                            if deliveries.is_empty() && queue.is_empty() {
+                                return Err(IoError::other("message too long"));
+                            }
+                        } else if let Some(error) = self.icmp_error(
+                            node,
+                            &packet,
+                            IcmpError::PacketTooBig(mtu),
+                            now + delay,
+                        ) {
+                            queue.push_back((error, delay));
+                        }
+                        break;
+                    }
+                }
+                if link.loss > 0.0 && self.rng.gen_bool(link.loss) {
+                    break;
+                }
+                delay += link.latency;
+                node = next_hop;
+            }
+        }
+
+        Ok(deliveries)
+    }
+
+    fn echo_reply(&self, packet: &Packet) -> Option<Packet> {
+        if !packet.is_echo_request() {
+            return None;
+        }
+        match self.topology.nodes.get(&packet.destination)? {
+            Node::Host {
+                up: true,
+                echo_reply: true,
+            }
+            | Node::Router { .. } => {}
+            _ => return None,
+        }
+
+        let icmp_bytes = match packet.destination {
+            IpAddr::V4(_) => icmpv4::render_echo_reply_packet_bytes(&packet.icmp_bytes)?,
+            IpAddr::V6(_) => icmpv6::render_echo_reply_packet_bytes(&packet.icmp_bytes)?,
+        };
+        Some(Packet {
+            source: packet.destination,
+            destination: packet.source,
+            ttl: DEFAULT_TTL,
+            icmp_bytes,
+        })
+    }
+
+    fn icmp_error(
+        &mut self,
+        node: IpAddr,
+        packet: &Packet,
+        error: IcmpError,
+        at: Instant,
+    ) -> Option<Packet> {
+        if packet.is_icmp_error() {
+            return None;
+        }
+        if let Some(Node::Router {
+            icmp_rate_limit: Some(icmp_rate_limit),
+        }) = self.topology.nodes.get(&node)
+        {
+            let token_bucket = self.token_buckets.entry(node).or_insert(TokenBucket {
+                tokens: icmp_rate_limit.burst,
+                updated_at: at,
+            });
+            if !icmp_rate_limit.interval.is_zero() {
+                let refill = (at
+                    .saturating_duration_since(token_bucket.updated_at)
+                    .as_nanos()
+                    / icmp_rate_limit.interval.as_nanos()) as u32;
+                if refill > 0 {
+                    token_bucket.tokens = token_bucket
+                        .tokens
+                        .saturating_add(refill)
+                        .min(icmp_rate_limit.burst);
+                    token_bucket.updated_at = at;
+                }
+            }
+            if token_bucket.tokens == 0 {
+                return None;
+            }
+            token_bucket.tokens -= 1;
+        }
+
+        let original_datagram = packet.render_ip_packet_bytes();
+        let icmp_bytes = match node {
+            IpAddr::V4(_) => {
+                let (icmp_type, code, rest_of_header) = match error {
+                    IcmpError::TimeExceeded => (IcmpTypes::TimeExceeded, 0, 0),
+                    IcmpError::NetUnreachable => (IcmpTypes::DestinationUnreachable, 0, 0),
+                    IcmpError::HostUnreachable => (IcmpTypes::DestinationUnreachable, 1, 0),
+                    // RFC 1191, next-hop MTU in the low-order 16 bits
+                    IcmpError::PacketTooBig(mtu) => {
+                        (IcmpTypes::DestinationUnreachable, 4, mtu as u32)
+                    }
+                };
+                icmpv4::render_error_packet_bytes(
+                    icmp_type,
+                    IcmpCode(code),
+                    rest_of_header,
+                    &original_datagram,
+                )
+            }
+            IpAddr::V6(_) => {
+                let (icmp_type, code, rest_of_header) = match error {
+                    IcmpError::TimeExceeded => (Icmpv6Types::TimeExceeded, 0, 0),
+                    IcmpError::NetUnreachable => (Icmpv6Types::DestinationUnreachable, 0, 0),
+                    IcmpError::HostUnreachable => (Icmpv6Types::DestinationUnreachable, 3, 0),
+                    IcmpError::PacketTooBig(mtu) => (Icmpv6Types::PacketTooBig, 0, mtu as u32),
+                };
+                icmpv6::render_error_packet_bytes(
+                    icmp_type,
+                    Icmpv6Code(code),
+                    rest_of_header,
+                    &original_datagram,
+                )
+            }
+        };
+
+        Some(Packet {
+            source: node,
+            destination: packet.source,
+            ttl: DEFAULT_TTL,
+            icmp_bytes,
+        })
+    }
+}
+
+//
+#[derive(Clone)]
+pub struct Network {
+    state: Arc<Mutex<State>>,
+}
+
+impl core::fmt::Debug for Network {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Network").finish()
+    }
+}
+
+impl Network {
+    pub fn new(topology: Topology) -> Self {
+        let rng = StdRng::seed_from_u64(topology.seed);
+        Self {
+            state: Arc::new(Mutex::new(State {
+                topology,
+                rng,
+                token_buckets: HashMap::new(),
+                sockets: HashMap::new(),
+            })),
+        }
+    }
+
+    fn register(
+        &self,
+        config: &Config,
+    ) -> Result<(IpAddr, mpsc::UnboundedReceiver<Datagram>), IoError> {
+        let mut state = self.state.lock().expect("Never when lock");
+
+        let local = match config.bind.map(|x| x.ip()) {
+            Some(ip) if !ip.is_unspecified() => ip,
+            _ => state
+                .topology
+                .addrs
+                .iter()
+                .find(|addr| {
+                    addr.is_ipv6() == config.is_ipv6()
+                        && matches!(state.topology.nodes.get(addr), Some(Node::Host { .. }))
+                })
+                .copied()
+                .ok_or_else(|| IoError::new(IoErrorKind::AddrNotAvailable, "no host"))?,
+        };
+        if !matches!(state.topology.nodes.get(&local), Some(Node::Host { .. })) {
+            return Err(IoError::new(IoErrorKind::AddrNotAvailable, "not a host"));
+        }
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        state.sockets.entry(local).or_default().push(Socket {
+            tx,
+            is_raw: config.is_raw(),
+        });
+        Ok((local, rx))
+    }
+
+    fn send(&self, packet: Packet) -> Result<(), IoError> {
+        let now = Instant::now();
+
+        let mut deliveries = {
+            let mut state = self.state.lock().expect("Never when lock");
+            let mut deliveries = state.transmit(packet, now)?;
+            deliveries.retain(|delivery| state.sockets.contains_key(&delivery.packet.destination));
+            deliveries
+        };
+        deliveries.sort_by_key(|delivery| delivery.delay);
+        if deliveries.is_empty() {
+            return Ok(());
+        }
+
+        let state = self.state.clone();
+        tokio::spawn(async move {
+            for Delivery { delay, packet } in deliveries {
+                tokio::time::sleep_until(now + delay).await;
+
+                let mut state = state.lock().expect("Never when lock");
+                if let Some(sockets) = state.sockets.get_mut(&packet.destination) {
+                    sockets.retain(|socket| socket.deliver(&packet));
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+//
+#[derive(Debug, Clone)]
+pub struct Client {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    network: Network,
+    local: IpAddr,
+    ttl: u8,
+    rx: Mutex<mpsc::UnboundedReceiver<Datagram>>,
+}
+
+impl Client {
+    pub fn new(network: &Network, config: &Config) -> Result<Self, AsyncClientWithConfigError> {
+        let (local, rx) = network.register(config)?;
+        Ok(Self {
+            inner: Arc::new(Inner {
+                network: network.clone(),
+                local,
+                ttl: config
+                    .ttl
+                    .map(|x| x.min(u8::MAX as u32) as u8)
+                    .unwrap_or(DEFAULT_TTL),
+                rx: Mutex::new(rx),
+            }),
+        })
+    }
+
+    pub fn local_addr(&self) -> IpAddr {
+        self.inner.local
+    }
+}
+
+impl AsyncClient for Client {
+    fn with_config(config: &Config) -> Result<Self, AsyncClientWithConfigError> {
+        let network = config
+            .sim_network
+            .as_ref()
+            .ok_or_else(|| IoError::new(IoErrorKind::InvalidInput, "config sim_network missing"))?;
+        Client::new(network, config)
+    }
+
+    fn poll_send_to(
+        &self,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+        addr: SocketAddr,
+    ) -> Poll<Result<usize, IoError>> {
+        if addr.is_ipv6() != self.inner.local.is_ipv6() {
+            return Poll::Ready(Err(IoError::new(
+                IoErrorKind::InvalidInput,
+                "address family",
+            )));
+        }
+
+        Poll::Ready(
+            self.inner
+                .network
+                .send(Packet {
+                    source: self.inner.local,
+                    destination: addr.ip(),
+                    ttl: self.inner.ttl,
+                    icmp_bytes: buf.to_vec(),
+                })
+                .map(|_| buf.len()),
+        )
+    }
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<SocketAddr, IoError>> {
+        let (bytes, addr) = match self.inner.rx.lock().expect("Never when lock").poll_recv(cx) {
+            Poll::Ready(Some(x)) => x,
+            Poll::Ready(None) => {
+                return Poll::Ready(Err(IoError::new(
+                    IoErrorKind::BrokenPipe,
+                    "network dropped",
+                )))
+            }
+            Poll::Pending => return Poll::Pending,
+        };
+        buf.put_slice(&bytes);
+        Poll::Ready(Ok(addr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::AsyncClientExt as _;
+
+    use std::net::Ipv4Addr;
+
+    use icmp_packet::{ip::parse_ipv4_packet_bytes, Icmpv4, PayloadLengthDelimitedEchoRequest};
+
+    fn topology() -> Topology {
+        let ms = Duration::from_millis;
+        Topology::new()
+            .host(Ipv4Addr::new(10, 0, 0, 1))
+            .router(Ipv4Addr::new(10, 0, 1, 1))
+            .router(Ipv4Addr::new(10, 0, 2, 1))
+            .router(Ipv4Addr::new(10, 0, 2, 2))
+            .router_with_icmp_rate_limit(
+                Ipv4Addr::new(10, 0, 3, 1),
+                IcmpRateLimit::new(1, Duration::from_secs(1)),
+            )
+            .host(Ipv4Addr::new(10, 0, 4, 1))
+            .unreachable_host(Ipv4Addr::new(10, 0, 4, 2))
+            .link([10, 0, 0, 1], [10, 0, 1, 1], Link::new(ms(1)))
+            .link([10, 0, 1, 1], [10, 0, 2, 1], Link::new(ms(10)))
+            .link([10, 0, 1, 1], [10, 0, 2, 2], Link::new(ms(10)))
+            .link([10, 0, 2, 1], [10, 0, 3, 1], Link::new(ms(10)))
+            .link([10, 0, 2, 2], [10, 0, 3, 1], Link::new(ms(10)))
+            .link([10, 0, 3, 1], [10, 0, 4, 1], Link::new(ms(1)))
+            .link([10, 0, 3, 1], [10, 0, 4, 2], Link::new(ms(1)))
+    }
+
+    async fn ping(
+        client: &Client,
+        ip: Ipv4Addr,
+        sequence_number: u16,
+    ) -> Result<(Icmpv4, SocketAddr, Duration), Box<dyn std::error::Error>> {
+        let echo_request = PayloadLengthDelimitedEchoRequest::new(
+            Some(1.into()),
+            Some(sequence_number.into()),
+            b"1234",
+        );
+        let instant_begin = Instant::now();
+        client
+            .send_to(&echo_request.render_v4_packet_bytes(), (ip, 0))
+            .await?;
+
+        let mut buf = vec![0; 1024];
+        let (n, addr) =
+            tokio::time::timeout(Duration::from_secs(2), client.recv_from(&mut buf)).await??;
+        let icmpv4 = Icmpv4::parse_from_packet_bytes(&buf[..n])?.ok_or("incomplete")?;
+        Ok((icmpv4, addr, instant_begin.elapsed()))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_client() -> Result<(), Box<dyn std::error::Error>> {
+        let network = topology().build();
+
+        // echo reply
+        let client = Client::new(&network, &Config::new())?;
+        assert_eq!(client.local_addr(), Ipv4Addr::new(10, 0, 0, 1));
+        match ping(&client, Ipv4Addr::new(10, 0, 4, 1), 1).await? {
+            (Icmpv4::EchoReply(echo_reply), addr, dur) => {
+                assert_eq!(*echo_reply.sequence_number.inner(), 1);
+                assert_eq!(addr, (Ipv4Addr::new(10, 0, 4, 1), 0).into());
+                assert_eq!(dur, Duration::from_millis(44));
+            }
+            x => panic!("{x:?}"),
+        }
+
+        // time exceeded, quoting the original datagram
+        let client = Client::new(&network, &Config::new().ttl(2))?;
+        match ping(&client, Ipv4Addr::new(10, 0, 4, 1), 2).await? {
+            (Icmpv4::Other(IcmpTypes::TimeExceeded, IcmpCode(0), payload), addr, _) => {
+                assert!(
+                    addr == (Ipv4Addr::new(10, 0, 2, 1), 0).into()
+                        || addr == (Ipv4Addr::new(10, 0, 2, 2), 0).into()
+                );
+                let (ipv4_header, icmp_bytes) =
+                    parse_ipv4_packet_bytes(&payload[4..]).ok_or("quote")?;
+                assert_eq!(ipv4_header.source, Ipv4Addr::new(10, 0, 0, 1));
+                assert_eq!(ipv4_header.destination, Ipv4Addr::new(10, 0, 4, 1));
+                assert_eq!(ipv4_header.ttl, 1);
+                assert_eq!(icmp_bytes[0], IcmpTypes::EchoRequest.0);
+                assert_eq!(&icmp_bytes[6..8], &2_u16.to_be_bytes());
+            }
+            x => panic!("{x:?}"),
+        }
+
+        // net unreachable
+        match ping(&client, Ipv4Addr::new(10, 9, 9, 9), 3).await {
+            Err(err) => assert!(err.to_string().contains("network unreachable")),
+            x => panic!("{x:?}"),
+        }
+
+        // host unreachable, then rate limited
+        let client = Client::new(&network, &Config::new())?;
+        match ping(&client, Ipv4Addr::new(10, 0, 4, 2), 4).await? {
+            (Icmpv4::Other(IcmpTypes::DestinationUnreachable, IcmpCode(1), _), addr, _) => {
+                assert_eq!(addr, (Ipv4Addr::new(10, 0, 3, 1), 0).into());
+            }
+            x => panic!("{x:?}"),
+        }
+        assert!(ping(&client, Ipv4Addr::new(10, 0, 4, 2), 5).await.is_err());
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(ping(&client, Ipv4Addr::new(10, 0, 4, 2), 6).await.is_ok());
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ecmp_loss_and_mtu() -> Result<(), Box<dyn std::error::Error>> {
+        let ms = Duration::from_millis;
+
+        let mut second_hops = std::collections::HashSet::new();
+        let network = topology().build();
+        {
+            let mut state = network.state.lock().expect("Never");
+            for identifier in 0..32_u16 {
+                let mut icmp_bytes = vec![8, 0, 0, 0];
+                icmp_bytes.extend_from_slice(&identifier.to_be_bytes());
+                icmp_bytes.extend_from_slice(&[0, 0]);
+                let packet = Packet {
+                    source: Ipv4Addr::new(10, 0, 0, 1).into(),
+                    destination: Ipv4Addr::new(10, 0, 4, 1).into(),
+                    ttl: 2,
+                    icmp_bytes,
+                };
+                for delivery in state.transmit(packet, Instant::now())? {
+                    second_hops.insert(delivery.packet.source);
+                }
+            }
+        }
+        assert_eq!(second_hops.len(), 2);
+
+        let network = Topology::new()
+            .host(Ipv4Addr::new(10, 0, 0, 1))
+            .host(Ipv4Addr::new(10, 0, 0, 2))
+            .link([10, 0, 0, 1], [10, 0, 0, 2], Link::new(ms(1)).loss(1.0))
+            .build();
+        let client = Client::new(&network, &Config::new())?;
+        assert!(ping(&client, Ipv4Addr::new(10, 0, 0, 2), 1).await.is_err());
+
+        // packet too big
+        let network = Topology::new()
+            .host(Ipv4Addr::new(10, 0, 0, 1))
+            .router(Ipv4Addr::new(10, 0, 1, 1))
+            .host(Ipv4Addr::new(10, 0, 2, 1))
+            .link([10, 0, 0, 1], [10, 0, 1, 1], Link::new(ms(1)))
+            .link([10, 0, 1, 1], [10, 0, 2, 1], Link::new(ms(1)).mtu(30))
+            .build();
+        let client = Client::new(&network, &Config::new())?;
+        match ping(&client, Ipv4Addr::new(10, 0, 2, 1), 1).await? {
+            (Icmpv4::Other(IcmpTypes::DestinationUnreachable, IcmpCode(4), payload), _, _) => {
+                assert_eq!(&payload[2..4], &30_u16.to_be_bytes());
+            }
+            x => panic!("{x:?}"),
+        }
+
+        Ok(())
+    }
+}
@@ -1,14 +1,24 @@
-use std::{io::Error as IoError, net::SocketAddr, sync::Arc};
+use core::task::{ready, Context, Poll};
+use std::{
+    io::Error as IoError,
+    net::{SocketAddr, UdpSocket as StdUdpSocket},
+    sync::Arc,
+};
 
-use async_trait::async_trait;
 use tokio::net::UdpSocket;
 
-use crate::{config::Config, utils::new_std_udp_socket, AsyncClient, AsyncClientWithConfigError};
+use crate::{
+    config::Config,
+    stats::StatsCounter,
+    utils::{apply_config, new_std_udp_socket, socket2_socket_to_std_udp_socket},
+    AsyncClient, AsyncClientWithConfigError, ReadBuf, Stats,
+};
 
 //
 #[derive(Debug, Clone)]
 pub struct Client {
     inner: Arc<UdpSocket>,
+    stats: Arc<StatsCounter>,
 }
 
 impl Client {
@@ -17,25 +27,144 @@ impl Client {
         let inner = UdpSocket::from_std(udp_socket)?;
         Ok(Self {
             inner: Arc::new(inner),
+            stats: Default::default(),
         })
     }
+
+    /// Adopts an ICMP socket opened elsewhere, e.g. by a privileged parent.
+    ///
+    /// With `config`, its options (bind, ttl, ...) are applied to the socket.
+    pub fn from_socket2(
+        socket: socket2::Socket,
+        config: Option<&Config>,
+    ) -> Result<Self, AsyncClientWithConfigError> {
+        apply_config(&socket, config.unwrap_or(&Config::new()))?;
+        let udp_socket = socket2_socket_to_std_udp_socket(socket);
+        let inner = UdpSocket::from_std(udp_socket)?;
+        Ok(Self {
+            inner: Arc::new(inner),
+            stats: Default::default(),
+        })
+    }
+
+    pub fn from_std(
+        udp_socket: StdUdpSocket,
+        config: Option<&Config>,
+    ) -> Result<Self, AsyncClientWithConfigError> {
+        Self::from_socket2(udp_socket.into(), config)
+    }
+
+    /// Adopts an inherited file descriptor, e.g. from systemd socket activation,
+    /// with `unsafe { OwnedFd::from_raw_fd(3) }`.
+    #[cfg(unix)]
+    pub fn from_fd(
+        fd: std::os::fd::OwnedFd,
+        config: Option<&Config>,
+    ) -> Result<Self, AsyncClientWithConfigError> {
+        Self::from_socket2(fd.into(), config)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn poll_recv_from_with_drops(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<Option<SocketAddr>, IoError>> {
+        use std::os::fd::AsRawFd as _;
+
+        use tokio::io::Interest;
+
+        let fd = self.inner.as_raw_fd();
+        loop {
+            ready!(self.inner.poll_recv_ready(cx))?;
+            match self.inner.try_io(Interest::READABLE, || {
+                crate::utils::recv_from_with_drops(fd, buf.unfilled_mut())
+            }) {
+                Ok((n, addr, drops)) => {
+                    buf.advance(n);
+                    self.stats.on_received(drops);
+                    return Poll::Ready(Ok(addr));
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => continue,
+                Err(err) => return Poll::Ready(Err(err)),
+            }
+        }
+    }
 }
 
-#[async_trait]
 impl AsyncClient for Client {
     fn with_config(config: &Config) -> Result<Self, AsyncClientWithConfigError> {
         Client::new(config)
     }
 
-    async fn send_to<A: Into<SocketAddr> + Send>(
+    fn poll_send_to(
         &self,
+        cx: &mut Context<'_>,
         buf: &[u8],
-        addr: A,
-    ) -> Result<usize, IoError> {
-        self.inner.send_to(buf, addr.into()).await
+        addr: SocketAddr,
+    ) -> Poll<Result<usize, IoError>> {
+        let n = ready!(self.inner.poll_send_to(cx, buf, addr))?;
+        self.stats.on_sent();
+        Poll::Ready(Ok(n))
+    }
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<SocketAddr, IoError>> {
+        #[cfg(target_os = "linux")]
+        {
+            let addr = ready!(self.poll_recv_from_with_drops(cx, buf))?;
+            Poll::Ready(addr.ok_or_else(|| IoError::other("addr missing")))
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let mut tokio_buf = tokio::io::ReadBuf::new(buf.unfilled_mut());
+            let addr = ready!(self.inner.poll_recv_from(cx, &mut tokio_buf))?;
+            let n = tokio_buf.filled().len();
+            buf.advance(n);
+            self.stats.on_received(None);
+            Poll::Ready(Ok(addr))
+        }
     }
-    async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), IoError> {
-        self.inner.recv_from(buf).await
+
+    fn stats(&self) -> Stats {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::fd::AsRawFd as _;
+
+            self.stats
+                .get(crate::utils::socket_drops(self.inner.as_raw_fd()))
+        }
+        #[cfg(not(target_os = "linux"))]
+        self.stats.get(None)
+    }
+
+    fn poll_send(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize, IoError>> {
+        let n = ready!(self.inner.poll_send(cx, buf))?;
+        self.stats.on_sent();
+        Poll::Ready(Ok(n))
+    }
+    fn poll_recv(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<(), IoError>> {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::fd::AsRawFd as _;
+
+            let ret = ready!(self.poll_recv_from_with_drops(cx, buf));
+            if ret.is_err() {
+                crate::utils::drain_error_queue(self.inner.as_raw_fd());
+            }
+            Poll::Ready(ret.map(|_| ()))
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let mut tokio_buf = tokio::io::ReadBuf::new(buf.unfilled_mut());
+            ready!(self.inner.poll_recv(cx, &mut tokio_buf))?;
+            let n = tokio_buf.filled().len();
+            buf.advance(n);
+            self.stats.on_received(None);
+            Poll::Ready(Ok(()))
+        }
     }
 }
 
@@ -43,6 +172,8 @@ impl AsyncClient for Client {
 mod tests {
     use super::*;
 
+    use crate::AsyncClientExt as _;
+
     #[tokio::test]
     async fn test_client() -> Result<(), Box<dyn std::error::Error>> {
         crate::tests_helper::ping_ipv4::<Client>("127.0.0.1".parse().expect("Never")).await?;
@@ -69,4 +200,65 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_from_socket2() -> Result<(), Box<dyn std::error::Error>> {
+        let socket = crate::utils::new_socket2_socket(&Config::new())?;
+        let client = Client::from_socket2(socket, Some(&Config::new().ttl(64)))?;
+
+        crate::tests_helper::ping_ipv4_with_client(&client, "127.0.0.1".parse().expect("Never"))
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_connect() -> Result<(), Box<dyn std::error::Error>> {
+        use icmp_packet::{Icmpv4, PayloadLengthDelimitedEchoRequest};
+
+        let client = Client::new(&Config::new().connect("127.0.0.1:0".parse()?))?;
+
+        let echo_request =
+            PayloadLengthDelimitedEchoRequest::new(Some(1.into()), Some(2.into()), b"1234");
+        client.send(&echo_request.render_v4_packet_bytes()).await?;
+
+        let mut buf = vec![0; 1024];
+        let n = client.recv(&mut buf).await?;
+        match Icmpv4::parse_from_packet_bytes(&buf[..n]) {
+            Ok(Some(Icmpv4::EchoReply(echo_reply))) => {
+                assert_eq!(echo_reply.sequence_number, echo_request.sequence_number);
+            }
+            x => panic!("{x:?}"),
+        }
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_stats() -> Result<(), Box<dyn std::error::Error>> {
+        use core::time::Duration;
+
+        use icmp_packet::PayloadLengthDelimitedEchoRequest;
+
+        // The smallest buffer the kernel allows.
+        let client = Client::new(&Config::new().recv_buffer_size(1))?;
+        let addr: SocketAddr = "127.0.0.1:0".parse()?;
+        let echo_request_bytes = PayloadLengthDelimitedEchoRequest::new(None, None, vec![0; 512])
+            .render_v4_packet_bytes();
+
+        for _ in 0..100 {
+            client.send_to(&echo_request_bytes, addr).await?;
+        }
+        let mut buf = vec![0; 2048];
+        while tokio::time::timeout(Duration::from_millis(100), client.recv_from(&mut buf))
+            .await
+            .is_ok()
+        {}
+
+        let stats = client.stats();
+        assert_eq!(stats.packets_sent, 100);
+        assert!(stats.kernel_drops > 0);
+        assert_eq!(stats.packets_received + stats.kernel_drops, 100);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,259 @@
+//! Client over a TUN device, for userspace network stacks.
+//!
+//! Full IPv4/IPv6 packets are read from and written to the file descriptor,
+//! the IP header is built around the outgoing ICMP message and stripped from the incoming one.
+//! Like the DGRAM sockets, echo requests are not received and no IP header is returned,
+//! with `SocketType::Raw` every ICMP packet is received, IPv4 ones with the IP header.
+//!
+//! The source address is the `bind` ip of the config, one client per file descriptor and family.
+
+use core::task::{ready, Context, Poll};
+use std::{
+    io::{Error as IoError, ErrorKind as IoErrorKind},
+    net::{IpAddr, SocketAddr},
+    os::fd::{AsRawFd as _, FromRawFd as _, OwnedFd},
+    sync::{Arc, Mutex},
+};
+
+use tokio::io::unix::AsyncFd;
+
+use crate::{
+    config::Config,
+    ip_stack::{parse_ip_packet_bytes, render_ip_packet_bytes},
+    AsyncClient, AsyncClientWithConfigError, ReadBuf,
+};
+
+//
+pub const DEFAULT_TTL: u8 = 64;
+
+//
+#[derive(Debug, Clone)]
+pub struct Client {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    fd: AsyncFd<OwnedFd>,
+    local: IpAddr,
+    ttl: u8,
+    is_raw: bool,
+    // Reused by every recv.
+    recv_bytes: Mutex<Vec<u8>>,
+}
+
+impl Client {
+    /// `fd` is a TUN device opened with `IFF_TUN | IFF_NO_PI`,
+    /// or anything reading and writing one IP packet at a time.
+    pub fn new(fd: OwnedFd, config: &Config) -> Result<Self, AsyncClientWithConfigError> {
+        let local = match config.bind.map(|x| x.ip()) {
+            Some(ip) if !ip.is_unspecified() => ip,
+            _ => {
+                return Err(
+                    IoError::new(IoErrorKind::InvalidInput, "config bind ip required").into(),
+                )
+            }
+        };
+
+        set_nonblocking(&fd)?;
+        // SAFETY: `OwnedFd` keeps the fd open as long as the `AsyncFd`.
+        let fd = unsafe { AsyncFd::register(fd).map_err(IoError::from)? };
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                fd,
+                local,
+                ttl: config
+                    .ttl
+                    .map(|x| x.min(u8::MAX as u32) as u8)
+                    .unwrap_or(DEFAULT_TTL),
+                is_raw: config.is_raw(),
+                recv_bytes: Mutex::new(vec![0; u16::MAX as usize]),
+            }),
+        })
+    }
+
+    pub fn open(name: &str, config: &Config) -> Result<Self, AsyncClientWithConfigError> {
+        Self::new(open_tun(name)?, config)
+    }
+
+    pub fn local_addr(&self) -> IpAddr {
+        self.inner.local
+    }
+}
+
+impl AsyncClient for Client {
+    fn with_config(config: &Config) -> Result<Self, AsyncClientWithConfigError> {
+        let name = config
+            .tun_name
+            .as_deref()
+            .ok_or_else(|| IoError::new(IoErrorKind::InvalidInput, "config tun_name missing"))?;
+        Client::open(name, config)
+    }
+
+    fn poll_send_to(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        addr: SocketAddr,
+    ) -> Poll<Result<usize, IoError>> {
+        let bytes = render_ip_packet_bytes(self.inner.local, self.inner.ttl, buf, addr.ip())?;
+
+        loop {
+            let mut guard = ready!(self.inner.fd.poll_write_ready(cx))?;
+            match guard.try_io(|fd| {
+                let n =
+                    unsafe { libc::write(fd.as_raw_fd(), bytes.as_ptr() as *const _, bytes.len()) };
+                if n < 0 {
+                    Err(IoError::last_os_error())
+                } else {
+                    Ok(n as usize)
+                }
+            }) {
+                Ok(ret) => return Poll::Ready(ret.map(|_| buf.len())),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<SocketAddr, IoError>> {
+        let mut bytes = self.inner.recv_bytes.lock().expect("Never when lock");
+
+        loop {
+            let mut guard = ready!(self.inner.fd.poll_read_ready(cx))?;
+            let n = match guard.try_io(|fd| {
+                let n = unsafe {
+                    libc::read(fd.as_raw_fd(), bytes.as_mut_ptr() as *mut _, bytes.len())
+                };
+                if n < 0 {
+                    Err(IoError::last_os_error())
+                } else {
+                    Ok(n as usize)
+                }
+            }) {
+                Ok(Ok(0)) => return Poll::Ready(Err(IoErrorKind::UnexpectedEof.into())),
+                Ok(Ok(n)) => n,
+                Ok(Err(err)) => return Poll::Ready(Err(err)),
+                Err(_would_block) => continue,
+            };
+
+            if let Some((start, end, source)) =
+                parse_ip_packet_bytes(self.inner.local, self.inner.is_raw, &bytes[..n])
+            {
+                buf.put_slice(&bytes[start..end]);
+                return Poll::Ready(Ok((source, 0).into()));
+            }
+        }
+    }
+}
+
+//
+fn set_nonblocking(fd: &OwnedFd) -> Result<(), IoError> {
+    let flags = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_GETFL) };
+    if flags < 0 {
+        return Err(IoError::last_os_error());
+    }
+    if unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+        return Err(IoError::last_os_error());
+    }
+    Ok(())
+}
+
+/// Opens (or creates, with CAP_NET_ADMIN) the TUN device `name`.
+pub fn open_tun(name: &str) -> Result<OwnedFd, IoError> {
+    if name.len() >= libc::IFNAMSIZ {
+        return Err(IoError::new(IoErrorKind::InvalidInput, "name too long"));
+    }
+
+    let fd = unsafe {
+        libc::open(
+            c"/dev/net/tun".as_ptr(),
+            libc::O_RDWR | libc::O_NONBLOCK | libc::O_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return Err(IoError::last_os_error());
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let mut ifreq: libc::ifreq = unsafe { core::mem::zeroed() };
+    for (dst, src) in ifreq.ifr_name.iter_mut().zip(name.as_bytes()) {
+        *dst = *src as libc::c_char;
+    }
+    ifreq.ifr_ifru.ifru_flags = (libc::IFF_TUN | libc::IFF_NO_PI) as libc::c_short;
+    if unsafe { libc::ioctl(fd.as_raw_fd(), libc::TUNSETIFF, &ifreq) } < 0 {
+        return Err(IoError::last_os_error());
+    }
+
+    Ok(fd)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::AsyncClientExt as _;
+
+    use std::net::Ipv4Addr;
+
+    use icmp_packet::{
+        icmpv4,
+        ip::{parse_ipv4_packet_bytes, render_ipv4_packet_bytes},
+        Icmpv4, PayloadLengthDelimitedEchoRequest,
+    };
+
+    #[tokio::test]
+    async fn test_client() -> Result<(), Box<dyn std::error::Error>> {
+        // A datagram socketpair behaves like a TUN device, one packet per read / write.
+        let mut fds = [0; 2];
+        if unsafe { libc::socketpair(libc::AF_UNIX, libc::SOCK_DGRAM, 0, fds.as_mut_ptr()) } < 0 {
+            return Err(IoError::last_os_error().into());
+        }
+        let (fd, peer) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+        let peer = std::os::unix::net::UnixDatagram::from(peer);
+
+        let local = Ipv4Addr::new(10, 0, 0, 1);
+        let remote = Ipv4Addr::new(10, 0, 0, 2);
+        let client = Client::new(fd, &Config::new().bind((local, 0).into()).ttl(3))?;
+
+        let echo_request =
+            PayloadLengthDelimitedEchoRequest::new(Some(1.into()), Some(2.into()), b"1234");
+        client
+            .send_to(&echo_request.render_v4_packet_bytes(), (remote, 0))
+            .await?;
+
+        let mut bytes = vec![0; 1024];
+        let n = peer.recv(&mut bytes)?;
+        let (header, icmp_bytes) = parse_ipv4_packet_bytes(&bytes[..n]).ok_or("ipv4")?;
+        assert_eq!(
+            (header.source, header.destination, header.ttl),
+            (local, remote, 3)
+        );
+        assert_eq!(icmp_bytes, echo_request.render_v4_packet_bytes());
+
+        // an echo request, then the echo reply
+        peer.send(&render_ipv4_packet_bytes(remote, local, 64, icmp_bytes))?;
+        let echo_reply_bytes = icmpv4::render_echo_reply_packet_bytes(icmp_bytes).ok_or("None")?;
+        peer.send(&render_ipv4_packet_bytes(
+            remote,
+            local,
+            64,
+            &echo_reply_bytes,
+        ))?;
+
+        let mut buf = vec![0; 1024];
+        let (n, addr) = client.recv_from(&mut buf).await?;
+        assert_eq!(addr, (remote, 0).into());
+        match Icmpv4::parse_from_packet_bytes(&buf[..n]) {
+            Ok(Some(Icmpv4::EchoReply(echo_reply))) => {
+                assert_eq!(echo_reply.identifier, echo_request.identifier);
+            }
+            x => panic!("{x:?}"),
+        }
+
+        Ok(())
+    }
+}
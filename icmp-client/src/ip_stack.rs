@@ -0,0 +1,101 @@
+//! The IP layer of the clients sending and receiving whole packets, e.g. `impl_tun` and `impl_packet`.
+
+use std::{
+    io::{Error as IoError, ErrorKind as IoErrorKind},
+    net::IpAddr,
+};
+
+use icmp_packet::{
+    ip::{
+        parse_ipv4_packet_bytes, parse_ipv6_packet_bytes, render_ipv4_packet_bytes,
+        render_ipv6_packet_bytes,
+    },
+    pnet_packet::{
+        icmp::IcmpTypes,
+        icmpv6::{self, Icmpv6Packet, Icmpv6Types, MutableIcmpv6Packet},
+        ip::IpNextHeaderProtocols,
+    },
+};
+
+//
+pub(crate) fn render_ip_packet_bytes(
+    local: IpAddr,
+    ttl: u8,
+    buf: &[u8],
+    destination: IpAddr,
+) -> Result<Vec<u8>, IoError> {
+    match (local, destination) {
+        (IpAddr::V4(source), IpAddr::V4(destination)) => {
+            Ok(render_ipv4_packet_bytes(source, destination, ttl, buf))
+        }
+        (IpAddr::V6(source), IpAddr::V6(destination)) => {
+            // The checksum is inserted by the kernel for ICMPv6 sockets, so here.
+            let mut icmp_bytes = buf.to_vec();
+            if let Some(icmpv6_packet) = Icmpv6Packet::new(buf) {
+                let checksum = icmpv6::checksum(
+                    &icmpv6_packet,
+                    &source.octets().into(),
+                    &destination.octets().into(),
+                );
+                MutableIcmpv6Packet::new(&mut icmp_bytes)
+                    .expect("Never when MutableIcmpv6Packet::new")
+                    .set_checksum(checksum);
+            }
+            Ok(render_ipv6_packet_bytes(
+                source,
+                destination,
+                ttl,
+                &icmp_bytes,
+            ))
+        }
+        _ => Err(IoError::new(IoErrorKind::InvalidInput, "address family")),
+    }
+}
+
+/// Returns the range of the bytes to hand out and the source.
+///
+/// Like the DGRAM sockets, echo requests are skipped and no IP header is handed out,
+/// `is_raw` hands out every ICMP packet, IPv4 ones with the IP header.
+pub(crate) fn parse_ip_packet_bytes(
+    local: IpAddr,
+    is_raw: bool,
+    bytes: &[u8],
+) -> Option<(usize, usize, IpAddr)> {
+    let (icmp_bytes, source, is_echo_request) = match local {
+        IpAddr::V4(local) => {
+            let (header, icmp_bytes) = parse_ipv4_packet_bytes(bytes)?;
+            if header.protocol != IpNextHeaderProtocols::Icmp.0 || header.destination != local {
+                return None;
+            }
+            (
+                icmp_bytes,
+                IpAddr::V4(header.source),
+                icmp_bytes.first() == Some(&IcmpTypes::EchoRequest.0),
+            )
+        }
+        IpAddr::V6(local) => {
+            let (header, icmp_bytes) = parse_ipv6_packet_bytes(bytes)?;
+            if header.next_header != IpNextHeaderProtocols::Icmpv6.0 || header.destination != local
+            {
+                return None;
+            }
+            (
+                icmp_bytes,
+                IpAddr::V6(header.source),
+                icmp_bytes.first() == Some(&Icmpv6Types::EchoRequest.0),
+            )
+        }
+    };
+
+    let end = icmp_bytes.as_ptr() as usize - bytes.as_ptr() as usize + icmp_bytes.len();
+    if is_raw {
+        match source {
+            IpAddr::V4(_) => Some((0, end, source)),
+            IpAddr::V6(_) => Some((end - icmp_bytes.len(), end, source)),
+        }
+    } else if is_echo_request {
+        None
+    } else {
+        Some((end - icmp_bytes.len(), end, source))
+    }
+}
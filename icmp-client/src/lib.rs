@@ -1,34 +1,114 @@
 //
-use std::{io::Error as IoError, net::SocketAddr};
+use core::task::{Context, Poll};
+use std::{
+    io::{Error as IoError, ErrorKind as IoErrorKind},
+    net::SocketAddr,
+};
 
-use async_trait::async_trait;
-
-#[async_trait]
+/// Object safe, e.g. `Box<dyn AsyncClient + Send + Sync>` to mix backends,
+/// the futures are in [`AsyncClientExt`].
+///
+/// Like with `tokio::net::UdpSocket`, only the last task polling a direction is woken.
 pub trait AsyncClient {
     fn with_config(config: &Config) -> Result<Self, AsyncClientWithConfigError>
     where
         Self: Sized;
 
-    async fn send_to<A: Into<SocketAddr> + Send>(
+    fn poll_send_to(
         &self,
+        cx: &mut Context<'_>,
         buf: &[u8],
-        addr: A,
-    ) -> Result<usize, IoError>;
-    async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), IoError>;
+        addr: SocketAddr,
+    ) -> Poll<Result<usize, IoError>>;
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<SocketAddr, IoError>>;
+
+    fn stats(&self) -> Stats {
+        Stats::default()
+    }
+
+    /// On a client connected with `Config::connect`.
+    fn poll_send(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize, IoError>> {
+        let _ = (cx, buf);
+        Poll::Ready(Err(IoError::new(
+            IoErrorKind::Unsupported,
+            "send unsupported",
+        )))
+    }
+    /// On a client connected with `Config::connect`, errors about the peer are returned here.
+    fn poll_recv(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<(), IoError>> {
+        let _ = (cx, buf);
+        Poll::Ready(Err(IoError::new(
+            IoErrorKind::Unsupported,
+            "recv unsupported",
+        )))
+    }
+}
+
+/// `with_config` is unsupported, box a client built with its own type.
+impl<C> AsyncClient for Box<C>
+where
+    C: AsyncClient + ?Sized,
+{
+    fn with_config(config: &Config) -> Result<Self, AsyncClientWithConfigError> {
+        let _ = config;
+        Err(IoError::new(IoErrorKind::Unsupported, "with_config unsupported on Box").into())
+    }
+
+    fn poll_send_to(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        addr: SocketAddr,
+    ) -> Poll<Result<usize, IoError>> {
+        (**self).poll_send_to(cx, buf, addr)
+    }
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<SocketAddr, IoError>> {
+        (**self).poll_recv_from(cx, buf)
+    }
+
+    fn stats(&self) -> Stats {
+        (**self).stats()
+    }
+
+    fn poll_send(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize, IoError>> {
+        (**self).poll_send(cx, buf)
+    }
+    fn poll_recv(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<(), IoError>> {
+        (**self).poll_recv(cx, buf)
+    }
 }
 
 //
 #[derive(Debug)]
 pub enum AsyncClientWithConfigError {
     IcmpV6ProtocolNotSupported(IoError),
+    PermissionDenied(PermissionDeniedError),
     OtherIoError(IoError),
 }
 impl core::fmt::Display for AsyncClientWithConfigError {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "{self:?}")
+        match self {
+            Self::PermissionDenied(err) => write!(f, "{err}"),
+            _ => write!(f, "{self:?}"),
+        }
+    }
+}
+impl std::error::Error for AsyncClientWithConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::IcmpV6ProtocolNotSupported(err) | Self::OtherIoError(err) => Some(err),
+            Self::PermissionDenied(err) => Some(&err.err),
+        }
     }
 }
-impl std::error::Error for AsyncClientWithConfigError {}
 
 impl From<IoError> for AsyncClientWithConfigError {
     fn from(err: IoError) -> Self {
@@ -36,17 +116,93 @@ impl From<IoError> for AsyncClientWithConfigError {
     }
 }
 
+//
+#[derive(Debug)]
+pub struct PermissionDeniedError {
+    pub socket_type: SocketType,
+    pub is_ipv6: bool,
+    /// Effective gid of the process, Linux only.
+    pub gid: Option<u32>,
+    /// `net.ipv4.ping_group_range`, also for IPv6, Linux only.
+    pub ping_group_range: Option<(u32, u32)>,
+    pub err: IoError,
+}
+
+impl PermissionDeniedError {
+    pub fn cause(&self) -> String {
+        match (self.socket_type, self.gid, self.ping_group_range) {
+            (SocketType::Dgram, Some(gid), Some((min, max))) => {
+                format!("gid {gid} is outside net.ipv4.ping_group_range \"{min} {max}\"")
+            }
+            (SocketType::Dgram, _, _) => "unprivileged ICMP sockets not allowed".to_owned(),
+            (SocketType::Raw, _, _) => "missing CAP_NET_RAW (or root)".to_owned(),
+        }
+    }
+
+    pub fn fix(&self) -> String {
+        match (self.socket_type, self.gid) {
+            (SocketType::Dgram, Some(gid)) => format!(
+                "sysctl -w net.ipv4.ping_group_range=\"0 {}\", or SocketType::Raw with CAP_NET_RAW",
+                gid.max(1)
+            ),
+            (SocketType::Dgram, None) => "SocketType::Raw with root".to_owned(),
+            (SocketType::Raw, _) => "run as root, setcap cap_net_raw+ep on the binary, SocketType::Dgram, or the privsep helper".to_owned(),
+        }
+    }
+}
+
+impl core::fmt::Display for PermissionDeniedError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "permission denied creating {:?} ICMPv{} socket, cause: {}, fix: {} ({})",
+            self.socket_type,
+            if self.is_ipv6 { 6 } else { 4 },
+            self.cause(),
+            self.fix(),
+            self.err
+        )
+    }
+}
+
 //
 pub mod config;
-pub use config::Config;
+pub use config::{Config, SocketType};
+pub mod dual_stack;
+pub use dual_stack::DualStackClient;
+pub mod ext;
+pub use ext::AsyncClientExt;
+pub mod read_buf;
+pub use read_buf::ReadBuf;
 
+#[cfg(target_os = "linux")]
+pub mod filter;
+#[cfg(all(
+    any(feature = "impl_tun", feature = "impl_packet"),
+    target_os = "linux"
+))]
+pub(crate) mod ip_stack;
+#[cfg(target_os = "linux")]
+pub mod netns;
+pub mod probe;
+pub mod stats;
+pub use stats::Stats;
 pub mod utils;
 
 //
 #[cfg(feature = "impl_async_io")]
 pub mod impl_async_io;
+#[cfg(all(feature = "impl_packet", target_os = "linux"))]
+pub mod impl_packet;
+#[cfg(feature = "impl_sim")]
+pub mod impl_sim;
 #[cfg(feature = "impl_tokio")]
 pub mod impl_tokio;
+#[cfg(all(feature = "impl_tun", target_os = "linux"))]
+pub mod impl_tun;
+
+#[cfg(all(feature = "privsep", unix))]
+pub mod privsep;
 
 #[cfg(any(feature = "impl_async_io", feature = "impl_tokio"))]
 #[cfg(test)]
@@ -63,6 +219,13 @@ pub(crate) mod tests_helper {
         // ipv4
         let client = C::with_config(&Config::new().ttl(64))?;
 
+        ping_ipv4_with_client(&client, ip).await
+    }
+
+    pub(crate) async fn ping_ipv4_with_client<C: AsyncClient>(
+        client: &C,
+        ip: Ipv4Addr,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         let echo_request =
             PayloadLengthDelimitedEchoRequest::new(Some(1.into()), Some(2.into()), b"1234");
 
@@ -0,0 +1,112 @@
+//! Linux network namespaces.
+//!
+//! Sockets belong to the namespace they were created in, so they are created on a short-lived thread
+//! that joined the namespace, the caller's thread stays in its own.
+//! Joining needs CAP_SYS_ADMIN.
+
+use std::{
+    fs::File,
+    io::Error as IoError,
+    os::fd::AsRawFd as _,
+    path::{Path, PathBuf},
+};
+
+//
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Netns {
+    /// e.g. `/var/run/netns/NAME`, or `/proc/PID/task/TID/ns/net`.
+    Path(PathBuf),
+    /// The namespace of the process `pid`.
+    Pid(u32),
+}
+
+impl Netns {
+    /// Named by `ip netns add NAME`.
+    pub fn named(name: impl AsRef<Path>) -> Self {
+        Self::Path(Path::new("/var/run/netns").join(name))
+    }
+
+    pub fn path(&self) -> PathBuf {
+        match self {
+            Self::Path(path) => path.to_owned(),
+            Self::Pid(pid) => format!("/proc/{pid}/ns/net").into(),
+        }
+    }
+}
+
+//
+/// Calls `f` on a thread inside `netns`.
+pub fn run_in_netns<T, F>(netns: &Netns, f: F) -> Result<T, IoError>
+where
+    T: Send,
+    F: FnOnce() -> T + Send,
+{
+    let file = File::open(netns.path())?;
+
+    std::thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                if unsafe { libc::setns(file.as_raw_fd(), libc::CLONE_NEWNET) } < 0 {
+                    return Err(IoError::last_os_error());
+                }
+                Ok(f())
+            })
+            .join()
+            .expect("Never when join")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc;
+
+    use crate::{utils::new_socket2_socket, AsyncClientWithConfigError, Config, SocketType};
+
+    #[test]
+    fn test_run_in_netns() -> Result<(), Box<dyn std::error::Error>> {
+        // A thread in a new, empty, namespace.
+        let (tx, rx) = mpsc::channel();
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+        let handle = std::thread::spawn(move || {
+            let ret = if unsafe { libc::unshare(libc::CLONE_NEWNET) } < 0 {
+                Err(IoError::last_os_error())
+            } else {
+                Ok(Netns::Path(
+                    format!("/proc/{}/task/{}/ns/net", std::process::id(), unsafe {
+                        libc::gettid()
+                    })
+                    .into(),
+                ))
+            };
+            tx.send(ret).expect("Never when send");
+            let _ = done_rx.recv();
+        });
+        let netns = match rx.recv()? {
+            Ok(x) => x,
+            Err(err) if err.raw_os_error() == Some(libc::EPERM) => {
+                eprintln!("skipped, needs CAP_SYS_ADMIN, err:{err}");
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        // net.ipv4.ping_group_range is per namespace, "1 0" in a new one.
+        let config = Config::new();
+        assert!(new_socket2_socket(&config).is_ok());
+        assert!(matches!(
+            new_socket2_socket(&config.clone().netns(netns.clone())),
+            Err(AsyncClientWithConfigError::PermissionDenied(_))
+        ));
+        let config = Config::new().socket_type(SocketType::Raw);
+        assert!(new_socket2_socket(&config.netns(netns.clone())).is_ok());
+
+        assert_eq!(run_in_netns(&Netns::Pid(std::process::id()), || 1)?, 1);
+
+        drop(done_tx);
+        handle.join().expect("Never");
+
+        Ok(())
+    }
+}
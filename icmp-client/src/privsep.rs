@@ -0,0 +1,469 @@
+//! Privilege separation, a privileged helper opens the sockets and passes them over a Unix socket.
+//!
+//! The unprivileged process sends the [`Config`] with [`request_socket`],
+//! the helper answers with [`serve`], opening the socket with that config
+//! and sending its file descriptor back with `SCM_RIGHTS`.
+//! The received socket is then wrapped with `Client::from_socket2`.
+//!
+//! Access to the helper is controlled by the permissions of its Unix socket.
+//! Only ICMP sockets are opened, of these [`Config`] fields:
+//! `is_ipv6`, `socket_type`, `connect`, `interface_index`, `ttl`, `ip_options`,
+//! `recv_buffer_size`, `send_buffer_size`, `fib`, `icmp_filter` and `echo_reply_filter`,
+//! plus `bind` and `netns` within the allowlists of [`ServeConfig`], no namespace by default.
+
+use core::mem::size_of;
+use std::{
+    io::{Error as IoError, ErrorKind as IoErrorKind, Read as _},
+    net::{IpAddr, SocketAddr},
+    os::{
+        fd::{AsRawFd as _, FromRawFd as _, OwnedFd, RawFd},
+        unix::net::UnixStream,
+    },
+};
+
+use crate::{
+    config::{Config, SocketType},
+    utils::new_socket2_socket,
+    AsyncClientWithConfigError,
+};
+
+//
+const MESSAGE_MAX_LEN: usize = 1024;
+
+//
+/// What the clients of the helper may ask for beyond plain ICMP sockets.
+#[derive(Debug, Clone, Default)]
+pub struct ServeConfig {
+    /// Local addresses to bind to, `None` for any.
+    pub bind_allowlist: Option<Vec<IpAddr>>,
+    /// Namespaces to open sockets in, joining one needs CAP_SYS_ADMIN.
+    #[cfg(target_os = "linux")]
+    pub netns_allowlist: Vec<crate::netns::Netns>,
+}
+
+impl ServeConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bind_allowlist(mut self, addrs: impl IntoIterator<Item = IpAddr>) -> Self {
+        self.bind_allowlist = Some(addrs.into_iter().collect());
+        self
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn allow_netns(mut self, netns: crate::netns::Netns) -> Self {
+        self.netns_allowlist.push(netns);
+        self
+    }
+
+    fn check(&self, config: &Config) -> Result<(), IoError> {
+        let denied = |field: &str| {
+            IoError::new(
+                IoErrorKind::PermissionDenied,
+                format!("{field} not allowed"),
+            )
+        };
+        if let (Some(bind), Some(bind_allowlist)) = (config.bind, self.bind_allowlist.as_ref()) {
+            if !bind_allowlist.contains(&bind.ip()) {
+                return Err(denied("bind"));
+            }
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(netns) = config.netns.as_ref() {
+            if !self.netns_allowlist.contains(netns) {
+                return Err(denied("netns"));
+            }
+        }
+        Ok(())
+    }
+}
+
+//
+/// Asks the helper on the other end of `stream` for a socket opened with `config`.
+pub fn request_socket(
+    stream: &UnixStream,
+    config: &Config,
+) -> Result<socket2::Socket, AsyncClientWithConfigError> {
+    let request = format!("{}\n", encode_config(config));
+    send_with_fd(stream, request.as_bytes(), None)?;
+
+    let (response, fd) = recv_with_fd(stream)?;
+    match (response.strip_prefix("ok"), fd) {
+        (Some(_), Some(fd)) => Ok(fd.into()),
+        (Some(_), None) => Err(IoError::new(IoErrorKind::InvalidData, "fd missing").into()),
+        (None, _) => {
+            let message = response.strip_prefix("err ").unwrap_or(&response);
+            if message.starts_with("IcmpV6ProtocolNotSupported") {
+                Err(AsyncClientWithConfigError::IcmpV6ProtocolNotSupported(
+                    IoError::other(message.to_owned()),
+                ))
+            } else {
+                Err(IoError::other(message.to_owned()).into())
+            }
+        }
+    }
+}
+
+/// Answers the requests coming on `stream` until it is closed, with the default [`ServeConfig`].
+pub fn serve(stream: &UnixStream) -> Result<(), IoError> {
+    serve_with_config(stream, &ServeConfig::default())
+}
+
+pub fn serve_with_config(stream: &UnixStream, serve_config: &ServeConfig) -> Result<(), IoError> {
+    loop {
+        let request = match read_line(stream)? {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+
+        let result = decode_config(&request)
+            .and_then(|config| serve_config.check(&config).map(|_| config))
+            .map_err(AsyncClientWithConfigError::from)
+            .and_then(|config| new_socket2_socket(&config));
+        match result {
+            Ok(socket) => send_with_fd(stream, b"ok\n", Some(socket.as_raw_fd()))?,
+            Err(err) => {
+                let message = format!("err {err:?}\n").replace('\n', " ");
+                send_with_fd(stream, format!("{}\n", message.trim_end()).as_bytes(), None)?
+            }
+        }
+    }
+}
+
+//
+/// Space separated `key=value`, only the options the helper can apply.
+pub fn encode_config(config: &Config) -> String {
+    let mut fields = vec![
+        format!("ipv6={}", config.is_ipv6() as u8),
+        format!("raw={}", config.is_raw() as u8),
+    ];
+    if let Some(bind) = config.bind {
+        fields.push(format!("bind={bind}"));
+    }
+    if let Some(connect) = config.connect {
+        fields.push(format!("connect={connect}"));
+    }
+    if let Some(interface_index) = config.interface_index {
+        fields.push(format!("interface_index={interface_index}"));
+    }
+    if let Some(ttl) = config.ttl {
+        fields.push(format!("ttl={ttl}"));
+    }
+    if let Some(ip_options) = &config.ip_options {
+        fields.push(format!("ip_options={}", join_u8s(ip_options)));
+    }
+    if let Some(recv_buffer_size) = config.recv_buffer_size {
+        fields.push(format!("recv_buffer_size={recv_buffer_size}"));
+    }
+    if let Some(send_buffer_size) = config.send_buffer_size {
+        fields.push(format!("send_buffer_size={send_buffer_size}"));
+    }
+    if let Some(fib) = config.fib {
+        fields.push(format!("fib={fib}"));
+    }
+    if let Some(icmp_types) = &config.icmp_filter {
+        fields.push(format!("icmp_filter={}", join_u8s(icmp_types)));
+    }
+    if let Some((identifier, error_types)) = &config.echo_reply_filter {
+        fields.push(format!(
+            "echo_reply_filter={identifier}:{}",
+            join_u8s(error_types)
+        ));
+    }
+    #[cfg(target_os = "linux")]
+    match &config.netns {
+        Some(crate::netns::Netns::Path(path)) => {
+            fields.push(format!("netns={}", path.display()));
+        }
+        Some(crate::netns::Netns::Pid(pid)) => {
+            fields.push(format!("netns_pid={pid}"));
+        }
+        None => {}
+    }
+    fields.join(" ")
+}
+
+pub fn decode_config(s: &str) -> Result<Config, IoError> {
+    let invalid = |field: &str| IoError::new(IoErrorKind::InvalidData, format!("{field} invalid"));
+
+    let mut config = if s.split_whitespace().any(|x| x == "ipv6=1") {
+        Config::with_ipv6()
+    } else {
+        Config::new()
+    };
+    for field in s.split_whitespace() {
+        let (key, value) = field.split_once('=').ok_or_else(|| invalid(field))?;
+        match key {
+            "ipv6" => {}
+            "raw" => {
+                if value == "1" {
+                    config.socket_type = SocketType::Raw;
+                }
+            }
+            "bind" => {
+                config.bind = Some(value.parse::<SocketAddr>().map_err(|_| invalid(field))?);
+            }
+            "connect" => {
+                config.connect = Some(value.parse::<SocketAddr>().map_err(|_| invalid(field))?);
+            }
+            "interface_index" => {
+                config.interface_index = Some(value.parse().map_err(|_| invalid(field))?);
+            }
+            "ttl" => {
+                config.ttl = Some(value.parse().map_err(|_| invalid(field))?);
+            }
+            "ip_options" => {
+                config.ip_options = Some(split_u8s(value).map_err(|_| invalid(field))?);
+            }
+            "recv_buffer_size" => {
+                config.recv_buffer_size = Some(value.parse().map_err(|_| invalid(field))?);
+            }
+            "send_buffer_size" => {
+                config.send_buffer_size = Some(value.parse().map_err(|_| invalid(field))?);
+            }
+            "fib" => {
+                config.fib = Some(value.parse().map_err(|_| invalid(field))?);
+            }
+            "icmp_filter" => {
+                config.icmp_filter = Some(split_u8s(value).map_err(|_| invalid(field))?);
+            }
+            "echo_reply_filter" => {
+                let (identifier, error_types) =
+                    value.split_once(':').ok_or_else(|| invalid(field))?;
+                config.echo_reply_filter = Some((
+                    identifier.parse().map_err(|_| invalid(field))?,
+                    split_u8s(error_types).map_err(|_| invalid(field))?,
+                ));
+            }
+            #[cfg(target_os = "linux")]
+            "netns" => {
+                config.netns = Some(crate::netns::Netns::Path(value.into()));
+            }
+            #[cfg(target_os = "linux")]
+            "netns_pid" => {
+                config.netns = Some(crate::netns::Netns::Pid(
+                    value.parse().map_err(|_| invalid(field))?,
+                ));
+            }
+            _ => return Err(invalid(field)),
+        }
+    }
+    Ok(config)
+}
+
+fn join_u8s(values: &[u8]) -> String {
+    values
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn split_u8s(s: &str) -> Result<Vec<u8>, core::num::ParseIntError> {
+    s.split(',')
+        .filter(|x| !x.is_empty())
+        .map(|x| x.parse())
+        .collect()
+}
+
+//
+fn read_line(stream: &UnixStream) -> Result<Option<String>, IoError> {
+    let mut bytes = vec![];
+    let mut byte = [0; 1];
+    loop {
+        match (&*stream).read(&mut byte)? {
+            0 if bytes.is_empty() => return Ok(None),
+            0 => return Err(IoErrorKind::UnexpectedEof.into()),
+            _ if byte[0] == b'\n' => break,
+            _ => bytes.push(byte[0]),
+        }
+        if bytes.len() > MESSAGE_MAX_LEN {
+            return Err(IoError::new(IoErrorKind::InvalidData, "line too long"));
+        }
+    }
+    String::from_utf8(bytes)
+        .map(Some)
+        .map_err(|err| IoError::new(IoErrorKind::InvalidData, err))
+}
+
+fn send_with_fd(stream: &UnixStream, bytes: &[u8], fd: Option<RawFd>) -> Result<(), IoError> {
+    let mut iov = libc::iovec {
+        iov_base: bytes.as_ptr() as *mut _,
+        iov_len: bytes.len(),
+    };
+    // u64 for the alignment of cmsghdr.
+    let mut cmsg_buf = [0_u64; 8];
+
+    let mut msg: libc::msghdr = unsafe { core::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    if let Some(fd) = fd {
+        unsafe {
+            msg.msg_control = cmsg_buf.as_mut_ptr() as *mut _;
+            msg.msg_controllen = libc::CMSG_SPACE(size_of::<libc::c_int>() as u32) as _;
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(size_of::<libc::c_int>() as u32) as _;
+            core::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut libc::c_int, fd);
+        }
+    }
+
+    let n = unsafe { libc::sendmsg(stream.as_raw_fd(), &msg, 0) };
+    if n < 0 {
+        return Err(IoError::last_os_error());
+    }
+    if (n as usize) < bytes.len() {
+        return Err(IoErrorKind::WriteZero.into());
+    }
+    Ok(())
+}
+
+fn recv_with_fd(stream: &UnixStream) -> Result<(String, Option<OwnedFd>), IoError> {
+    let mut bytes = [0_u8; MESSAGE_MAX_LEN];
+    let mut iov = libc::iovec {
+        iov_base: bytes.as_mut_ptr() as *mut _,
+        iov_len: bytes.len(),
+    };
+    let mut cmsg_buf = [0_u64; 8];
+
+    let mut msg: libc::msghdr = unsafe { core::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = cmsg_buf.as_mut_ptr() as *mut _;
+    msg.msg_controllen = core::mem::size_of_val(&cmsg_buf) as _;
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let flags = libc::MSG_CMSG_CLOEXEC;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let flags = 0;
+
+    let n = unsafe { libc::recvmsg(stream.as_raw_fd(), &mut msg, flags) };
+    if n < 0 {
+        return Err(IoError::last_os_error());
+    }
+    if n == 0 {
+        return Err(IoErrorKind::UnexpectedEof.into());
+    }
+
+    let mut fd = None;
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let raw_fd = core::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::c_int);
+                fd = Some(OwnedFd::from_raw_fd(raw_fd));
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+
+    let mut response = bytes[..n as usize].to_vec();
+    while response.last() != Some(&b'\n') {
+        let mut byte = [0; 1];
+        if (&*stream).read(&mut byte)? == 0 {
+            return Err(IoErrorKind::UnexpectedEof.into());
+        }
+        response.push(byte[0]);
+    }
+    response.pop();
+
+    let response =
+        String::from_utf8(response).map_err(|err| IoError::new(IoErrorKind::InvalidData, err))?;
+    Ok((response, fd))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_and_decode_config() -> Result<(), Box<dyn std::error::Error>> {
+        let config = Config::with_ipv6()
+            .socket_type(SocketType::Raw)
+            .ttl(3)
+            .interface_index(2.try_into()?)
+            .icmp_filter([129, 1])
+            .echo_reply_filter(0x1234, []);
+        let decoded = decode_config(&encode_config(&config))?;
+        assert!(decoded.is_ipv6());
+        assert!(decoded.is_raw());
+        assert_eq!(decoded.ttl, Some(3));
+        assert_eq!(decoded.interface_index, config.interface_index);
+        assert_eq!(decoded.icmp_filter, config.icmp_filter);
+        assert_eq!(decoded.echo_reply_filter, config.echo_reply_filter);
+
+        let config = Config::new()
+            .bind("127.0.0.1:0".parse()?)
+            .ip_options([1, 7, 7, 4, 0, 0, 0, 0]);
+        let decoded = decode_config(&encode_config(&config))?;
+        assert!(!decoded.is_ipv6());
+        assert!(!decoded.is_raw());
+        assert_eq!(decoded.bind, config.bind);
+        assert_eq!(decoded.ip_options, config.ip_options);
+
+        assert!(decode_config("ttl=x").is_err());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "impl_tokio")]
+    #[tokio::test]
+    async fn test_request_socket() -> Result<(), Box<dyn std::error::Error>> {
+        let (stream, helper_stream) = UnixStream::pair()?;
+        let helper = std::thread::spawn(move || serve(&helper_stream));
+
+        let socket = request_socket(&stream, &Config::new())?;
+        let client = crate::impl_tokio::Client::from_socket2(socket, Some(&Config::new().ttl(64)))?;
+        crate::tests_helper::ping_ipv4_with_client(&client, "127.0.0.1".parse().expect("Never"))
+            .await?;
+
+        match request_socket(&stream, &Config::new().bind("[::1]:0".parse()?).ttl(0)) {
+            Err(AsyncClientWithConfigError::OtherIoError(_))
+            | Err(AsyncClientWithConfigError::IcmpV6ProtocolNotSupported(_)) => {}
+            x => panic!("{x:?}"),
+        }
+
+        drop(stream);
+        helper.join().expect("Never")?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_serve_config_check() -> Result<(), Box<dyn std::error::Error>> {
+        let serve_config = ServeConfig::new();
+        assert!(serve_config
+            .check(&Config::new().bind("127.0.0.1:0".parse()?))
+            .is_ok());
+
+        let serve_config = serve_config.bind_allowlist(["127.0.0.1".parse()?]);
+        assert!(serve_config
+            .check(&Config::new().bind("127.0.0.1:0".parse()?))
+            .is_ok());
+        let err = serve_config
+            .check(&Config::new().bind("127.0.0.2:0".parse()?))
+            .expect_err("Never");
+        assert_eq!(err.kind(), IoErrorKind::PermissionDenied);
+
+        #[cfg(target_os = "linux")]
+        {
+            use crate::netns::Netns;
+
+            let config = decode_config("ipv6=0 raw=0 netns=/proc/1/ns/net")?;
+            let err = serve_config.check(&config).expect_err("Never");
+            assert_eq!(err.kind(), IoErrorKind::PermissionDenied);
+            let config = decode_config("ipv6=0 raw=0 netns_pid=1")?;
+            assert!(serve_config.check(&config).is_err());
+
+            let serve_config = serve_config.allow_netns(Netns::Pid(1));
+            assert!(serve_config.check(&config).is_ok());
+            // Same namespace, another name.
+            let config = decode_config("ipv6=0 raw=0 netns=/proc/1/ns/net")?;
+            assert!(serve_config.check(&config).is_err());
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+//! Which socket modes are available to the current process.
+
+use crate::{
+    config::{Config, SocketType},
+    utils::new_socket2_socket,
+    AsyncClientWithConfigError,
+};
+
+//
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    pub dgram_v4: bool,
+    pub dgram_v6: bool,
+    pub raw_v4: bool,
+    pub raw_v6: bool,
+    /// SO_BINDTODEVICE on Linux, IP_BOUND_IF on Apple platforms.
+    pub bind_to_device: bool,
+    /// SO_TIMESTAMP, kernel receive timestamps.
+    pub timestamps: bool,
+}
+
+/// Opens (and closes) one socket per mode.
+pub fn probe() -> Capabilities {
+    let sockets = [
+        Config::new(),
+        Config::with_ipv6(),
+        Config::new().socket_type(SocketType::Raw),
+        Config::with_ipv6().socket_type(SocketType::Raw),
+    ]
+    .map(|config| new_socket2_socket(&config).ok());
+
+    let socket = sockets.iter().flatten().next();
+
+    Capabilities {
+        dgram_v4: sockets[0].is_some(),
+        dgram_v6: sockets[1].is_some(),
+        raw_v4: sockets[2].is_some(),
+        raw_v6: sockets[3].is_some(),
+        bind_to_device: socket.map(probe_bind_to_device).unwrap_or_default(),
+        timestamps: socket.map(probe_timestamps).unwrap_or_default(),
+    }
+}
+
+/// Like [`probe`] for one config, the error tells why the mode is not available.
+pub fn probe_config(config: &Config) -> Result<(), AsyncClientWithConfigError> {
+    new_socket2_socket(config).map(|_| ())
+}
+
+//
+#[allow(unused_variables)]
+fn probe_bind_to_device(socket: &socket2::Socket) -> bool {
+    #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+    return socket.bind_device(Some(b"lo")).is_ok();
+
+    #[cfg(any(
+        target_os = "ios",
+        target_os = "macos",
+        target_os = "tvos",
+        target_os = "watchos",
+    ))]
+    return socket
+        .bind_device_by_index_v4(core::num::NonZeroU32::new(1))
+        .or_else(|_| socket.bind_device_by_index_v6(core::num::NonZeroU32::new(1)))
+        .is_ok();
+
+    #[allow(unreachable_code)]
+    false
+}
+
+#[allow(unused_variables)]
+fn probe_timestamps(socket: &socket2::Socket) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::fd::AsRawFd as _;
+
+        let on: libc::c_int = 1;
+        return unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_TIMESTAMP,
+                &on as *const libc::c_int as *const _,
+                core::mem::size_of_val(&on) as libc::socklen_t,
+            )
+        } == 0;
+    }
+
+    #[allow(unreachable_code)]
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Error as IoError, ErrorKind as IoErrorKind};
+
+    use crate::PermissionDeniedError;
+
+    #[test]
+    fn test_probe() {
+        let capabilities = probe();
+        println!("{capabilities:?}");
+        assert!(capabilities.dgram_v4 || capabilities.raw_v4);
+        assert_eq!(probe_config(&Config::new()).is_ok(), capabilities.dgram_v4);
+    }
+
+    #[test]
+    fn test_permission_denied_error() {
+        let err = AsyncClientWithConfigError::PermissionDenied(PermissionDeniedError {
+            socket_type: SocketType::Dgram,
+            is_ipv6: false,
+            gid: Some(1000),
+            ping_group_range: Some((1, 0)),
+            err: IoError::from(IoErrorKind::PermissionDenied),
+        });
+        let s = err.to_string();
+        assert!(s.contains("gid 1000 is outside net.ipv4.ping_group_range \"1 0\""));
+        assert!(s.contains("sysctl -w net.ipv4.ping_group_range=\"0 1000\""));
+    }
+}
@@ -0,0 +1,69 @@
+//! A buffer filled by [`AsyncClient::poll_recv_from`](crate::AsyncClient::poll_recv_from),
+//! like `tokio::io::ReadBuf` without the uninitialized part.
+
+//
+#[derive(Debug)]
+pub struct ReadBuf<'a> {
+    buf: &'a mut [u8],
+    filled: usize,
+}
+
+impl<'a> ReadBuf<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, filled: 0 }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn filled(&self) -> &[u8] {
+        &self.buf[..self.filled]
+    }
+
+    pub fn unfilled_mut(&mut self) -> &mut [u8] {
+        &mut self.buf[self.filled..]
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.filled
+    }
+
+    /// Marks `n` more bytes of `unfilled_mut` as filled.
+    pub fn advance(&mut self, n: usize) {
+        assert!(n <= self.remaining(), "filled overflow");
+        self.filled += n;
+    }
+
+    /// Copies as much of `bytes` as fits, returns that length.
+    pub fn put_slice(&mut self, bytes: &[u8]) -> usize {
+        let n = bytes.len().min(self.remaining());
+        self.unfilled_mut()[..n].copy_from_slice(&bytes[..n]);
+        self.filled += n;
+        n
+    }
+
+    pub fn clear(&mut self) {
+        self.filled = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_buf() {
+        let mut bytes = [0; 4];
+        let mut buf = ReadBuf::new(&mut bytes);
+        assert_eq!(buf.put_slice(b"12"), 2);
+        buf.unfilled_mut()[0] = b'3';
+        buf.advance(1);
+        assert_eq!(buf.filled(), b"123");
+        assert_eq!(buf.put_slice(b"45"), 1);
+        assert_eq!(buf.filled(), b"1234");
+        assert_eq!(buf.remaining(), 0);
+        buf.clear();
+        assert_eq!(buf.capacity(), 4);
+    }
+}
@@ -0,0 +1,74 @@
+use core::ops::Add;
+#[cfg(any(
+    feature = "impl_tokio",
+    feature = "impl_async_io",
+    all(feature = "impl_packet", target_os = "linux")
+))]
+use core::sync::atomic::{AtomicU64, Ordering};
+
+//
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    /// Packets dropped by the kernel before being received, from SO_RXQ_OVFL or SO_MEMINFO, Linux only.
+    ///
+    /// Raw sockets also count the dropped packets not for us, e.g. echo requests.
+    pub kernel_drops: u64,
+}
+
+impl Add for Stats {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self {
+            packets_sent: self.packets_sent + rhs.packets_sent,
+            packets_received: self.packets_received + rhs.packets_received,
+            kernel_drops: self.kernel_drops + rhs.kernel_drops,
+        }
+    }
+}
+
+//
+#[cfg(any(
+    feature = "impl_tokio",
+    feature = "impl_async_io",
+    all(feature = "impl_packet", target_os = "linux")
+))]
+#[derive(Debug, Default)]
+pub(crate) struct StatsCounter {
+    packets_sent: AtomicU64,
+    packets_received: AtomicU64,
+    kernel_drops: AtomicU64,
+}
+
+#[cfg(any(
+    feature = "impl_tokio",
+    feature = "impl_async_io",
+    all(feature = "impl_packet", target_os = "linux")
+))]
+impl StatsCounter {
+    pub(crate) fn on_sent(&self) {
+        self.packets_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn on_received(&self, kernel_drops: Option<u32>) {
+        self.packets_received.fetch_add(1, Ordering::Relaxed);
+        // The counter of the socket, not a delta.
+        if let Some(kernel_drops) = kernel_drops {
+            self.kernel_drops
+                .fetch_max(kernel_drops as u64, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn get(&self, kernel_drops: Option<u32>) -> Stats {
+        Stats {
+            packets_sent: self.packets_sent.load(Ordering::Relaxed),
+            packets_received: self.packets_received.load(Ordering::Relaxed),
+            kernel_drops: self
+                .kernel_drops
+                .load(Ordering::Relaxed)
+                .max(kernel_drops.unwrap_or_default() as u64),
+        }
+    }
+}
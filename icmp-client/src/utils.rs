@@ -1,28 +1,97 @@
 use std::net::UdpSocket;
 
-use crate::{config::Config, AsyncClientWithConfigError};
+use crate::{config::Config, AsyncClientWithConfigError, PermissionDeniedError};
 
-// Ref https://github.com/kolapapa/surge-ping/blob/0.7.3/src/client.rs#L36-L54
 pub fn new_socket2_socket(config: &Config) -> Result<socket2::Socket, AsyncClientWithConfigError> {
-    use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+    #[cfg(target_os = "linux")]
+    if let Some(netns) = &config.netns {
+        return crate::netns::run_in_netns(netns, || new_socket2_socket_in_current_netns(config))?;
+    }
 
-    let socket = if config.is_ipv6() {
-        Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::ICMPV6)).map_err(|err| {
-            if err.raw_os_error() == Some(93) {
-                AsyncClientWithConfigError::IcmpV6ProtocolNotSupported(err)
-            } else {
-                AsyncClientWithConfigError::OtherIoError(err)
-            }
-        })?
+    new_socket2_socket_in_current_netns(config)
+}
+
+// Ref https://github.com/kolapapa/surge-ping/blob/0.7.3/src/client.rs#L36-L54
+fn new_socket2_socket_in_current_netns(
+    config: &Config,
+) -> Result<socket2::Socket, AsyncClientWithConfigError> {
+    use socket2::{Domain, Protocol, Socket, Type};
+
+    let ty = if config.is_raw() {
+        Type::RAW
     } else {
-        Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::ICMPV4))?
+        Type::DGRAM
     };
 
+    let socket = if config.is_ipv6() {
+        Socket::new(Domain::IPV6, ty, Some(Protocol::ICMPV6))
+    } else {
+        Socket::new(Domain::IPV4, ty, Some(Protocol::ICMPV4))
+    }
+    .map_err(|err| socket_creation_error(err, config))?;
+
+    apply_config(&socket, config)?;
+
+    Ok(socket)
+}
+
+fn socket_creation_error(err: std::io::Error, config: &Config) -> AsyncClientWithConfigError {
+    #[cfg(unix)]
+    let protocol_not_supported = [libc::EPROTONOSUPPORT, libc::EAFNOSUPPORT];
+    #[cfg(windows)]
+    let protocol_not_supported = [10043, 10047];
+
+    if err.kind() == std::io::ErrorKind::PermissionDenied {
+        #[cfg(target_os = "linux")]
+        let (gid, ping_group_range) = (
+            Some(unsafe { libc::getegid() }),
+            std::fs::read_to_string("/proc/sys/net/ipv4/ping_group_range")
+                .ok()
+                .and_then(|x| {
+                    let mut values = x.split_whitespace().map(|x| x.parse::<u32>().ok());
+                    Some((values.next()??, values.next()??))
+                }),
+        );
+        #[cfg(not(target_os = "linux"))]
+        let (gid, ping_group_range) = (None, None);
+
+        AsyncClientWithConfigError::PermissionDenied(PermissionDeniedError {
+            socket_type: config.socket_type,
+            is_ipv6: config.is_ipv6(),
+            gid,
+            ping_group_range,
+            err,
+        })
+    } else if config.is_ipv6()
+        && err
+            .raw_os_error()
+            .map(|x| protocol_not_supported.contains(&x))
+            .unwrap_or_default()
+    {
+        AsyncClientWithConfigError::IcmpV6ProtocolNotSupported(err)
+    } else {
+        AsyncClientWithConfigError::OtherIoError(err)
+    }
+}
+
+/// Applies the options of `config` to an existing socket, the socket type and family are the socket's.
+pub fn apply_config(
+    socket: &socket2::Socket,
+    config: &Config,
+) -> Result<(), AsyncClientWithConfigError> {
+    use socket2::SockAddr;
+
     socket.set_nonblocking(true)?;
 
     if let Some(bind) = config.bind {
         socket.bind(&SockAddr::from(bind))?;
     }
+    if let Some(connect) = config.connect {
+        // The ICMP errors about the peer are then reported on recv.
+        #[cfg(target_os = "linux")]
+        set_recv_err(socket, connect.is_ipv6())?;
+        socket.connect(&SockAddr::from(connect))?;
+    }
     #[cfg(any(
         target_os = "ios",
         target_os = "macos",
@@ -35,27 +104,236 @@ pub fn new_socket2_socket(config: &Config) -> Result<socket2::Socket, AsyncClien
     if let Some(ttl) = config.ttl {
         socket.set_ttl(ttl)?;
     }
+    if let Some(ip_options) = &config.ip_options {
+        #[cfg(unix)]
+        set_ip_options(socket, ip_options)?;
+        #[cfg(not(unix))]
+        {
+            let _ = ip_options;
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "ip_options unsupported",
+            )
+            .into());
+        }
+    }
+    if let Some(recv_buffer_size) = config.recv_buffer_size {
+        socket.set_recv_buffer_size(recv_buffer_size)?;
+    }
+    if let Some(send_buffer_size) = config.send_buffer_size {
+        socket.set_send_buffer_size(send_buffer_size)?;
+    }
+    #[cfg(target_os = "linux")]
+    set_rxq_ovfl(socket)?;
     #[cfg(target_os = "freebsd")]
     if let Some(fib) = config.fib {
         socket.set_fib(fib)?;
     }
 
-    Ok(socket)
+    #[cfg(target_os = "linux")]
+    if config.icmp_filter.is_some() || config.echo_reply_filter.is_some() {
+        let is_ipv6 = socket.local_addr()?.is_ipv6();
+        if let Some(icmp_types) = &config.icmp_filter {
+            crate::filter::set_icmp_filter(socket, is_ipv6, icmp_types)?;
+        }
+        if let Some((identifier, error_types)) = &config.echo_reply_filter {
+            let is_raw = socket.r#type()? == socket2::Type::RAW;
+            socket.attach_filter(&crate::filter::echo_reply_bpf_program(
+                is_ipv6,
+                is_raw,
+                *identifier,
+                error_types,
+            ))?;
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    if config.icmp_filter.is_some() || config.echo_reply_filter.is_some() {
+        return Err(
+            std::io::Error::new(std::io::ErrorKind::Unsupported, "filter unsupported").into(),
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_ip_options(socket: &socket2::Socket, ip_options: &[u8]) -> Result<(), std::io::Error> {
+    use std::os::fd::AsRawFd as _;
+
+    if unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_IP,
+            libc::IP_OPTIONS,
+            ip_options.as_ptr() as *const _,
+            ip_options.len() as libc::socklen_t,
+        )
+    } < 0
+    {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_recv_err(socket: &socket2::Socket, is_ipv6: bool) -> Result<(), std::io::Error> {
+    use std::os::fd::AsRawFd as _;
+
+    let (level, name) = if is_ipv6 {
+        (libc::IPPROTO_IPV6, libc::IPV6_RECVERR)
+    } else {
+        (libc::IPPROTO_IP, libc::IP_RECVERR)
+    };
+    let on: libc::c_int = 1;
+    if unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &on as *const libc::c_int as *const _,
+            core::mem::size_of_val(&on) as libc::socklen_t,
+        )
+    } < 0
+    {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_rxq_ovfl(socket: &socket2::Socket) -> Result<(), std::io::Error> {
+    use std::os::fd::AsRawFd as _;
+
+    let on: libc::c_int = 1;
+    if unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_RXQ_OVFL,
+            &on as *const libc::c_int as *const _,
+            core::mem::size_of_val(&on) as libc::socklen_t,
+        )
+    } < 0
+    {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The drop counter of the socket, from SO_MEMINFO.
+///
+/// Unlike raw sockets, ICMP DGRAM sockets do not deliver the SO_RXQ_OVFL counter on recv.
+#[cfg(target_os = "linux")]
+pub fn socket_drops(fd: std::os::fd::RawFd) -> Option<u32> {
+    // asm-generic/socket.h
+    const SO_MEMINFO: libc::c_int = 55;
+
+    let mut meminfo = [0_u32; 9];
+    let mut len = core::mem::size_of_val(&meminfo) as libc::socklen_t;
+    if unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            SO_MEMINFO,
+            meminfo.as_mut_ptr() as *mut _,
+            &mut len,
+        )
+    } < 0
+    {
+        return None;
+    }
+    let i = libc::SK_MEMINFO_DROPS as usize;
+    if (len as usize) < (i + 1) * core::mem::size_of::<u32>() {
+        return None;
+    }
+    Some(meminfo[i])
+}
+
+/// Like `recv_from`, with the SO_RXQ_OVFL drop counter when present.
+#[cfg(target_os = "linux")]
+pub fn recv_from_with_drops(
+    fd: std::os::fd::RawFd,
+    buf: &mut [u8],
+) -> Result<(usize, Option<std::net::SocketAddr>, Option<u32>), std::io::Error> {
+    let mut cmsg_buf = [0_u64; 8];
+    let mut drops = None;
+
+    let (n, addr) = unsafe {
+        socket2::SockAddr::try_init(|storage, len| {
+            let mut iov = libc::iovec {
+                iov_base: buf.as_mut_ptr() as *mut _,
+                iov_len: buf.len(),
+            };
+            let mut msg: libc::msghdr = core::mem::zeroed();
+            msg.msg_name = storage as *mut _;
+            msg.msg_namelen = *len;
+            msg.msg_iov = &mut iov;
+            msg.msg_iovlen = 1;
+            msg.msg_control = cmsg_buf.as_mut_ptr() as *mut _;
+            msg.msg_controllen = core::mem::size_of_val(&cmsg_buf) as _;
+
+            let n = libc::recvmsg(fd, &mut msg, 0);
+            if n < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            *len = msg.msg_namelen;
+
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SO_RXQ_OVFL
+                {
+                    drops = Some(core::ptr::read_unaligned(
+                        libc::CMSG_DATA(cmsg) as *const u32
+                    ));
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+
+            Ok(n as usize)
+        })?
+    };
+
+    Ok((n, addr.as_socket(), drops))
+}
+
+/// Discards the queued errors, once reported by recv, so the queue does not grow.
+#[cfg(target_os = "linux")]
+pub fn drain_error_queue(fd: std::os::fd::RawFd) {
+    let mut buf = [0_u8; 512];
+    let mut cmsg_buf = [0_u64; 32];
+    loop {
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut _,
+            iov_len: buf.len(),
+        };
+        let mut msg: libc::msghdr = unsafe { core::mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = cmsg_buf.as_mut_ptr() as *mut _;
+        msg.msg_controllen = core::mem::size_of_val(&cmsg_buf) as _;
+        if unsafe { libc::recvmsg(fd, &mut msg, libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT) } < 0 {
+            break;
+        }
+    }
 }
 
 //
 pub fn new_std_udp_socket(config: &Config) -> Result<UdpSocket, AsyncClientWithConfigError> {
+    let socket = new_socket2_socket(config)?;
+
+    Ok(socket2_socket_to_std_udp_socket(socket))
+}
+
+pub fn socket2_socket_to_std_udp_socket(socket: socket2::Socket) -> UdpSocket {
     #[cfg(unix)]
     use std::os::fd::{FromRawFd as _, IntoRawFd as _};
     #[cfg(windows)]
     use std::os::windows::{FromRawSocket as _, IntoRawSocket as _};
 
-    let socket = new_socket2_socket(config)?;
-
     #[cfg(unix)]
     let udp_socket = unsafe { UdpSocket::from_raw_fd(socket.into_raw_fd()) };
     #[cfg(windows)]
     let udp_socket = unsafe { UdpSocket::from_raw_socket(socket.into_raw_socket()) };
 
-    Ok(udp_socket)
+    udp_socket
 }
@@ -24,4 +24,126 @@ impl PayloadLengthDelimitedEchoReply {
             payload,
         }
     }
+
+    /// Fewer payload bytes than `len`, e.g. cut by a middlebox.
+    pub fn is_truncated(&self) -> bool {
+        self.payload.len() < (*self.len.inner()) as usize
+    }
+
+    /// Against the payload of the echo request.
+    pub fn verify_payload(&self, expected: &[u8]) -> Result<(), PayloadMismatch> {
+        let len = (*self.len.inner()) as usize;
+        if len != expected.len() {
+            return Err(PayloadMismatch::LenMismatch {
+                expected: expected.len(),
+                actual: len,
+            });
+        }
+        if self.is_truncated() {
+            return Err(PayloadMismatch::Truncated {
+                expected: len,
+                actual: self.payload.len(),
+            });
+        }
+
+        let mut diff = self
+            .payload
+            .iter()
+            .zip(expected)
+            .enumerate()
+            .filter(|(_, (a, b))| a != b)
+            .map(|(i, _)| i);
+        match diff.next() {
+            Some(offset) => Err(PayloadMismatch::Corrupted {
+                offset,
+                bytes: 1 + diff.count(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadMismatch {
+    /// The `len` echoed differs from the one sent.
+    LenMismatch { expected: usize, actual: usize },
+    /// Fewer payload bytes than sent.
+    Truncated { expected: usize, actual: usize },
+    /// `bytes` bytes differ, the first at `offset`.
+    Corrupted { offset: usize, bytes: usize },
+}
+impl core::fmt::Display for PayloadMismatch {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::LenMismatch { expected, actual } => write!(
+                f,
+                "payload len mismatch, expected {expected} bytes got {actual}"
+            ),
+            Self::Truncated { expected, actual } => write!(
+                f,
+                "payload truncated, expected {expected} bytes got {actual}"
+            ),
+            Self::Corrupted { offset, bytes } => write!(
+                f,
+                "payload mismatch at offset {offset}, {bytes} bytes differ"
+            ),
+        }
+    }
+}
+impl std::error::Error for PayloadMismatch {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{Icmpv4, PayloadLengthDelimitedEchoRequest, ICMP_HEADER_SIZE};
+
+    #[test]
+    fn test_verify_payload() {
+        let echo_request =
+            PayloadLengthDelimitedEchoRequest::new(Some(1.into()), Some(2.into()), b"1234");
+        let echo_reply_bytes =
+            crate::icmpv4::render_echo_reply_packet_bytes(&echo_request.render_v4_packet_bytes())
+                .expect("Never");
+        let parse = |bytes: &[u8]| match Icmpv4::parse_from_packet_bytes(bytes) {
+            Ok(Some(Icmpv4::EchoReply(echo_reply))) => echo_reply,
+            x => panic!("{x:?}"),
+        };
+
+        let echo_reply = parse(&echo_reply_bytes);
+        assert!(!echo_reply.is_truncated());
+        assert_eq!(echo_reply.verify_payload(b"1234"), Ok(()));
+        assert_eq!(
+            echo_reply.verify_payload(b"12345"),
+            Err(PayloadMismatch::LenMismatch {
+                expected: 5,
+                actual: 4
+            })
+        );
+        assert_eq!(
+            echo_reply.verify_payload(b"1x3x"),
+            Err(PayloadMismatch::Corrupted {
+                offset: 1,
+                bytes: 2
+            })
+        );
+        assert_eq!(
+            echo_reply
+                .verify_payload(b"1x3x")
+                .expect_err("Never")
+                .to_string(),
+            "payload mismatch at offset 1, 2 bytes differ"
+        );
+
+        let echo_reply = parse(&echo_reply_bytes[..ICMP_HEADER_SIZE + 2 + 3]);
+        assert!(echo_reply.is_truncated());
+        assert_eq!(
+            echo_reply.verify_payload(b"1234"),
+            Err(PayloadMismatch::Truncated {
+                expected: 4,
+                actual: 3
+            })
+        );
+    }
 }
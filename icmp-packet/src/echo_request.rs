@@ -42,13 +42,21 @@ impl PayloadLengthDelimitedEchoRequest {
     }
 
     pub fn render_v4_packet_bytes(&self) -> Vec<u8> {
-        use pnet_packet::{
-            icmp::{checksum, echo_request::MutableEchoRequestPacket, IcmpPacket, IcmpTypes},
-            Packet as _,
+        let mut buf = vec![];
+        self.render_v4_packet_bytes_into(&mut buf);
+        buf
+    }
+
+    /// Into `buf`, resized to the packet, e.g. a reused one.
+    pub fn render_v4_packet_bytes_into(&self, buf: &mut Vec<u8>) {
+        use pnet_packet::icmp::{
+            checksum, echo_request::MutableEchoRequestPacket, IcmpPacket, IcmpTypes,
+            MutableIcmpPacket,
         };
 
         //
-        let mut buf = vec![0; ICMP_HEADER_SIZE + self.payload_with_len.len()];
+        buf.clear();
+        buf.resize(ICMP_HEADER_SIZE + self.payload_with_len.len(), 0);
         let mut echo_request_packet = MutableEchoRequestPacket::new(&mut buf[..])
             .expect("Never when MutableEchoRequestPacket::new");
         echo_request_packet.set_icmp_type(IcmpTypes::EchoRequest);
@@ -56,21 +64,24 @@ impl PayloadLengthDelimitedEchoRequest {
         echo_request_packet.set_sequence_number(self.sequence_number.into_inner());
         echo_request_packet.set_payload(&self.payload_with_len);
 
-        let icmp_packet =
-            IcmpPacket::new(echo_request_packet.packet()).expect("Never when IcmpPacket::new");
-        let checksum = checksum(&icmp_packet);
-        echo_request_packet.set_checksum(checksum);
-
-        echo_request_packet.packet().to_vec()
+        let checksum = checksum(&IcmpPacket::new(buf).expect("Never when IcmpPacket::new"));
+        MutableIcmpPacket::new(buf)
+            .expect("Never when MutableIcmpPacket::new")
+            .set_checksum(checksum);
     }
 
     pub fn render_v6_packet_bytes(&self) -> Vec<u8> {
-        use pnet_packet::{
-            icmpv6::{echo_request::MutableEchoRequestPacket, Icmpv6Types},
-            Packet as _,
-        };
+        let mut buf = vec![];
+        self.render_v6_packet_bytes_into(&mut buf);
+        buf
+    }
+
+    /// Into `buf`, resized to the packet, e.g. a reused one.
+    pub fn render_v6_packet_bytes_into(&self, buf: &mut Vec<u8>) {
+        use pnet_packet::icmpv6::{echo_request::MutableEchoRequestPacket, Icmpv6Types};
 
-        let mut buf = vec![0; ICMP_HEADER_SIZE + self.payload_with_len.len()];
+        buf.clear();
+        buf.resize(ICMP_HEADER_SIZE + self.payload_with_len.len(), 0);
         let mut echo_request_packet = MutableEchoRequestPacket::new(&mut buf[..])
             .expect("Never when MutableEchoRequestPacket::new");
         echo_request_packet.set_icmpv6_type(Icmpv6Types::EchoRequest);
@@ -81,8 +92,6 @@ impl PayloadLengthDelimitedEchoRequest {
         // https://github.com/kolapapa/surge-ping/blob/0.7.3/src/icmp/icmpv6.rs#L26
         // https://tools.ietf.org/html/rfc3542#section-3.1
         // the checksum is omitted, the kernel will insert it.
-
-        echo_request_packet.packet().to_vec()
     }
 }
 
@@ -110,5 +119,12 @@ mod tests {
             echo_request.render_v6_packet_bytes(),
             vec![128, 0, 0, 0, 0, 1, 0, 2, 0, 4, 49, 50, 51, 52]
         );
+
+        // Reused
+        let mut buf = vec![0xff; 64];
+        echo_request.render_v4_packet_bytes_into(&mut buf);
+        assert_eq!(buf, echo_request.render_v4_packet_bytes());
+        echo_request.render_v6_packet_bytes_into(&mut buf);
+        assert_eq!(buf, echo_request.render_v6_packet_bytes());
     }
 }
@@ -1,11 +1,14 @@
 use pnet_packet::{
-    icmp::{echo_reply::EchoReplyPacket, IcmpCode, IcmpPacket, IcmpType, IcmpTypes},
+    icmp::{
+        checksum, echo_reply::EchoReplyPacket, IcmpCode, IcmpPacket, IcmpType, IcmpTypes,
+        MutableIcmpPacket,
+    },
     Packet,
 };
 
 use crate::{
-    echo_reply::PayloadLengthDelimitedEchoReply, types::Payload, LenWithPayloadLengthDelimited,
-    ICMP_HEADER_SIZE,
+    echo_reply::PayloadLengthDelimitedEchoReply, ip::IPV4_HEADER_SIZE, types::Payload,
+    LenWithPayloadLengthDelimited, ICMP_HEADER_SIZE,
 };
 
 //
@@ -45,27 +48,20 @@ impl Icmpv4 {
                 };
                 let len = LenWithPayloadLengthDelimited::from_bytes([*char_a, *char_b]);
 
-                if bytes.len()
-                    < ICMP_HEADER_SIZE
-                        + LenWithPayloadLengthDelimited::size()
-                        + (*len.inner()) as usize
-                {
-                    return Ok(None);
-                }
+                // Shorter than `len` when truncated on the way, see `is_truncated`.
+                let payload_bytes =
+                    &bytes[ICMP_HEADER_SIZE + LenWithPayloadLengthDelimited::size()..];
+                let payload_bytes =
+                    &payload_bytes[..payload_bytes.len().min((*len.inner()) as usize)];
 
-                return Ok(Some(Icmpv4::EchoReply(
+                Ok(Some(Icmpv4::EchoReply(
                     PayloadLengthDelimitedEchoReply::new(
                         echo_reply_packet.get_identifier().into(),
                         echo_reply_packet.get_sequence_number().into(),
                         len,
-                        bytes[ICMP_HEADER_SIZE + LenWithPayloadLengthDelimited::size()
-                            ..ICMP_HEADER_SIZE
-                                + LenWithPayloadLengthDelimited::size()
-                                + (*len.inner()) as usize]
-                            .to_vec()
-                            .into(),
+                        payload_bytes.to_vec().into(),
                     ),
-                )));
+                )))
             }
             icmp_type => Ok(Some(Icmpv4::Other(
                 icmp_type,
@@ -76,6 +72,55 @@ impl Icmpv4 {
     }
 }
 
+//
+/// Max size of an error message, so that the IP packet carrying it does not exceed 576 bytes (RFC 1812 4.3.2.3).
+pub const ERROR_PACKET_MAX_SIZE: usize = 576 - IPV4_HEADER_SIZE;
+
+/// Turns an echo request into its echo reply, identifier, sequence number and data are kept as is.
+pub fn render_echo_reply_packet_bytes(echo_request_bytes: &[u8]) -> Option<Vec<u8>> {
+    if echo_request_bytes.len() < ICMP_HEADER_SIZE {
+        return None;
+    }
+
+    let mut buf = echo_request_bytes.to_vec();
+    let mut icmp_packet = MutableIcmpPacket::new(&mut buf[..])?;
+    if icmp_packet.get_icmp_type() != IcmpTypes::EchoRequest {
+        return None;
+    }
+    icmp_packet.set_icmp_type(IcmpTypes::EchoReply);
+    icmp_packet.set_checksum(0);
+    let checksum = checksum(&icmp_packet.to_immutable());
+    icmp_packet.set_checksum(checksum);
+
+    Some(buf)
+}
+
+/// Error message (Destination Unreachable, Time Exceeded, ...) quoting as much of `original_datagram` as fits.
+pub fn render_error_packet_bytes(
+    icmp_type: IcmpType,
+    icmp_code: IcmpCode,
+    rest_of_header: u32,
+    original_datagram: &[u8],
+) -> Vec<u8> {
+    let original_datagram = &original_datagram[..original_datagram
+        .len()
+        .min(ERROR_PACKET_MAX_SIZE - ICMP_HEADER_SIZE)];
+
+    let mut buf = vec![0; ICMP_HEADER_SIZE + original_datagram.len()];
+    buf[4..ICMP_HEADER_SIZE].copy_from_slice(&rest_of_header.to_be_bytes());
+    buf[ICMP_HEADER_SIZE..].copy_from_slice(original_datagram);
+
+    let mut icmp_packet =
+        MutableIcmpPacket::new(&mut buf[..]).expect("Never when MutableIcmpPacket::new");
+    icmp_packet.set_icmp_type(icmp_type);
+    icmp_packet.set_icmp_code(icmp_code);
+
+    let checksum = checksum(&icmp_packet.to_immutable());
+    icmp_packet.set_checksum(checksum);
+
+    buf
+}
+
 //
 #[derive(Debug)]
 pub enum ParseError {
@@ -117,4 +162,29 @@ mod tests {
             x => panic!("{x:?}"),
         }
     }
+
+    #[test]
+    fn test_render_echo_reply_and_error_packet_bytes() {
+        let echo_request =
+            PayloadLengthDelimitedEchoRequest::new(Some(1.into()), Some(2.into()), b"1234");
+        let echo_request_bytes = echo_request.render_v4_packet_bytes();
+
+        let echo_reply_bytes = render_echo_reply_packet_bytes(&echo_request_bytes).expect("Never");
+        let icmp_packet = IcmpPacket::new(&echo_reply_bytes).expect("Never");
+        assert_eq!(icmp_packet.get_icmp_type(), IcmpTypes::EchoReply);
+        assert_eq!(icmp_packet.get_checksum(), checksum(&icmp_packet));
+        assert!(render_echo_reply_packet_bytes(&echo_reply_bytes).is_none());
+
+        let original_datagram = vec![0; 1000];
+        let error_bytes =
+            render_error_packet_bytes(IcmpTypes::TimeExceeded, IcmpCode(0), 0, &original_datagram);
+        assert_eq!(error_bytes.len(), ERROR_PACKET_MAX_SIZE);
+        match Icmpv4::parse_from_packet_bytes(&error_bytes) {
+            Ok(Some(Icmpv4::Other(IcmpTypes::TimeExceeded, IcmpCode(0), payload))) => {
+                assert!(payload
+                    .ends_with(&original_datagram[..ERROR_PACKET_MAX_SIZE - ICMP_HEADER_SIZE]));
+            }
+            x => panic!("{x:?}"),
+        }
+    }
 }
@@ -1,11 +1,14 @@
 use pnet_packet::{
-    icmpv6::{echo_reply::EchoReplyPacket, Icmpv6Code, Icmpv6Packet, Icmpv6Type, Icmpv6Types},
+    icmpv6::{
+        echo_reply::EchoReplyPacket, Icmpv6Code, Icmpv6Packet, Icmpv6Type, Icmpv6Types,
+        MutableIcmpv6Packet,
+    },
     Packet,
 };
 
 use crate::{
-    echo_reply::PayloadLengthDelimitedEchoReply, types::Payload, LenWithPayloadLengthDelimited,
-    ICMP_HEADER_SIZE,
+    echo_reply::PayloadLengthDelimitedEchoReply, ip::IPV6_HEADER_SIZE, types::Payload,
+    LenWithPayloadLengthDelimited, ICMP_HEADER_SIZE,
 };
 
 //
@@ -45,27 +48,20 @@ impl Icmpv6 {
                 };
                 let len = LenWithPayloadLengthDelimited::from_bytes([*char_a, *char_b]);
 
-                if bytes.len()
-                    < ICMP_HEADER_SIZE
-                        + LenWithPayloadLengthDelimited::size()
-                        + (*len.inner()) as usize
-                {
-                    return Ok(None);
-                }
+                // Shorter than `len` when truncated on the way, see `is_truncated`.
+                let payload_bytes =
+                    &bytes[ICMP_HEADER_SIZE + LenWithPayloadLengthDelimited::size()..];
+                let payload_bytes =
+                    &payload_bytes[..payload_bytes.len().min((*len.inner()) as usize)];
 
-                return Ok(Some(Icmpv6::EchoReply(
+                Ok(Some(Icmpv6::EchoReply(
                     PayloadLengthDelimitedEchoReply::new(
                         echo_reply_packet.get_identifier().into(),
                         echo_reply_packet.get_sequence_number().into(),
                         len,
-                        bytes[ICMP_HEADER_SIZE + LenWithPayloadLengthDelimited::size()
-                            ..ICMP_HEADER_SIZE
-                                + LenWithPayloadLengthDelimited::size()
-                                + (*len.inner()) as usize]
-                            .to_vec()
-                            .into(),
+                        payload_bytes.to_vec().into(),
                     ),
-                )));
+                )))
             }
             icmp_type => Ok(Some(Icmpv6::Other(
                 icmp_type,
@@ -76,6 +72,53 @@ impl Icmpv6 {
     }
 }
 
+//
+/// Max size of an error message, so that the IP packet carrying it does not exceed 1280 bytes (RFC 4443 2.4).
+pub const ERROR_PACKET_MAX_SIZE: usize = 1280 - IPV6_HEADER_SIZE;
+
+/// Turns an echo request into its echo reply, identifier, sequence number and data are kept as is.
+pub fn render_echo_reply_packet_bytes(echo_request_bytes: &[u8]) -> Option<Vec<u8>> {
+    if echo_request_bytes.len() < ICMP_HEADER_SIZE {
+        return None;
+    }
+
+    let mut buf = echo_request_bytes.to_vec();
+    let mut icmp_packet = MutableIcmpv6Packet::new(&mut buf[..])?;
+    if icmp_packet.get_icmpv6_type() != Icmpv6Types::EchoRequest {
+        return None;
+    }
+    icmp_packet.set_icmpv6_type(Icmpv6Types::EchoReply);
+    // the checksum is omitted, the kernel will insert it.
+    icmp_packet.set_checksum(0);
+
+    Some(buf)
+}
+
+/// Error message (Destination Unreachable, Time Exceeded, ...) quoting as much of `original_datagram` as fits.
+pub fn render_error_packet_bytes(
+    icmp_type: Icmpv6Type,
+    icmp_code: Icmpv6Code,
+    rest_of_header: u32,
+    original_datagram: &[u8],
+) -> Vec<u8> {
+    let original_datagram = &original_datagram[..original_datagram
+        .len()
+        .min(ERROR_PACKET_MAX_SIZE - ICMP_HEADER_SIZE)];
+
+    let mut buf = vec![0; ICMP_HEADER_SIZE + original_datagram.len()];
+    buf[4..ICMP_HEADER_SIZE].copy_from_slice(&rest_of_header.to_be_bytes());
+    buf[ICMP_HEADER_SIZE..].copy_from_slice(original_datagram);
+
+    let mut icmp_packet =
+        MutableIcmpv6Packet::new(&mut buf[..]).expect("Never when MutableIcmpv6Packet::new");
+    icmp_packet.set_icmpv6_type(icmp_type);
+    icmp_packet.set_icmpv6_code(icmp_code);
+
+    // the checksum is omitted, the kernel will insert it.
+
+    buf
+}
+
 //
 #[derive(Debug)]
 pub enum ParseError {
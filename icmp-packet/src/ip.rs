@@ -0,0 +1,166 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use pnet_packet::{
+    ip::IpNextHeaderProtocols,
+    ipv4::{self, Ipv4Packet, MutableIpv4Packet},
+    ipv6::{Ipv6Packet, MutableIpv6Packet},
+};
+
+//
+pub const IPV4_HEADER_SIZE: usize = 20;
+pub const IPV6_HEADER_SIZE: usize = 40;
+
+//
+pub fn render_ipv4_packet_bytes(
+    source: Ipv4Addr,
+    destination: Ipv4Addr,
+    ttl: u8,
+    icmp_bytes: &[u8],
+) -> Vec<u8> {
+    let mut buf = vec![0; IPV4_HEADER_SIZE + icmp_bytes.len()];
+    let mut ipv4_packet =
+        MutableIpv4Packet::new(&mut buf[..]).expect("Never when MutableIpv4Packet::new");
+    ipv4_packet.set_version(4);
+    ipv4_packet.set_header_length((IPV4_HEADER_SIZE / 4) as u8);
+    ipv4_packet.set_total_length((IPV4_HEADER_SIZE + icmp_bytes.len()) as u16);
+    ipv4_packet.set_ttl(ttl);
+    ipv4_packet.set_next_level_protocol(IpNextHeaderProtocols::Icmp);
+    ipv4_packet.set_source(source.octets().into());
+    ipv4_packet.set_destination(destination.octets().into());
+    ipv4_packet.set_payload(icmp_bytes);
+
+    let checksum = ipv4::checksum(&ipv4_packet.to_immutable());
+    ipv4_packet.set_checksum(checksum);
+
+    buf
+}
+
+pub fn render_ipv6_packet_bytes(
+    source: Ipv6Addr,
+    destination: Ipv6Addr,
+    hop_limit: u8,
+    icmp_bytes: &[u8],
+) -> Vec<u8> {
+    let mut buf = vec![0; IPV6_HEADER_SIZE + icmp_bytes.len()];
+    let mut ipv6_packet =
+        MutableIpv6Packet::new(&mut buf[..]).expect("Never when MutableIpv6Packet::new");
+    ipv6_packet.set_version(6);
+    ipv6_packet.set_payload_length(icmp_bytes.len() as u16);
+    ipv6_packet.set_next_header(IpNextHeaderProtocols::Icmpv6);
+    ipv6_packet.set_hop_limit(hop_limit);
+    ipv6_packet.set_source(source.octets().into());
+    ipv6_packet.set_destination(destination.octets().into());
+    ipv6_packet.set_payload(icmp_bytes);
+
+    buf
+}
+
+//
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ipv4Header {
+    pub source: Ipv4Addr,
+    pub destination: Ipv4Addr,
+    pub ttl: u8,
+    pub protocol: u8,
+    pub options: Vec<u8>,
+}
+
+impl Ipv4Header {
+    pub fn parse_options(&self) -> Vec<crate::ip_options::Ipv4Option> {
+        crate::ip_options::parse_ipv4_options(&self.options)
+    }
+}
+
+/// Returns the header and the bytes after it, `None` when `bytes` is not a complete IPv4 packet.
+pub fn parse_ipv4_packet_bytes(bytes: &[u8]) -> Option<(Ipv4Header, &[u8])> {
+    let ipv4_packet = Ipv4Packet::new(bytes)?;
+    if ipv4_packet.get_version() != 4 {
+        return None;
+    }
+    let header_len = ipv4_packet.get_header_length() as usize * 4;
+    if header_len < IPV4_HEADER_SIZE || bytes.len() < header_len {
+        return None;
+    }
+    let total_len = (ipv4_packet.get_total_length() as usize).clamp(header_len, bytes.len());
+
+    Some((
+        Ipv4Header {
+            source: ipv4_packet.get_source().octets().into(),
+            destination: ipv4_packet.get_destination().octets().into(),
+            ttl: ipv4_packet.get_ttl(),
+            protocol: ipv4_packet.get_next_level_protocol().0,
+            options: bytes[IPV4_HEADER_SIZE..header_len].to_vec(),
+        },
+        &bytes[header_len..total_len],
+    ))
+}
+
+//
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ipv6Header {
+    pub source: Ipv6Addr,
+    pub destination: Ipv6Addr,
+    pub hop_limit: u8,
+    pub next_header: u8,
+}
+
+/// Returns the header and the bytes after it, `None` when `bytes` is not a complete IPv6 packet.
+///
+/// Extension headers are not walked, `next_header` is the one in the fixed header.
+pub fn parse_ipv6_packet_bytes(bytes: &[u8]) -> Option<(Ipv6Header, &[u8])> {
+    let ipv6_packet = Ipv6Packet::new(bytes)?;
+    if ipv6_packet.get_version() != 6 {
+        return None;
+    }
+    let end = (IPV6_HEADER_SIZE + ipv6_packet.get_payload_length() as usize).min(bytes.len());
+
+    Some((
+        Ipv6Header {
+            source: ipv6_packet.get_source().octets().into(),
+            destination: ipv6_packet.get_destination().octets().into(),
+            hop_limit: ipv6_packet.get_hop_limit(),
+            next_header: ipv6_packet.get_next_header().0,
+        },
+        &bytes[IPV6_HEADER_SIZE..end],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_and_parse_ipv4() {
+        let bytes = render_ipv4_packet_bytes(
+            Ipv4Addr::new(10, 0, 0, 1),
+            Ipv4Addr::new(10, 0, 0, 2),
+            3,
+            b"1234",
+        );
+        assert_eq!(bytes.len(), IPV4_HEADER_SIZE + 4);
+
+        let ipv4_packet = Ipv4Packet::new(&bytes).expect("Never");
+        assert_eq!(ipv4_packet.get_checksum(), ipv4::checksum(&ipv4_packet));
+
+        let (header, payload) = parse_ipv4_packet_bytes(&bytes).expect("Never");
+        assert_eq!(header.source, Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(header.destination, Ipv4Addr::new(10, 0, 0, 2));
+        assert_eq!(header.ttl, 3);
+        assert_eq!(header.protocol, IpNextHeaderProtocols::Icmp.0);
+        assert!(header.options.is_empty());
+        assert_eq!(payload, b"1234");
+    }
+
+    #[test]
+    fn test_render_and_parse_ipv6() {
+        let bytes = render_ipv6_packet_bytes(Ipv6Addr::LOCALHOST, Ipv6Addr::LOCALHOST, 3, b"1234");
+        assert_eq!(bytes.len(), IPV6_HEADER_SIZE + 4);
+
+        let (header, payload) = parse_ipv6_packet_bytes(&bytes).expect("Never");
+        assert_eq!(header.hop_limit, 3);
+        assert_eq!(header.next_header, IpNextHeaderProtocols::Icmpv6.0);
+        assert_eq!(payload, b"1234");
+
+        assert!(parse_ipv4_packet_bytes(&bytes).is_none());
+    }
+}
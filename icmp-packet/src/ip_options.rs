@@ -0,0 +1,225 @@
+//! IPv4 options, Record Route and Internet Timestamp (RFC 791), like `ping -R` / `ping -T`.
+
+use std::net::Ipv4Addr;
+
+//
+pub const IPOPT_END: u8 = 0;
+pub const IPOPT_NOP: u8 = 1;
+pub const IPOPT_RR: u8 = 7;
+pub const IPOPT_TS: u8 = 68;
+
+/// Max size of the options in an IPv4 header.
+pub const IPV4_OPTIONS_MAX_SIZE: usize = 40;
+
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampFlag {
+    /// Timestamps only, `ping -T tsonly`.
+    TsOnly,
+    /// Address and timestamp of each hop, `ping -T tsandaddr`.
+    TsAndAddr,
+    /// Timestamps of the listed hops only, `ping -T tsprespec`.
+    Prespecified,
+}
+
+impl TimestampFlag {
+    fn to_u8(self) -> u8 {
+        match self {
+            Self::TsOnly => 0,
+            Self::TsAndAddr => 1,
+            Self::Prespecified => 3,
+        }
+    }
+
+    fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(Self::TsOnly),
+            1 => Some(Self::TsAndAddr),
+            3 => Some(Self::Prespecified),
+            _ => None,
+        }
+    }
+}
+
+//
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ipv4Option {
+    /// The addresses recorded so far.
+    RecordRoute(Vec<Ipv4Addr>),
+    /// The entries recorded so far, timestamps in milliseconds since midnight UT.
+    Timestamp {
+        flag: TimestampFlag,
+        /// Hops that could not record, no room left.
+        overflow: u8,
+        entries: Vec<(Option<Ipv4Addr>, u32)>,
+    },
+    Other(u8, Vec<u8>),
+}
+
+//
+/// Record Route with room for 9 addresses, the most that fits.
+pub fn render_record_route_option_bytes() -> Vec<u8> {
+    let len = IPV4_OPTIONS_MAX_SIZE - 1;
+    let mut bytes = vec![IPOPT_NOP, IPOPT_RR, len as u8, 4];
+    bytes.resize(IPV4_OPTIONS_MAX_SIZE, 0);
+    bytes
+}
+
+/// Internet Timestamp, `prespecified` (at most 4) is only used with [`TimestampFlag::Prespecified`].
+pub fn render_timestamp_option_bytes(flag: TimestampFlag, prespecified: &[Ipv4Addr]) -> Vec<u8> {
+    let data = match flag {
+        TimestampFlag::TsOnly => vec![0; 36],
+        TimestampFlag::TsAndAddr => vec![0; 32],
+        TimestampFlag::Prespecified => {
+            assert!(!prespecified.is_empty() && prespecified.len() <= 4);
+            prespecified
+                .iter()
+                .flat_map(|x| {
+                    let mut entry = x.octets().to_vec();
+                    entry.extend_from_slice(&[0; 4]);
+                    entry
+                })
+                .collect()
+        }
+    };
+
+    let mut bytes = vec![IPOPT_TS, (4 + data.len()) as u8, 5, flag.to_u8()];
+    bytes.extend_from_slice(&data);
+    bytes
+}
+
+/// Parses the `options` of an `Ipv4Header`, stops at the first malformed option.
+pub fn parse_ipv4_options(bytes: &[u8]) -> Vec<Ipv4Option> {
+    let mut options = vec![];
+
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            IPOPT_END => break,
+            IPOPT_NOP => {
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        let option_type = bytes[i];
+        let len = match bytes.get(i + 1) {
+            Some(len) if *len >= 2 && i + (*len as usize) <= bytes.len() => *len as usize,
+            _ => break,
+        };
+        let option = &bytes[i..i + len];
+        i += len;
+
+        match option_type {
+            IPOPT_RR if len >= 3 => {
+                // The pointer is 1-based, on the next free slot.
+                let end = (option[2] as usize).saturating_sub(1).clamp(3, len);
+                options.push(Ipv4Option::RecordRoute(
+                    option[3..end]
+                        .chunks_exact(4)
+                        .map(|x| Ipv4Addr::new(x[0], x[1], x[2], x[3]))
+                        .collect(),
+                ));
+            }
+            IPOPT_TS if len >= 4 => {
+                let flag = match TimestampFlag::from_u8(option[3] & 0x0f) {
+                    Some(x) => x,
+                    None => {
+                        options.push(Ipv4Option::Other(option_type, option[2..].to_vec()));
+                        continue;
+                    }
+                };
+                let end = (option[2] as usize).saturating_sub(1).clamp(4, len);
+                let entries = match flag {
+                    TimestampFlag::TsOnly => option[4..end]
+                        .chunks_exact(4)
+                        .map(|x| (None, u32::from_be_bytes([x[0], x[1], x[2], x[3]])))
+                        .collect(),
+                    TimestampFlag::TsAndAddr | TimestampFlag::Prespecified => option[4..end]
+                        .chunks_exact(8)
+                        .map(|x| {
+                            (
+                                Some(Ipv4Addr::new(x[0], x[1], x[2], x[3])),
+                                u32::from_be_bytes([x[4], x[5], x[6], x[7]]),
+                            )
+                        })
+                        .collect(),
+                };
+                options.push(Ipv4Option::Timestamp {
+                    flag,
+                    overflow: option[3] >> 4,
+                    entries,
+                });
+            }
+            _ => {
+                options.push(Ipv4Option::Other(option_type, option[2..].to_vec()));
+            }
+        }
+    }
+
+    options
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_route() {
+        let mut bytes = render_record_route_option_bytes();
+        assert_eq!(bytes.len(), IPV4_OPTIONS_MAX_SIZE);
+        assert_eq!(
+            parse_ipv4_options(&bytes),
+            vec![Ipv4Option::RecordRoute(vec![])]
+        );
+
+        // Two hops recorded.
+        bytes[4..8].copy_from_slice(&[10, 0, 0, 1]);
+        bytes[8..12].copy_from_slice(&[10, 0, 0, 2]);
+        bytes[3] = 4 + 8;
+        assert_eq!(
+            parse_ipv4_options(&bytes),
+            vec![Ipv4Option::RecordRoute(vec![
+                Ipv4Addr::new(10, 0, 0, 1),
+                Ipv4Addr::new(10, 0, 0, 2)
+            ])]
+        );
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let mut bytes = render_timestamp_option_bytes(TimestampFlag::TsOnly, &[]);
+        assert_eq!(bytes.len(), IPV4_OPTIONS_MAX_SIZE);
+        bytes[4..8].copy_from_slice(&1000_u32.to_be_bytes());
+        bytes[2] = 5 + 4;
+        bytes[3] |= 2 << 4;
+        assert_eq!(
+            parse_ipv4_options(&bytes),
+            vec![Ipv4Option::Timestamp {
+                flag: TimestampFlag::TsOnly,
+                overflow: 2,
+                entries: vec![(None, 1000)]
+            }]
+        );
+
+        let ip = Ipv4Addr::new(10, 0, 0, 1);
+        let mut bytes = render_timestamp_option_bytes(TimestampFlag::Prespecified, &[ip, ip]);
+        assert_eq!(bytes.len(), 4 + 16);
+        bytes[8..12].copy_from_slice(&1000_u32.to_be_bytes());
+        bytes[2] = 5 + 8;
+        assert_eq!(
+            parse_ipv4_options(&bytes),
+            vec![Ipv4Option::Timestamp {
+                flag: TimestampFlag::Prespecified,
+                overflow: 0,
+                entries: vec![(Some(ip), 1000)]
+            }]
+        );
+
+        assert_eq!(
+            parse_ipv4_options(&[IPOPT_NOP, 130, 3, 0, IPOPT_END, 7]),
+            vec![Ipv4Option::Other(130, vec![0])]
+        );
+    }
+}
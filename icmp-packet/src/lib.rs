@@ -3,7 +3,7 @@ pub use pnet_packet;
 
 //
 pub mod echo_reply;
-pub use echo_reply::PayloadLengthDelimitedEchoReply;
+pub use echo_reply::{PayloadLengthDelimitedEchoReply, PayloadMismatch};
 
 pub mod echo_request;
 pub use echo_request::PayloadLengthDelimitedEchoRequest;
@@ -17,6 +17,9 @@ pub use icmpv4::Icmpv4;
 pub mod icmpv6;
 pub use icmpv6::Icmpv6;
 
+pub mod ip;
+pub mod ip_options;
+
 pub mod types;
 pub use types::{Identifier, LenWithPayloadLengthDelimited, Payload, SequenceNumber};
 